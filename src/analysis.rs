@@ -0,0 +1,664 @@
+use crate::{
+    config::{AnalysisConfig, StandConfig},
+    record::{EVENTS_HEADER, RECORD_HEADER, events_path},
+};
+use plotters::prelude::*;
+use serde::Serialize;
+use std::{collections::BTreeMap, error::Error, fmt::Display, fs, io, path::Path};
+
+/// Name of the column every record starts with, giving the time of each row in seconds.
+const TIME_COLUMN: &str = "Time (Seconds)";
+
+/// Seconds before the burn which the thrust baseline is averaged over.
+const BASELINE_WINDOW: f64 = 1.0;
+
+/// Seconds before and after the burn which tank masses are averaged over, so that the masses
+/// consumed are not thrown off by the scales settling.
+const MASS_WINDOW: f64 = 0.5;
+
+/// Size of the plots written, in pixels.
+const PLOT_SIZE: (u32, u32) = (1200, 600);
+
+/// Font family plots are labeled in.
+const PLOT_FONT: &str = "sans-serif";
+
+/// Samples of a single field, as pairs of time in seconds and value.
+pub type Series = Vec<(f64, f64)>;
+
+/// The fields of a [`StandRecord`] loaded back from disk.
+///
+/// [`StandRecord`]: crate::record::StandRecord
+#[derive(Debug, Clone, Default)]
+pub struct RecordData {
+    pub series: BTreeMap<String, Series>,
+
+    /// Events logged alongside the record, oldest first, if its events CSV was found.
+    pub events: Vec<RecordedEvent>,
+}
+
+/// A [`StandEvent`] loaded back from the events CSV of a [`StandRecord`].
+///
+/// [`StandEvent`]: crate::event::StandEvent
+/// [`StandRecord`]: crate::record::StandRecord
+#[derive(Debug, Clone, Serialize)]
+pub struct RecordedEvent {
+    /// Time of the event in seconds, on the record's time base.
+    pub time: f64,
+    pub name: String,
+    pub detail: String,
+}
+
+/// Key metrics of a burn found in a [`RecordData`].
+///
+/// [`RecordData`]: RecordData
+#[derive(Debug, Clone, Serialize)]
+pub struct BurnSummary {
+    /// Times the burn started and stopped at, in seconds on the record's time base.
+    pub burn_start: f64,
+    pub burn_stop: f64,
+    pub burn_duration: f64,
+
+    /// Thrust before the burn, which the other thrust metrics have had subtracted.
+    pub thrust_baseline: f64,
+    pub peak_thrust: f64,
+    pub average_thrust: f64,
+    pub total_impulse: f64,
+
+    /// Masses consumed over the burn, if recorded.
+    pub ox_consumed: Option<f64>,
+    pub fuel_consumed: Option<f64>,
+
+    /// Ratio of oxidizer to fuel consumed over the burn.
+    pub average_of_ratio: Option<f64>,
+
+    /// Highest chamber and tank pressures over the whole record, by field name.
+    pub peak_pressures: BTreeMap<String, f64>,
+
+    /// Events logged alongside the record, with times relative to the start of the burn, so that
+    /// e.g. a valve opening may be lined up against the thrust rise.
+    pub events: Vec<RecordedEvent>,
+
+    /// Names of the thrust and mass fields the metrics were found from.
+    #[serde(skip)]
+    thrust_field: String,
+    #[serde(skip)]
+    mass_field: String,
+
+    /// Units of the fields the metrics were found from, by field name, if known.
+    #[serde(skip)]
+    units: BTreeMap<String, String>,
+}
+
+impl RecordData {
+    /// Load a record CSV, as written by a [`StandRecord`], from the given path, along with its
+    /// events CSV if there is one.
+    ///
+    /// [`StandRecord`]: crate::record::StandRecord
+    pub fn load<P>(path: P) -> Result<Self, AnalysisError>
+    where
+        P: AsRef<Path>,
+    {
+        let mut record = Self::parse(&fs::read_to_string(&path).map_err(AnalysisError::Io)?)?;
+
+        match fs::read_to_string(events_path(&path)) {
+            Ok(text) => record.events = parse_events(&text)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => (),
+            Err(e) => return Err(AnalysisError::Io(e)),
+        }
+
+        Ok(record)
+    }
+
+    /// Parse a record from the text of a record CSV, either in the long format with a row per
+    /// field, or in the older wide format with a column per field. Booleans are read as 1 and 0,
+    /// and empty cells are skipped.
+    pub fn parse(text: &str) -> Result<Self, AnalysisError> {
+        let mut lines = text.lines().enumerate();
+
+        let header: Vec<&str> = match lines.next() {
+            Some((_, header)) if header.trim_end() == RECORD_HEADER => {
+                return Self::parse_long(lines);
+            }
+            Some((_, header)) => header.split(',').collect(),
+            None => return Err(AnalysisError::Format(1, "empty record".to_string())),
+        };
+
+        if header.first() != Some(&TIME_COLUMN) {
+            return Err(AnalysisError::Format(
+                1,
+                format!("record should start with a '{TIME_COLUMN}' column"),
+            ));
+        }
+
+        let mut series: Vec<Series> = vec![Vec::new(); header.len() - 1];
+
+        for (i, line) in lines.filter(|(_, line)| !line.trim().is_empty()) {
+            let line_num = i + 1;
+            let mut cells = line.split(',');
+
+            let time = cells
+                .next()
+                .and_then(parse_cell)
+                .ok_or_else(|| AnalysisError::Format(line_num, "missing time".to_string()))?;
+
+            for (column, cell) in cells.enumerate() {
+                let Some(samples) = series.get_mut(column) else {
+                    return Err(AnalysisError::Format(
+                        line_num,
+                        "more cells than columns".to_string(),
+                    ));
+                };
+
+                if cell.trim().is_empty() {
+                    continue;
+                }
+
+                let value = parse_cell(cell).ok_or_else(|| {
+                    AnalysisError::Format(line_num, format!("invalid value '{cell}'"))
+                })?;
+                samples.push((time, value));
+            }
+        }
+
+        Ok(Self {
+            series: header[1..]
+                .iter()
+                .map(|name| name.to_string())
+                .zip(series)
+                .collect(),
+            events: Vec::new(),
+        })
+    }
+
+    /// Parse the rows of a long format record, following its header.
+    fn parse_long<'a>(
+        lines: impl Iterator<Item = (usize, &'a str)>,
+    ) -> Result<Self, AnalysisError> {
+        let mut series: BTreeMap<String, Series> = BTreeMap::new();
+
+        for (i, line) in lines.filter(|(_, line)| !line.trim().is_empty()) {
+            let line_num = i + 1;
+            let invalid = || AnalysisError::Format(line_num, format!("invalid row '{line}'"));
+
+            let mut cells = line.splitn(3, ',');
+            let time = cells.next().and_then(parse_cell).ok_or_else(invalid)?;
+            let (name, cell) = cells
+                .nth(1)
+                .and_then(|rest| rest.rsplit_once(','))
+                .ok_or_else(invalid)?;
+
+            if cell.trim().is_empty() {
+                continue;
+            }
+
+            let value = parse_cell(cell).ok_or_else(|| {
+                AnalysisError::Format(line_num, format!("invalid value '{cell}'"))
+            })?;
+            series
+                .entry(csv_unquote(name))
+                .or_default()
+                .push((time, value));
+        }
+
+        Ok(Self {
+            series,
+            events: Vec::new(),
+        })
+    }
+
+    /// Gives the samples of the field with the given name, if it was recorded.
+    pub fn get(&self, name: &str) -> Option<&Series> {
+        self.series.get(name).filter(|s| !s.is_empty())
+    }
+
+    /// Gives the samples of the field with the given name, or an error if it was not recorded.
+    fn require(&self, name: &str) -> Result<&Series, AnalysisError> {
+        self.get(name)
+            .ok_or_else(|| AnalysisError::MissingField(name.to_string()))
+    }
+}
+
+/// Parse the text of the events CSV written alongside a record.
+fn parse_events(text: &str) -> Result<Vec<RecordedEvent>, AnalysisError> {
+    let mut lines = text.lines().enumerate();
+
+    if lines.next().map(|(_, header)| header) != Some(EVENTS_HEADER) {
+        return Err(AnalysisError::Format(
+            1,
+            format!("events should start with a '{EVENTS_HEADER}' header"),
+        ));
+    }
+
+    lines
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            let invalid = || AnalysisError::Format(i + 1, format!("invalid event '{line}'"));
+            let mut cells = line.splitn(3, ',');
+
+            let time = cells
+                .next()
+                .and_then(|t| t.parse().ok())
+                .ok_or_else(invalid)?;
+            let name = cells.next().ok_or_else(invalid)?.to_string();
+            let detail = csv_unquote(cells.next().ok_or_else(invalid)?);
+
+            Ok(RecordedEvent { time, name, detail })
+        })
+        .collect()
+}
+
+/// The text of the given CSV cell, without any quotes around it, and with doubled quotes in it
+/// undoubled.
+fn csv_unquote(cell: &str) -> String {
+    cell.strip_prefix('"')
+        .and_then(|c| c.strip_suffix('"'))
+        .map(|c| c.replace("\"\"", "\""))
+        .unwrap_or_else(|| cell.to_string())
+}
+
+/// Parse a single cell of a record as a number, reading booleans as 1 and 0.
+fn parse_cell(cell: &str) -> Option<f64> {
+    match cell.trim() {
+        "true" => Some(1.0),
+        "false" => Some(0.0),
+        cell => cell.parse().ok(),
+    }
+}
+
+/// Find the burn in the given [`RecordData`] and measure it, reading the fields given by the
+/// [`StandConfig`]'s [`AnalysisConfig`].
+///
+/// The burn is the span around the peak thrust over which thrust stays above
+/// [`AnalysisConfig::burn_threshold`] of the peak. Thrust metrics are taken relative to the
+/// average thrust over the [`BASELINE_WINDOW`] before the burn, to remove any offset in the
+/// scale.
+///
+/// [`RecordData`]: RecordData
+/// [`StandConfig`]: StandConfig
+/// [`AnalysisConfig`]: AnalysisConfig
+/// [`AnalysisConfig::burn_threshold`]: AnalysisConfig::burn_threshold
+/// [`BASELINE_WINDOW`]: BASELINE_WINDOW
+pub fn analyze(record: &RecordData, config: &StandConfig) -> Result<BurnSummary, AnalysisError> {
+    let analysis = &config.analysis;
+    let thrust = record.require(&analysis.thrust)?;
+
+    let (peak_index, &(_, peak)) = thrust
+        .iter()
+        .enumerate()
+        .max_by(|(_, a), (_, b)| a.1.total_cmp(&b.1))
+        .ok_or_else(|| AnalysisError::MissingField(analysis.thrust.clone()))?;
+
+    if peak < analysis.min_burn_thrust {
+        return Err(AnalysisError::NoBurn(peak));
+    }
+
+    let threshold = peak * analysis.burn_threshold;
+    let start_index = thrust[..peak_index]
+        .iter()
+        .rposition(|&(_, v)| v < threshold)
+        .map_or(0, |i| i + 1);
+    let stop_index = thrust[peak_index..]
+        .iter()
+        .position(|&(_, v)| v < threshold)
+        .map_or(thrust.len() - 1, |i| {
+            (peak_index + i).saturating_sub(1).max(peak_index)
+        });
+
+    let burn_start = thrust[start_index].0;
+    let burn_stop = thrust[stop_index].0;
+    let burn_duration = burn_stop - burn_start;
+
+    let thrust_baseline = mean_between(thrust, burn_start - BASELINE_WINDOW, burn_start)
+        .filter(|&b| b < threshold)
+        .unwrap_or(0.0);
+
+    let total_impulse = thrust[start_index..=stop_index]
+        .windows(2)
+        .map(|w| (w[1].0 - w[0].0) * ((w[0].1 + w[1].1) / 2.0 - thrust_baseline))
+        .sum::<f64>();
+
+    let average_thrust = if burn_duration > 0.0 {
+        total_impulse / burn_duration
+    } else {
+        peak - thrust_baseline
+    };
+
+    let consumed = |name: &str| {
+        let mass = record.get(name)?;
+        let before = mean_between(mass, burn_start - MASS_WINDOW, burn_start)
+            .or_else(|| value_at_or_before(mass, burn_start))?;
+        let after = mean_between(mass, burn_stop, burn_stop + MASS_WINDOW)
+            .or_else(|| value_at_or_after(mass, burn_stop))?;
+
+        Some(before - after)
+    };
+
+    let ox_consumed = consumed(&analysis.ox_mass);
+    let fuel_consumed = consumed(&analysis.fuel_mass);
+    let average_of_ratio = match (ox_consumed, fuel_consumed) {
+        (Some(ox), Some(fuel)) if fuel > 0.0 => Some(ox / fuel),
+        _ => None,
+    };
+
+    let peak_pressures = analysis
+        .chamber_pressure
+        .iter()
+        .chain(&analysis.tank_pressures)
+        .filter_map(|name| {
+            let peak = record.get(name)?.iter().map(|&(_, v)| v).reduce(f64::max)?;
+            Some((name.clone(), peak))
+        })
+        .collect();
+
+    let units = [&analysis.thrust, &analysis.ox_mass]
+        .into_iter()
+        .chain(&analysis.chamber_pressure)
+        .chain(&analysis.tank_pressures)
+        .filter_map(|name| Some((name.clone(), config.unit(name)?.to_string())))
+        .collect();
+
+    Ok(BurnSummary {
+        burn_start,
+        burn_stop,
+        burn_duration,
+        thrust_baseline,
+        peak_thrust: peak - thrust_baseline,
+        average_thrust,
+        total_impulse,
+        ox_consumed,
+        fuel_consumed,
+        average_of_ratio,
+        peak_pressures,
+        events: record
+            .events
+            .iter()
+            .map(|event| RecordedEvent {
+                time: event.time - burn_start,
+                ..event.clone()
+            })
+            .collect(),
+        thrust_field: analysis.thrust.clone(),
+        mass_field: analysis.ox_mass.clone(),
+        units,
+    })
+}
+
+/// Average of the samples in the given series strictly between the given times.
+fn mean_between(series: &Series, from: f64, to: f64) -> Option<f64> {
+    let values: Vec<f64> = series
+        .iter()
+        .filter(|&&(t, _)| from < t && t < to)
+        .map(|&(_, v)| v)
+        .collect();
+
+    (!values.is_empty()).then(|| values.iter().sum::<f64>() / values.len() as f64)
+}
+
+/// The last value in the given series at or before the given time.
+fn value_at_or_before(series: &Series, time: f64) -> Option<f64> {
+    series.iter().rev().find(|&&(t, _)| t <= time).map(|s| s.1)
+}
+
+/// The first value in the given series at or after the given time.
+fn value_at_or_after(series: &Series, time: f64) -> Option<f64> {
+    series.iter().find(|&&(t, _)| t >= time).map(|s| s.1)
+}
+
+/// Write PNG plots of the thrust, tank masses, and pressures in the given [`RecordData`] into the
+/// given directory, marking the burn described by the given [`BurnSummary`]. Gives the paths of
+/// the plots written.
+///
+/// [`RecordData`]: RecordData
+/// [`BurnSummary`]: BurnSummary
+pub fn write_plots(
+    record: &RecordData,
+    config: &AnalysisConfig,
+    summary: &BurnSummary,
+    dir: &Path,
+) -> Result<Vec<String>, AnalysisError> {
+    plotters::style::register_font(
+        PLOT_FONT,
+        FontStyle::Normal,
+        epaint_default_fonts::UBUNTU_LIGHT,
+    )
+    .map_err(|_| AnalysisError::Plot("could not load plot font".to_string()))?;
+
+    let pressures: Vec<&String> = config
+        .chamber_pressure
+        .iter()
+        .chain(&config.tank_pressures)
+        .collect();
+
+    let plots = [
+        ("thrust.png", "Thrust", vec![&config.thrust]),
+        (
+            "masses.png",
+            "Tank Masses",
+            vec![&config.ox_mass, &config.fuel_mass],
+        ),
+        ("pressures.png", "Pressures", pressures),
+    ];
+
+    let mut written = Vec::new();
+
+    for (file, title, names) in plots {
+        let series: Vec<(&str, &Series)> = names
+            .into_iter()
+            .filter_map(|name| Some((name.as_str(), record.get(name)?)))
+            .collect();
+
+        if series.is_empty() {
+            continue;
+        }
+
+        let path = dir.join(file);
+        plot(&path, title, &series, summary).map_err(|e| AnalysisError::Plot(e.to_string()))?;
+        written.push(path.display().to_string());
+    }
+
+    Ok(written)
+}
+
+/// Draw the given series on a single plot, with lines marking the start and stop of the burn.
+fn plot(
+    path: &Path,
+    title: &str,
+    series: &[(&str, &Series)],
+    summary: &BurnSummary,
+) -> Result<(), Box<dyn Error>> {
+    let points = || series.iter().flat_map(|(_, s)| s.iter());
+
+    let (t_min, t_max) = points().fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), p| {
+        (lo.min(p.0), hi.max(p.0))
+    });
+    let (v_min, v_max) = points().fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), p| {
+        (lo.min(p.1), hi.max(p.1))
+    });
+    let v_pad = ((v_max - v_min) * 0.05).max(1e-3);
+    let (v_min, v_max) = (v_min - v_pad, v_max + v_pad);
+
+    let root = BitMapBackend::new(path, PLOT_SIZE).into_drawing_area();
+    root.fill(&WHITE)?;
+
+    let mut chart = ChartBuilder::on(&root)
+        .caption(title, (PLOT_FONT, 28))
+        .margin(16)
+        .x_label_area_size(48)
+        .y_label_area_size(72)
+        .build_cartesian_2d(t_min..t_max.max(t_min + 1e-3), v_min..v_max)?;
+
+    chart
+        .configure_mesh()
+        .x_desc(TIME_COLUMN)
+        .label_style((PLOT_FONT, 16))
+        .draw()?;
+
+    for (i, &(name, samples)) in series.iter().enumerate() {
+        let color = Palette99::pick(i).to_rgba();
+
+        chart
+            .draw_series(LineSeries::new(
+                samples.iter().copied(),
+                color.stroke_width(2),
+            ))?
+            .label(name)
+            .legend(move |(x, y)| PathElement::new([(x, y), (x + 20, y)], color.stroke_width(2)));
+    }
+
+    for time in [summary.burn_start, summary.burn_stop] {
+        chart.draw_series(LineSeries::new(
+            [(time, v_min), (time, v_max)],
+            BLACK.mix(0.6),
+        ))?;
+    }
+
+    chart
+        .configure_series_labels()
+        .label_font((PLOT_FONT, 16))
+        .background_style(WHITE.mix(0.8))
+        .border_style(BLACK)
+        .draw()?;
+
+    root.present()?;
+    Ok(())
+}
+
+impl BurnSummary {
+    /// The given value with the unit of the field with the given name, if known.
+    fn with_unit(&self, value: f64, name: &str) -> String {
+        match self.units.get(name) {
+            Some(unit) => format!("{value:.2} {unit}"),
+            None => format!("{value:.2}"),
+        }
+    }
+
+    /// The given impulse with the unit of the thrust field multiplied by seconds, if known.
+    fn with_impulse_unit(&self, impulse: f64) -> String {
+        match self.units.get(&self.thrust_field) {
+            Some(unit) => format!("{impulse:.2} {unit}·s"),
+            None => format!("{impulse:.2}"),
+        }
+    }
+}
+
+impl Display for BurnSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let thrust = |t: f64| self.with_unit(t, &self.thrust_field);
+        let mass = |m: Option<f64>| match m {
+            Some(m) => self.with_unit(m, &self.mass_field),
+            None => "not recorded".to_string(),
+        };
+
+        writeln!(
+            f,
+            "Burn: {:.3}s to {:.3}s ({:.3}s)",
+            self.burn_start, self.burn_stop, self.burn_duration
+        )?;
+        writeln!(f, "Thrust baseline: {}", thrust(self.thrust_baseline))?;
+        writeln!(f, "Peak thrust: {}", thrust(self.peak_thrust))?;
+        writeln!(f, "Average thrust: {}", thrust(self.average_thrust))?;
+        writeln!(
+            f,
+            "Total impulse: {}",
+            self.with_impulse_unit(self.total_impulse)
+        )?;
+        writeln!(f, "Oxidizer consumed: {}", mass(self.ox_consumed))?;
+        writeln!(f, "Fuel consumed: {}", mass(self.fuel_consumed))?;
+
+        match self.average_of_ratio {
+            Some(ratio) => writeln!(f, "Average O/F ratio: {ratio:.3}")?,
+            None => writeln!(f, "Average O/F ratio: not recorded")?,
+        }
+
+        for (name, peak) in &self.peak_pressures {
+            writeln!(f, "Peak {name}: {}", self.with_unit(*peak, name))?;
+        }
+
+        if !self.events.is_empty() {
+            writeln!(f, "Events, relative to burn start:")?;
+        }
+
+        for event in &self.events {
+            writeln!(f, "  {:+.3}s {}: {}", event.time, event.name, event.detail)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Errors which may occur analysing a record.
+#[derive(Debug)]
+pub enum AnalysisError {
+    Io(io::Error),
+
+    /// The record is malformed at the given line.
+    Format(usize, String),
+
+    /// A field needed for analysis was not recorded.
+    MissingField(String),
+
+    /// Thrust never reached the minimum for a burn, peaking at the given value.
+    NoBurn(f64),
+
+    /// A plot could not be drawn.
+    Plot(String),
+}
+
+impl Display for AnalysisError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Could not analyse record: ")?;
+        match self {
+            AnalysisError::Io(e) => write!(f, "IO error: {e}"),
+            AnalysisError::Format(line, reason) => write!(f, "Line {line}: {reason}"),
+            AnalysisError::MissingField(name) => write!(f, "'{name}' was not recorded"),
+            AnalysisError::NoBurn(peak) => {
+                write!(f, "No burn found, thrust peaked at {peak:.2}")
+            }
+            AnalysisError::Plot(e) => write!(f, "Could not draw plot: {e}"),
+        }
+    }
+}
+
+impl Error for AnalysisError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A record of only the given thrust samples, one every 0.1s.
+    fn thrust_record(config: &StandConfig, thrust: &[f64]) -> RecordData {
+        let series = thrust
+            .iter()
+            .enumerate()
+            .map(|(i, &v)| (i as f64 * 0.1, v))
+            .collect();
+
+        RecordData {
+            series: BTreeMap::from([(config.analysis.thrust.clone(), series)]),
+            events: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn burn_spans_thrust_above_threshold() {
+        let config = StandConfig::built_in();
+        let record = thrust_record(&config, &[0.0, 0.0, 50.0, 100.0, 80.0, 0.0, 0.0]);
+
+        let summary = analyze(&record, &config).unwrap();
+        assert_eq!(summary.burn_start, 0.2);
+        assert_eq!(summary.burn_stop, 0.4);
+        assert_eq!(summary.peak_thrust, 100.0);
+    }
+
+    #[test]
+    fn peak_below_threshold_is_a_burn_of_one_sample() {
+        let mut config = StandConfig::built_in();
+        config.analysis.min_burn_thrust = -10.0;
+        let record = thrust_record(&config, &[-5.0, -3.0, -1.0, -4.0]);
+
+        let summary = analyze(&record, &config).unwrap();
+        assert_eq!(summary.burn_start, 0.2);
+        assert_eq!(summary.burn_stop, 0.2);
+        assert_eq!(summary.burn_duration, 0.0);
+    }
+}
@@ -0,0 +1,243 @@
+use crate::{
+    config::StandConfig,
+    serial::{SensorField, SensorValue},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    fmt::Display,
+    fs, io,
+    path::Path,
+};
+
+/// Calibrations of the stand's sensors, converting the raw values the stand sends, such as ADC
+/// counts, to engineering units as they arrive. Loaded from TOML, and recorded in full in the
+/// metadata of every session so that the raw values can be recovered.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Calibration {
+    /// Version of the calibration, e.g. the date the sensors were last calibrated, which should
+    /// be changed whenever any calibration is.
+    pub version: String,
+
+    #[serde(rename = "field", default)]
+    pub fields: Vec<FieldCalibration>,
+}
+
+/// The calibration of a single field. A field may be given a `polynomial` or a `table`, or
+/// neither if it only needs a unit or to be tared.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FieldCalibration {
+    pub name: String,
+
+    /// Unit of the calibrated value, replacing any unit in the [`StandConfig`].
+    ///
+    /// [`StandConfig`]: StandConfig
+    #[serde(default)]
+    pub unit: Option<String>,
+
+    /// Coefficients of a polynomial in the raw value, constant first, e.g. `[-12.5, 0.1875]` for
+    /// `0.1875 * raw - 12.5`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub polynomial: Option<Vec<f64>>,
+
+    /// Pairs of raw and calibrated values, in increasing raw order. Values between pairs are
+    /// interpolated linearly, and values beyond either end are extrapolated from the end pairs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub table: Option<Vec<[f64; 2]>>,
+
+    /// Whether the operator may tare the field from the console, e.g. for a scale.
+    #[serde(default)]
+    pub tare: bool,
+}
+
+impl Calibration {
+    /// Load and validate a [`Calibration`] from the TOML file at the given path.
+    ///
+    /// [`Calibration`]: Calibration
+    pub fn load<P>(path: P, config: &StandConfig) -> Result<Self, CalibrationError>
+    where
+        P: AsRef<Path>,
+    {
+        let text = fs::read_to_string(path).map_err(CalibrationError::Io)?;
+        let calibration: Calibration = toml::from_str(&text).map_err(CalibrationError::Parse)?;
+        calibration.validate(config)?;
+        Ok(calibration)
+    }
+
+    /// Check that every calibrated field is sent by the stand and calibrated once, and that every
+    /// polynomial and table can be evaluated.
+    fn validate(&self, config: &StandConfig) -> Result<(), CalibrationError> {
+        let field_names = config.field_names();
+        let mut names = HashSet::new();
+
+        for field in &self.fields {
+            let name = &field.name;
+
+            if !field_names.contains(name) {
+                return Err(CalibrationError::Invalid(format!(
+                    "calibration of unknown field '{name}'"
+                )));
+            }
+
+            if !names.insert(name.as_str()) {
+                return Err(CalibrationError::Invalid(format!(
+                    "'{name}' is calibrated twice"
+                )));
+            }
+
+            match (&field.polynomial, &field.table) {
+                (Some(_), Some(_)) => {
+                    return Err(CalibrationError::Invalid(format!(
+                        "'{name}' has both a polynomial and a table"
+                    )));
+                }
+
+                (Some(polynomial), None) if polynomial.is_empty() => {
+                    return Err(CalibrationError::Invalid(format!(
+                        "'{name}' has an empty polynomial"
+                    )));
+                }
+
+                (None, Some(table))
+                    if table.len() < 2 || table.windows(2).any(|w| w[0][0] >= w[1][0]) =>
+                {
+                    return Err(CalibrationError::Invalid(format!(
+                        "'{name}' needs a table of at least two pairs in increasing raw order"
+                    )));
+                }
+
+                _ => (),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Give every calibrated field with a unit that unit in the given [`StandConfig`], so that it
+    /// is shown wherever the field is.
+    ///
+    /// [`StandConfig`]: StandConfig
+    pub fn apply_units(&self, config: &mut StandConfig) {
+        for field in &self.fields {
+            let Some(unit) = &field.unit else {
+                continue;
+            };
+
+            if let Some(sensor) = config.sensors.iter_mut().find(|s| s.name == field.name) {
+                sensor.unit = Some(unit.clone());
+            }
+        }
+    }
+
+    /// Names of the fields which may be tared.
+    pub fn tareable(&self) -> impl Iterator<Item = &str> {
+        self.fields
+            .iter()
+            .filter(|field| field.tare)
+            .map(|field| field.name.as_str())
+    }
+}
+
+impl FieldCalibration {
+    /// The calibrated value of the given raw value.
+    fn apply(&self, raw: f64) -> f64 {
+        if let Some(polynomial) = &self.polynomial {
+            return polynomial.iter().rev().fold(0.0, |acc, c| acc * raw + c);
+        }
+
+        if let Some(table) = &self.table {
+            let upper = table
+                .partition_point(|[x, _]| *x < raw)
+                .clamp(1, table.len() - 1);
+            let [x0, y0] = table[upper - 1];
+            let [x1, y1] = table[upper];
+
+            return y0 + (raw - x0) * (y1 - y0) / (x1 - x0);
+        }
+
+        raw
+    }
+}
+
+/// Applies a [`Calibration`] to fields as they arrive, on the field thread, along with any tares
+/// the operator has set.
+///
+/// [`Calibration`]: Calibration
+#[derive(Debug, Default)]
+pub struct Calibrator {
+    fields: HashMap<String, FieldCalibration>,
+    /// Offset subtracted from each tared field.
+    tares: HashMap<String, f64>,
+    /// The latest calibrated value of each tareable field, before taring, which it is tared to.
+    latest: HashMap<String, f64>,
+}
+
+impl Calibrator {
+    pub fn new(calibration: &Calibration) -> Self {
+        Self {
+            fields: calibration
+                .fields
+                .iter()
+                .map(|field| (field.name.clone(), field.clone()))
+                .collect(),
+            tares: HashMap::new(),
+            latest: HashMap::new(),
+        }
+    }
+
+    /// Calibrate the given [`SensorField`] in place, then subtract its tare if it has one.
+    ///
+    /// [`SensorField`]: SensorField
+    pub fn calibrate(&mut self, field: &mut SensorField) {
+        let Some(calibration) = self.fields.get(&field.name) else {
+            return;
+        };
+
+        let value = calibration.apply(field.value.to_num());
+        if calibration.tare {
+            self.latest.insert(field.name.clone(), value);
+        }
+
+        let tare = self.tares.get(&field.name).copied().unwrap_or_default();
+        field.value = SensorValue::Float(value - tare);
+    }
+
+    /// Tare the field with the given name so that its latest value reads zero, giving the offset
+    /// now subtracted from it. Gives [`None`] if the field may not be tared or has not been
+    /// recieved yet.
+    ///
+    /// [`None`]: None
+    pub fn tare(&mut self, name: &str) -> Option<f64> {
+        let offset = *self.latest.get(name)?;
+        self.tares.insert(name.to_string(), offset);
+        Some(offset)
+    }
+
+    /// Stop taring the field with the given name.
+    pub fn clear_tare(&mut self, name: &str) {
+        self.tares.remove(name);
+    }
+}
+
+/// Errors from loading a [`Calibration`].
+///
+/// [`Calibration`]: Calibration
+#[derive(Debug)]
+pub enum CalibrationError {
+    Io(io::Error),
+    Parse(toml::de::Error),
+    Invalid(String),
+}
+
+impl Display for CalibrationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CalibrationError::Io(e) => write!(f, "Could not read calibration: {e}"),
+            CalibrationError::Parse(e) => write!(f, "Could not parse calibration: {e}"),
+            CalibrationError::Invalid(e) => write!(f, "Invalid calibration: {e}"),
+        }
+    }
+}
+
+impl Error for CalibrationError {}
@@ -0,0 +1,339 @@
+use crate::frame::{PROTOCOL_REPLY, Protocol};
+use std::{
+    collections::VecDeque,
+    error::Error,
+    fmt::Display,
+    fs::{self, File},
+    io::{self, Read, Write},
+    path::Path,
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// Bytes every capture file starts with.
+const CAPTURE_MAGIC: &[u8; 8] = b"NILECAP1";
+
+/// Bytes of the header following [`CAPTURE_MAGIC`]: the host time the capture started at, in
+/// microseconds since the Unix epoch, and the [`Protocol`] the stand was sending in.
+///
+/// [`CAPTURE_MAGIC`]: CAPTURE_MAGIC
+/// [`Protocol`]: Protocol
+const HEADER_LEN: usize = 9;
+
+/// Bytes of the header of each record: its [`Direction`], its time since the capture started in
+/// microseconds, and the length of its bytes.
+///
+/// [`Direction`]: Direction
+const RECORD_HEADER_LEN: usize = 13;
+
+/// Longest a [`ReplayStand`] blocks a read for while waiting for its next bytes to be due.
+///
+/// [`ReplayStand`]: ReplayStand
+const READ_WAIT: Duration = Duration::from_millis(10);
+
+/// Whether captured bytes were read from the stand or written to it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Read,
+    Written,
+}
+
+/// Writes every byte read from and written to a field device to a file, each stamped with the
+/// time since the capture started, so that a session may be replayed byte for byte.
+///
+/// The file starts with [`CAPTURE_MAGIC`] and a header, followed by records of a byte for the
+/// [`Direction`] (0 for read, 1 for written), a little endian `u64` of microseconds since the
+/// capture started, a little endian `u32` length, and then that many bytes. Each record is written
+/// with a single write, so a capture cut short by a crash loses at most its last record.
+///
+/// [`CAPTURE_MAGIC`]: CAPTURE_MAGIC
+/// [`Direction`]: Direction
+#[derive(Debug)]
+pub struct CaptureWriter {
+    file: File,
+    start: Instant,
+}
+
+impl CaptureWriter {
+    /// Create a new capture file at the given path, noting that the stand is currently sending in
+    /// the given [`Protocol`].
+    ///
+    /// [`Protocol`]: Protocol
+    pub fn create<P>(path: P, protocol: Protocol) -> io::Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let mut file = File::create(path)?;
+        let started_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_micros() as u64;
+
+        let mut header = CAPTURE_MAGIC.to_vec();
+        header.extend_from_slice(&started_at.to_le_bytes());
+        header.push(match protocol {
+            Protocol::Text => 0,
+            Protocol::Binary => 1,
+        });
+        file.write_all(&header)?;
+
+        Ok(Self {
+            file,
+            start: Instant::now(),
+        })
+    }
+
+    /// Record the given bytes as having just been read or written.
+    pub fn record(&mut self, direction: Direction, bytes: &[u8]) -> io::Result<()> {
+        if bytes.is_empty() {
+            return Ok(());
+        }
+
+        let mut record = Vec::with_capacity(RECORD_HEADER_LEN + bytes.len());
+        record.push(match direction {
+            Direction::Read => 0,
+            Direction::Written => 1,
+        });
+        record.extend_from_slice(&(self.start.elapsed().as_micros() as u64).to_le_bytes());
+        record.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+        record.extend_from_slice(bytes);
+
+        self.file.write_all(&record)
+    }
+}
+
+/// Bytes read from or written to a field device at some time during a [`Capture`].
+///
+/// [`Capture`]: Capture
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CaptureRecord {
+    pub direction: Direction,
+
+    /// Time since the capture started.
+    pub time: Duration,
+    pub bytes: Vec<u8>,
+}
+
+/// A capture file written by a [`CaptureWriter`], loaded for replay.
+///
+/// [`CaptureWriter`]: CaptureWriter
+#[derive(Debug, Clone)]
+pub struct Capture {
+    /// Host time the capture started at.
+    pub started_at: SystemTime,
+
+    /// The [`Protocol`] the stand was sending in when the capture started.
+    ///
+    /// [`Protocol`]: Protocol
+    pub protocol: Protocol,
+    pub records: Vec<CaptureRecord>,
+}
+
+impl Capture {
+    /// Load a capture file from the given path. A last record cut short, e.g. by the console
+    /// crashing, is dropped.
+    pub fn load<P>(path: P) -> Result<Self, CaptureError>
+    where
+        P: AsRef<Path>,
+    {
+        Self::parse(&fs::read(path).map_err(CaptureError::Io)?)
+    }
+
+    /// Parse a capture from the given bytes of a capture file.
+    pub fn parse(bytes: &[u8]) -> Result<Self, CaptureError> {
+        let header = bytes
+            .strip_prefix(CAPTURE_MAGIC)
+            .and_then(|rest| rest.get(..HEADER_LEN))
+            .ok_or(CaptureError::NotACapture)?;
+
+        let started_at = u64::from_le_bytes(header[..8].try_into().unwrap());
+        let protocol = match header[8] {
+            0 => Protocol::Text,
+            1 => Protocol::Binary,
+            _ => return Err(CaptureError::Malformed(CAPTURE_MAGIC.len() + 8)),
+        };
+
+        let mut offset = CAPTURE_MAGIC.len() + HEADER_LEN;
+        let mut records = Vec::new();
+
+        while let Some(header) = bytes.get(offset..offset + RECORD_HEADER_LEN) {
+            let direction = match header[0] {
+                0 => Direction::Read,
+                1 => Direction::Written,
+                _ => return Err(CaptureError::Malformed(offset)),
+            };
+            let time = u64::from_le_bytes(header[1..9].try_into().unwrap());
+            let len = u32::from_le_bytes(header[9..13].try_into().unwrap()) as usize;
+
+            let start = offset + RECORD_HEADER_LEN;
+            let Some(record_bytes) = bytes.get(start..start + len) else {
+                break;
+            };
+
+            records.push(CaptureRecord {
+                direction,
+                time: Duration::from_micros(time),
+                bytes: record_bytes.to_vec(),
+            });
+            offset = start + len;
+        }
+
+        if offset < bytes.len() {
+            log::warn!(
+                "Capture ends with a partial record, dropped {} bytes",
+                bytes.len() - offset
+            );
+        }
+
+        Ok(Self {
+            started_at: UNIX_EPOCH + Duration::from_micros(started_at),
+            protocol,
+            records,
+        })
+    }
+
+    /// Time from the start of the capture to its last record.
+    pub fn duration(&self) -> Duration {
+        self.records.last().map(|r| r.time).unwrap_or_default()
+    }
+}
+
+/// A device which plays back the bytes read in a [`Capture`] at the times they were read, scaled
+/// by a speed, so that a session may be replayed through a [`FieldReciever`] exactly as the stand
+/// sent it. Commands captured as written are logged when due, and commands written to a
+/// [`ReplayStand`] are ignored.
+///
+/// [`Capture`]: Capture
+/// [`FieldReciever`]: crate::serial::FieldReciever
+/// [`ReplayStand`]: ReplayStand
+#[derive(Debug)]
+pub struct ReplayStand {
+    records: VecDeque<CaptureRecord>,
+    output: VecDeque<u8>,
+    speed: f64,
+
+    /// When the replay started, set by the first read.
+    start: Option<Instant>,
+    finished: bool,
+    warned_write: bool,
+}
+
+impl ReplayStand {
+    /// Replay the given [`Capture`] at the given speed, where 1 is real time, 2 twice as fast, and
+    /// so on.
+    ///
+    /// [`Capture`]: Capture
+    pub fn new(capture: Capture, speed: f64) -> Self {
+        let mut output = VecDeque::new();
+
+        // The console starts out expecting text, so tell it the stand already switched.
+        if capture.protocol == Protocol::Binary {
+            output.extend(format!("\n{PROTOCOL_REPLY}\n").bytes());
+        }
+
+        Self {
+            records: capture.records.into(),
+            output,
+            speed,
+            start: None,
+            finished: false,
+            warned_write: false,
+        }
+    }
+
+    /// Move the bytes of every record now due into the output, giving the time until the next
+    /// record is due, if there is one.
+    fn queue_due_records(&mut self) -> Option<Duration> {
+        let start = *self.start.get_or_insert_with(Instant::now);
+        // saturating, as extreme speeds would overflow a Duration
+        let scale = |t: Duration, factor: f64| {
+            Duration::try_from_secs_f64(t.as_secs_f64() * factor).unwrap_or(Duration::MAX)
+        };
+        let elapsed = scale(start.elapsed(), self.speed);
+
+        while let Some(record) = self.records.front() {
+            if record.time > elapsed {
+                return Some(scale(record.time - elapsed, self.speed.recip()));
+            }
+
+            let record = self.records.pop_front().unwrap();
+            match record.direction {
+                Direction::Read => self.output.extend(record.bytes),
+                Direction::Written => log::info!(
+                    "Captured write at {:.3}s: {}",
+                    record.time.as_secs_f64(),
+                    String::from_utf8_lossy(&record.bytes).trim()
+                ),
+            }
+        }
+
+        if !self.finished {
+            log::info!("Replay finished");
+            self.finished = true;
+        }
+
+        None
+    }
+}
+
+impl Read for ReplayStand {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let until_next = self.queue_due_records();
+
+        if self.output.is_empty() {
+            thread::sleep(until_next.map_or(READ_WAIT, |t| t.min(READ_WAIT)));
+            return Ok(0);
+        }
+
+        let len = buf.len().min(self.output.len());
+        for (b, out) in buf.iter_mut().zip(self.output.drain(..len)) {
+            *b = out;
+        }
+
+        Ok(len)
+    }
+}
+
+impl Write for ReplayStand {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if !self.warned_write {
+            log::warn!("Commands are not sent anywhere while replaying a capture");
+            self.warned_write = true;
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Errors which may occur loading a [`Capture`].
+///
+/// [`Capture`]: Capture
+#[derive(Debug)]
+pub enum CaptureError {
+    Io(io::Error),
+
+    /// The file does not start with [`CAPTURE_MAGIC`].
+    ///
+    /// [`CAPTURE_MAGIC`]: CAPTURE_MAGIC
+    NotACapture,
+
+    /// The record at the given byte offset is malformed.
+    Malformed(usize),
+}
+
+impl Display for CaptureError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Could not load capture: ")?;
+        match self {
+            CaptureError::Io(e) => write!(f, "IO error: {e}"),
+            CaptureError::NotACapture => write!(f, "Not a capture file"),
+            CaptureError::Malformed(offset) => write!(f, "Malformed record at byte {offset}"),
+        }
+    }
+}
+
+impl Error for CaptureError {}
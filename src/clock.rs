@@ -0,0 +1,210 @@
+use std::{
+    collections::VecDeque,
+    fmt::Display,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Span of stand time which samples are kept over, in seconds.
+const SAMPLE_WINDOW: f64 = 60.0;
+
+/// Shortest stand time between kept samples, in seconds. Of the samples within this of each other
+/// only the one with the least latency is kept.
+const SAMPLE_INTERVAL: f64 = 0.1;
+
+/// Shortest span of samples which drift is estimated over, in seconds, before which the clocks are
+/// taken to run at the same rate.
+const MIN_DRIFT_SPAN: f64 = 5.0;
+
+/// Estimates the relationship between the stand's clock and the host's from the times fields
+/// stamped by the stand are recieved at, so that stand times may be converted to host times.
+///
+/// Fields take a varying time to arrive but never arrive before they were sent, so the host clock
+/// is fit to the least delayed samples: the drift is the slope of a least squares fit over the last
+/// [`SAMPLE_WINDOW`] of samples, and the offset is chosen so that no sample arrived before it was
+/// sent.
+///
+/// [`SAMPLE_WINDOW`]: SAMPLE_WINDOW
+#[derive(Debug, Default, Clone)]
+pub struct ClockSync {
+    /// Stand time and host time which samples are measured relative to, to keep precision.
+    reference: Option<(f64, f64)>,
+    /// Pairs of stand time and host time a field was recieved at, relative to `reference`.
+    samples: VecDeque<(f64, f64)>,
+    /// Host seconds per stand second.
+    slope: f64,
+    /// Relative host time at relative stand time zero.
+    intercept: f64,
+    /// Average delay of samples beyond the least delayed, in seconds.
+    latency: f64,
+}
+
+/// The relationship between the stand's clock and the host's estimated by a [`ClockSync`].
+///
+/// [`ClockSync`]: ClockSync
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct ClockEstimate {
+    /// Host time minus stand time, in seconds.
+    pub offset: f64,
+
+    /// How much faster the host clock runs than the stand's, as a fraction.
+    pub drift: f64,
+
+    /// Average delay of fields beyond the least delayed, in seconds.
+    pub latency: f64,
+}
+
+impl ClockSync {
+    /// Add a sample of a field stamped with the given stand time, in seconds, being recieved at
+    /// the given host time. If the stand's clock has gone backwards, e.g. because it restarted,
+    /// every earlier sample is forgotten.
+    pub fn add(&mut self, stand_time: f64, host_time: SystemTime) {
+        let host_time = epoch_secs(host_time);
+
+        let went_backwards = self
+            .reference
+            .zip(self.samples.back())
+            .is_some_and(|((stand_ref, _), &(last, _))| stand_time - stand_ref < last);
+
+        if self.reference.is_none() || went_backwards {
+            self.reference = Some((stand_time, host_time));
+            self.samples.clear();
+        }
+
+        let Some((stand_ref, host_ref)) = self.reference else {
+            return;
+        };
+
+        let sample = (stand_time - stand_ref, host_time - host_ref);
+        let delay = |(x, y): (f64, f64)| y - x;
+
+        match self.samples.back_mut() {
+            Some(last) if sample.0 - last.0 < SAMPLE_INTERVAL => {
+                if delay(sample) < delay(*last) {
+                    *last = sample;
+                }
+            }
+
+            _ => self.samples.push_back(sample),
+        }
+
+        while let Some(&(first, _)) = self.samples.front()
+            && sample.0 - first > SAMPLE_WINDOW
+        {
+            self.samples.pop_front();
+        }
+
+        self.fit();
+    }
+
+    fn fit(&mut self) {
+        let n = self.samples.len() as f64;
+        let span = match (self.samples.front(), self.samples.back()) {
+            (Some(first), Some(last)) => last.0 - first.0,
+            _ => return,
+        };
+
+        self.slope = if span >= MIN_DRIFT_SPAN {
+            let mean_x = self.samples.iter().map(|s| s.0).sum::<f64>() / n;
+            let mean_y = self.samples.iter().map(|s| s.1).sum::<f64>() / n;
+
+            let (cov, var) = self.samples.iter().fold((0.0, 0.0), |(cov, var), &(x, y)| {
+                (
+                    cov + (x - mean_x) * (y - mean_y),
+                    var + (x - mean_x) * (x - mean_x),
+                )
+            });
+
+            cov / var
+        } else {
+            1.0
+        };
+
+        let residuals = self.samples.iter().map(|&(x, y)| y - self.slope * x);
+        self.intercept = residuals.clone().fold(f64::INFINITY, f64::min);
+        self.latency = residuals.map(|r| r - self.intercept).sum::<f64>() / n;
+    }
+
+    /// Convert the given stand time, in seconds, to host time. Gives [`None`] if no samples have
+    /// been added, or if the host time would be before the Unix epoch or not representable.
+    ///
+    /// [`None`]: None
+    pub fn to_host(&self, stand_time: f64) -> Option<SystemTime> {
+        let (stand_ref, host_ref) = self.reference?;
+        let host_time = host_ref + self.intercept + self.slope * (stand_time - stand_ref);
+
+        Duration::try_from_secs_f64(host_time)
+            .ok()
+            .and_then(|since_epoch| UNIX_EPOCH.checked_add(since_epoch))
+    }
+
+    /// The current [`ClockEstimate`], if any samples have been added.
+    ///
+    /// [`ClockEstimate`]: ClockEstimate
+    pub fn estimate(&self) -> Option<ClockEstimate> {
+        let (stand_ref, host_ref) = self.reference?;
+        let &(last, _) = self.samples.back()?;
+
+        Some(ClockEstimate {
+            offset: host_ref + self.intercept + (self.slope - 1.0) * last - stand_ref,
+            drift: self.slope - 1.0,
+            latency: self.latency,
+        })
+    }
+}
+
+/// Seconds since the Unix epoch of the given time.
+fn epoch_secs(time: SystemTime) -> f64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64()
+}
+
+impl Display for ClockEstimate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "offset {:.3}s, drift {:.1} ppm, latency {:.1}ms",
+            self.offset,
+            self.drift * 1e6,
+            self.latency * 1e3
+        )
+    }
+}
+
+/// Formats the given time as a UTC date and time fit for a file name, like `2025-06-01_14-30-05`.
+pub fn file_timestamp(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let (year, month, day) = civil_from_days((secs / 86_400) as i64);
+    let secs = secs % 86_400;
+
+    format!(
+        "{year:04}-{month:02}-{day:02}_{:02}-{:02}-{:02}",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+/// The year, month and day of the given number of days since the Unix epoch, in the proleptic
+/// Gregorian calendar.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_from_march = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_from_march + 2) / 5 + 1;
+    let month = if month_from_march < 10 {
+        month_from_march + 3
+    } else {
+        month_from_march - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    (year, month as u32, day as u32)
+}
@@ -0,0 +1,617 @@
+use crate::{
+    derived::Expression, serial::CommandRetry, stand::StandMode, watchdog::WatchdogConfig,
+};
+use serde::Deserialize;
+use std::{collections::HashSet, error::Error, fmt::Display, fs, io, path::Path, time::Duration};
+
+/// The stand description built into the console, used when no other is given.
+const DEFAULT_STAND_CONFIG: &str = include_str!("../stand.toml");
+
+/// A description of the test stand: its valves, sensors, and the rules for each [`StandMode`].
+/// Loaded from TOML at startup so that the console may be reconfigured without recompiling.
+///
+/// [`StandMode`]: StandMode
+#[derive(Debug, Clone, Deserialize)]
+pub struct StandConfig {
+    #[serde(default)]
+    pub timing: TimingConfig,
+    pub valves: Vec<ValveConfig>,
+    #[serde(default)]
+    pub sensors: Vec<SensorConfig>,
+    #[serde(default)]
+    pub derived: Vec<DerivedConfig>,
+    pub modes: ModesConfig,
+    #[serde(default)]
+    pub redlines: Vec<RedlineConfig>,
+    #[serde(default)]
+    pub analysis: AnalysisConfig,
+}
+
+/// Timeouts and delays used throughout the console, all in seconds.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default)]
+pub struct TimingConfig {
+    pub command_ack_timeout: f64,
+    pub command_max_attempts: u32,
+    pub valve_settling_time: f64,
+    pub watchdog_warn_after: f64,
+    pub watchdog_safe_after: f64,
+}
+
+/// A single valve on the stand.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ValveConfig {
+    /// The name the stand knows this valve by, used both in commands and in the field reporting
+    /// its state.
+    pub name: String,
+
+    #[serde(default)]
+    pub kind: ValveKind,
+
+    /// Top left pixel of the valve's indicator on the P&ID, if it is drawn there.
+    #[serde(default)]
+    pub diagram: Option<[usize; 2]>,
+}
+
+/// The role a valve plays on the stand.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ValveKind {
+    /// A valve which the stand reports the state of.
+    #[default]
+    Reported,
+
+    /// A valve which may be commanded but whose state is not reported.
+    Manual,
+
+    /// An engine valve, fired after lighting the igniter.
+    Engine,
+
+    /// A timing valve, fired on its own.
+    Timing,
+
+    /// The igniter which lights an engine.
+    Igniter,
+}
+
+/// A single sensor field sent by the stand.
+#[derive(Debug, Clone, Deserialize)]
+pub struct SensorConfig {
+    pub name: String,
+
+    #[serde(default)]
+    pub unit: Option<String>,
+}
+
+/// A field computed by the console from the fields the stand sends, e.g. a pressure differential.
+/// Derived fields are recorded, plotted, and may be redlined like any other.
+#[derive(Debug, Clone, Deserialize)]
+pub struct DerivedConfig {
+    pub name: String,
+
+    /// The [`Expression`] the field is computed from, which may read fields the stand sends and
+    /// fields derived before this one.
+    ///
+    /// [`Expression`]: Expression
+    pub expression: Expression,
+
+    #[serde(default)]
+    pub unit: Option<String>,
+}
+
+/// Limits on a single field which safe the stand when exceeded during
+/// [`StandMode::PressurizationAndFiring`]. Every limit is optional.
+///
+/// [`StandMode::PressurizationAndFiring`]: StandMode::PressurizationAndFiring
+#[derive(Debug, Clone, Deserialize)]
+pub struct RedlineConfig {
+    pub field: String,
+
+    #[serde(default)]
+    pub min: Option<f64>,
+
+    #[serde(default)]
+    pub max: Option<f64>,
+
+    /// Largest allowed rate of change in either direction, in the field's unit per second.
+    #[serde(default)]
+    pub max_rate: Option<f64>,
+
+    /// Seconds after a sequence starts that the redline is armed from. If neither this nor
+    /// `armed_until` are given the redline is always armed.
+    #[serde(default)]
+    pub armed_after: Option<f64>,
+
+    /// Seconds after a sequence starts that the redline is disarmed at.
+    #[serde(default)]
+    pub armed_until: Option<f64>,
+}
+
+/// The fields post-test analysis of a record reads, and how it detects the burn.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct AnalysisConfig {
+    /// Field giving the engine's thrust, which the burn is detected from.
+    pub thrust: String,
+
+    /// Fields giving the mass of oxidizer and fuel left in their tanks.
+    pub ox_mass: String,
+    pub fuel_mass: String,
+
+    /// Field giving the chamber pressure, if the stand measures it.
+    pub chamber_pressure: Option<String>,
+
+    /// Fields giving the pressures of the tanks.
+    pub tank_pressures: Vec<String>,
+
+    /// Fraction of the peak thrust above which the engine is taken to be burning.
+    pub burn_threshold: f64,
+
+    /// Least peak thrust taken as a burn at all, in the thrust field's unit.
+    pub min_burn_thrust: f64,
+}
+
+/// The [`ModeConfig`] for each [`StandMode`].
+///
+/// [`ModeConfig`]: ModeConfig
+/// [`StandMode`]: StandMode
+#[derive(Debug, Clone, Deserialize)]
+pub struct ModesConfig {
+    #[serde(default)]
+    pub check_out: ModeConfig,
+    #[serde(default)]
+    pub oxygen_filling: ModeConfig,
+    #[serde(default)]
+    pub pressurization_and_firing: ModeConfig,
+    #[serde(default)]
+    pub safing: ModeConfig,
+}
+
+/// Rules for a single [`StandMode`], all given as lists of valve names.
+///
+/// [`StandMode`]: StandMode
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ModeConfig {
+    /// Valves which may be opened and closed by hand in this mode.
+    pub manual_valves: Vec<String>,
+
+    /// Valves commanded open when entering this mode.
+    pub open_on_entry: Vec<String>,
+
+    /// Valves commanded closed when entering this mode.
+    pub close_on_entry: Vec<String>,
+
+    /// Valves which must be reported closed for this mode to be entered.
+    pub closed_on_entry: Vec<String>,
+
+    /// Valves which must be reported closed for this mode to be left.
+    pub closed_on_exit: Vec<String>,
+}
+
+impl Default for TimingConfig {
+    fn default() -> Self {
+        Self {
+            command_ack_timeout: 0.25,
+            command_max_attempts: 4,
+            valve_settling_time: 1.0,
+            watchdog_warn_after: 1.0,
+            watchdog_safe_after: 5.0,
+        }
+    }
+}
+
+impl Default for AnalysisConfig {
+    fn default() -> Self {
+        Self {
+            thrust: "Scale Thrust".to_string(),
+            ox_mass: "Scale Ox".to_string(),
+            fuel_mass: "Scale Fuel".to_string(),
+            chamber_pressure: None,
+            tank_pressures: vec!["NPT1".to_string(), "IPT1".to_string()],
+            burn_threshold: 0.05,
+            min_burn_thrust: 5.0,
+        }
+    }
+}
+
+impl TimingConfig {
+    /// The [`CommandRetry`] policy described by this config.
+    ///
+    /// [`CommandRetry`]: CommandRetry
+    pub fn command_retry(&self) -> CommandRetry {
+        CommandRetry {
+            timeout: self.command_ack_timeout(),
+            max_attempts: self.command_max_attempts,
+        }
+    }
+
+    /// The [`WatchdogConfig`] described by this config.
+    ///
+    /// [`WatchdogConfig`]: WatchdogConfig
+    pub fn watchdog(&self) -> WatchdogConfig {
+        WatchdogConfig {
+            warn_after: self.watchdog_warn_after(),
+            safe_after: self.watchdog_safe_after(),
+        }
+    }
+
+    pub fn command_ack_timeout(&self) -> Duration {
+        Duration::from_secs_f64(self.command_ack_timeout)
+    }
+
+    pub fn valve_settling_time(&self) -> Duration {
+        Duration::from_secs_f64(self.valve_settling_time)
+    }
+
+    pub fn watchdog_warn_after(&self) -> Duration {
+        Duration::from_secs_f64(self.watchdog_warn_after)
+    }
+
+    pub fn watchdog_safe_after(&self) -> Duration {
+        Duration::from_secs_f64(self.watchdog_safe_after)
+    }
+}
+
+impl StandConfig {
+    /// Load and validate a [`StandConfig`] from the TOML file at the given path.
+    ///
+    /// [`StandConfig`]: StandConfig
+    pub fn load<P>(path: P) -> Result<Self, ConfigError>
+    where
+        P: AsRef<Path>,
+    {
+        let text = fs::read_to_string(path).map_err(ConfigError::Io)?;
+        Self::parse(&text)
+    }
+
+    /// Parse and validate a [`StandConfig`] from TOML text.
+    ///
+    /// [`StandConfig`]: StandConfig
+    pub fn parse(text: &str) -> Result<Self, ConfigError> {
+        let config: StandConfig = toml::from_str(text).map_err(ConfigError::Parse)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// The [`StandConfig`] built into the console.
+    ///
+    /// [`StandConfig`]: StandConfig
+    pub fn built_in() -> Self {
+        Self::parse(DEFAULT_STAND_CONFIG).expect("Built in stand config should be valid")
+    }
+
+    /// Check that every timing is a usable number of seconds, that every valve referenced by name
+    /// is defined, and that no name is used twice.
+    fn validate(&self) -> Result<(), ConfigError> {
+        let timing = &self.timing;
+        for (name, seconds) in [
+            ("command_ack_timeout", timing.command_ack_timeout),
+            ("valve_settling_time", timing.valve_settling_time),
+            ("watchdog_warn_after", timing.watchdog_warn_after),
+            ("watchdog_safe_after", timing.watchdog_safe_after),
+        ] {
+            if Duration::try_from_secs_f64(seconds).is_err() {
+                return Err(ConfigError::Invalid(format!(
+                    "timing {name} should be a non-negative number of seconds"
+                )));
+            }
+        }
+
+        if timing.watchdog_warn_after > timing.watchdog_safe_after {
+            return Err(ConfigError::Invalid(
+                "timing watchdog_warn_after should not be after watchdog_safe_after".to_string(),
+            ));
+        }
+
+        if timing.command_max_attempts < 1 {
+            return Err(ConfigError::Invalid(
+                "timing command_max_attempts should be at least 1".to_string(),
+            ));
+        }
+
+        let mut names = HashSet::new();
+
+        for name in self
+            .valves
+            .iter()
+            .map(|v| &v.name)
+            .chain(self.sensors.iter().map(|s| &s.name))
+            .chain(self.derived.iter().map(|d| &d.name))
+        {
+            if !names.insert(name.as_str()) {
+                return Err(ConfigError::Invalid(format!("'{name}' is defined twice")));
+            }
+        }
+
+        for (i, derived) in self.derived.iter().enumerate() {
+            for name in derived.expression.fields() {
+                let derived_before = self.derived[..i].iter().any(|d| &d.name == name);
+
+                if !derived_before && !self.field_names().contains(name) {
+                    return Err(ConfigError::Invalid(format!(
+                        "derived field '{}' reads unknown field '{name}'",
+                        derived.name
+                    )));
+                }
+            }
+        }
+
+        for redline in &self.redlines {
+            if !self.has_field(&redline.field) {
+                return Err(ConfigError::Invalid(format!(
+                    "redline on unknown field '{}'",
+                    redline.field
+                )));
+            }
+
+            let invalid = |message: &str| {
+                Err(ConfigError::Invalid(format!(
+                    "redline on '{}' {message}",
+                    redline.field
+                )))
+            };
+
+            if redline
+                .min
+                .into_iter()
+                .chain(redline.max)
+                .any(|v| !v.is_finite())
+            {
+                return invalid("should have finite limits");
+            }
+
+            if redline
+                .max_rate
+                .is_some_and(|r| !(r.is_finite() && r >= 0.0))
+            {
+                return invalid("should have a finite, non-negative max_rate");
+            }
+
+            if redline
+                .armed_after
+                .into_iter()
+                .chain(redline.armed_until)
+                .any(|t| !(t.is_finite() && t >= 0.0))
+            {
+                return invalid("should be armed at a finite, non-negative number of seconds");
+            }
+
+            if let (Some(min), Some(max)) = (redline.min, redline.max)
+                && min > max
+            {
+                return invalid("has min above max");
+            }
+
+            if let (Some(after), Some(until)) = (redline.armed_after, redline.armed_until)
+                && after >= until
+            {
+                return invalid("should be armed_after a time before armed_until");
+            }
+        }
+
+        let analysis = &self.analysis;
+        for name in [&analysis.thrust, &analysis.ox_mass, &analysis.fuel_mass]
+            .into_iter()
+            .chain(&analysis.chamber_pressure)
+            .chain(&analysis.tank_pressures)
+        {
+            if !self.has_field(name) {
+                return Err(ConfigError::Invalid(format!(
+                    "analysis reads unknown field '{name}'"
+                )));
+            }
+        }
+
+        if !(0.0..1.0).contains(&analysis.burn_threshold) {
+            return Err(ConfigError::Invalid(
+                "analysis burn_threshold should be a fraction of peak thrust".to_string(),
+            ));
+        }
+
+        if !(analysis.min_burn_thrust.is_finite() && analysis.min_burn_thrust > 0.0) {
+            return Err(ConfigError::Invalid(
+                "analysis min_burn_thrust should be a positive thrust".to_string(),
+            ));
+        }
+
+        for mode in StandMode::ALL {
+            let mode_config = self.mode(mode);
+
+            for name in mode_config
+                .manual_valves
+                .iter()
+                .chain(&mode_config.open_on_entry)
+                .chain(&mode_config.close_on_entry)
+                .chain(&mode_config.closed_on_entry)
+                .chain(&mode_config.closed_on_exit)
+            {
+                if self.valve(name).is_none() {
+                    return Err(ConfigError::Invalid(format!(
+                        "{mode} references unknown valve '{name}'"
+                    )));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Gives the [`ValveConfig`] for the valve with the given name.
+    ///
+    /// [`ValveConfig`]: ValveConfig
+    pub fn valve(&self, name: &str) -> Option<&ValveConfig> {
+        self.valves.iter().find(|v| v.name == name)
+    }
+
+    /// Gives an [`Iterator`] of the valves of the given [`ValveKind`].
+    ///
+    /// [`Iterator`]: Iterator
+    /// [`ValveKind`]: ValveKind
+    pub fn valves_of_kind(&self, kind: ValveKind) -> impl Iterator<Item = &ValveConfig> {
+        self.valves.iter().filter(move |v| v.kind == kind)
+    }
+
+    /// Gives the [`ModeConfig`] for the given [`StandMode`].
+    ///
+    /// [`ModeConfig`]: ModeConfig
+    /// [`StandMode`]: StandMode
+    pub fn mode(&self, mode: StandMode) -> &ModeConfig {
+        match mode {
+            StandMode::CheckOut => &self.modes.check_out,
+            StandMode::OxygenFilling => &self.modes.oxygen_filling,
+            StandMode::PressurizationAndFiring => &self.modes.pressurization_and_firing,
+            StandMode::Safing => &self.modes.safing,
+        }
+    }
+
+    /// Gives the unit of the field with the given name, if it has one.
+    pub fn unit(&self, name: &str) -> Option<&str> {
+        self.sensors
+            .iter()
+            .map(|s| (&s.name, &s.unit))
+            .chain(self.derived.iter().map(|d| (&d.name, &d.unit)))
+            .find(|(n, _)| *n == name)
+            .and_then(|(_, unit)| unit.as_deref())
+    }
+
+    /// Whether a field with the given name is expected, either sent by the stand or derived from
+    /// the fields it sends.
+    pub fn has_field(&self, name: &str) -> bool {
+        self.valves_of_kind(ValveKind::Reported)
+            .any(|v| v.name == name)
+            || self.sensors.iter().any(|s| s.name == name)
+            || self.derived.iter().any(|d| d.name == name)
+    }
+
+    /// The names of all fields the stand is expected to send, being every reported valve and every
+    /// sensor.
+    pub fn field_names(&self) -> Vec<String> {
+        self.valves_of_kind(ValveKind::Reported)
+            .map(|v| v.name.clone())
+            .chain(self.sensors.iter().map(|s| s.name.clone()))
+            .collect()
+    }
+}
+
+/// Errors from loading a [`StandConfig`].
+///
+/// [`StandConfig`]: StandConfig
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(io::Error),
+    Parse(toml::de::Error),
+    Invalid(String),
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::Io(e) => write!(f, "Could not read stand config: {e}"),
+            ConfigError::Parse(e) => write!(f, "Could not parse stand config: {e}"),
+            ConfigError::Invalid(e) => write!(f, "Invalid stand config: {e}"),
+        }
+    }
+}
+
+impl Error for ConfigError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The built in config with the given line of its `[timing]` table replaced.
+    fn with_timing(line: &str, replacement: &str) -> Result<StandConfig, ConfigError> {
+        assert!(DEFAULT_STAND_CONFIG.contains(line));
+        StandConfig::parse(&DEFAULT_STAND_CONFIG.replace(line, replacement))
+    }
+
+    #[test]
+    fn built_in_config_is_valid() {
+        StandConfig::built_in();
+    }
+
+    #[test]
+    fn rejects_unusable_timings() {
+        for replacement in [
+            "command_ack_timeout = -0.25",
+            "command_ack_timeout = nan",
+            "command_ack_timeout = inf",
+            "command_ack_timeout = 1e300",
+        ] {
+            let result = with_timing("command_ack_timeout = 0.25", replacement);
+            assert!(
+                matches!(result, Err(ConfigError::Invalid(_))),
+                "{replacement} was accepted"
+            );
+        }
+
+        for (line, replacement) in [
+            ("valve_settling_time = 1.0", "valve_settling_time = -1.0"),
+            ("watchdog_warn_after = 1.0", "watchdog_warn_after = nan"),
+            ("watchdog_safe_after = 5.0", "watchdog_safe_after = -inf"),
+            ("watchdog_warn_after = 1.0", "watchdog_warn_after = 6.0"),
+            ("command_max_attempts = 4", "command_max_attempts = 0"),
+        ] {
+            let result = with_timing(line, replacement);
+            assert!(
+                matches!(result, Err(ConfigError::Invalid(_))),
+                "{replacement} was accepted"
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_unusable_redlines() {
+        let with_redline = |redline: &str| {
+            StandConfig::parse(&format!(
+                "{DEFAULT_STAND_CONFIG}\n[[redlines]]\nfield = \"NPT1\"\n{redline}\n"
+            ))
+        };
+
+        with_redline(
+            "min = -10.0\nmax = 750.0\nmax_rate = 0.0\narmed_after = 0.0\narmed_until = 2.0",
+        )
+        .expect("redline should be valid");
+
+        for redline in [
+            "max = nan",
+            "min = -inf",
+            "max = inf",
+            "min = 10.0\nmax = 5.0",
+            "max_rate = nan",
+            "max_rate = -1.0",
+            "max_rate = inf",
+            "armed_after = nan",
+            "armed_until = -1.0",
+            "armed_after = 2.0\narmed_until = 2.0",
+            "armed_after = 3.0\narmed_until = 1.0",
+        ] {
+            assert!(
+                matches!(with_redline(redline), Err(ConfigError::Invalid(_))),
+                "{redline:?} was accepted"
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_unusable_min_burn_thrust() {
+        for replacement in ["0.0", "-5.0", "nan", "inf"] {
+            let text = DEFAULT_STAND_CONFIG.replace(
+                "min_burn_thrust = 5.0",
+                &format!("min_burn_thrust = {replacement}"),
+            );
+            assert!(
+                matches!(StandConfig::parse(&text), Err(ConfigError::Invalid(_))),
+                "{replacement} was accepted"
+            );
+        }
+    }
+
+    #[test]
+    fn accepts_zero_timings() {
+        let config = with_timing("valve_settling_time = 1.0", "valve_settling_time = 0.0")
+            .expect("zero settling time should be valid");
+        assert_eq!(config.timing.valve_settling_time(), Duration::ZERO);
+    }
+}
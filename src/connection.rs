@@ -0,0 +1,187 @@
+use crate::serial::{self, CommandRetry, FieldReciever, PortSettings, UsbSerialPortInfo};
+use std::time::{Duration, Instant};
+
+/// How long to wait between attempts to reopen a lost port.
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Manages which serial port the console is connected to. Lists the available ports for an
+/// operator to choose from, opens the chosen one, and reopens it when the [`FieldReciever`]
+/// reports the connection lost.
+///
+/// [`FieldReciever`]: FieldReciever
+#[derive(Debug, Default)]
+pub struct Connection {
+    /// The USB ports found by the last call to [`Connection::refresh_ports`].
+    ///
+    /// [`Connection::refresh_ports`]: Connection::refresh_ports
+    pub ports: Vec<UsbSerialPortInfo>,
+
+    /// Index into `ports` of the port selected by the operator.
+    pub selected_port: Option<usize>,
+
+    /// Settings to open the next port with.
+    pub settings: PortSettings,
+
+    /// Retry policy for commands sent to the opened port.
+    pub command_retry: CommandRetry,
+
+    /// The port to keep connected to, and the settings it was opened with.
+    target: Option<(UsbSerialPortInfo, PortSettings)>,
+    last_attempt: Option<Instant>,
+}
+
+impl Connection {
+    /// Create a new [`Connection`] with the available ports already listed.
+    ///
+    /// [`Connection`]: Connection
+    pub fn new(command_retry: CommandRetry) -> Self {
+        let mut connection = Self {
+            command_retry,
+            ..Self::default()
+        };
+        connection.refresh_ports();
+        connection
+    }
+
+    /// The port this [`Connection`] keeps connected, if any.
+    ///
+    /// [`Connection`]: Connection
+    pub fn target(&self) -> Option<&UsbSerialPortInfo> {
+        self.target.as_ref().map(|(port, _)| port)
+    }
+
+    /// The settings the port this [`Connection`] keeps connected was opened with, if any.
+    ///
+    /// [`Connection`]: Connection
+    pub fn target_settings(&self) -> Option<PortSettings> {
+        self.target.as_ref().map(|(_, settings)| *settings)
+    }
+
+    /// Re-enumerate the available USB ports, keeping the selection on the same port if it is
+    /// still present.
+    pub fn refresh_ports(&mut self) {
+        let selected = self.selected_port.and_then(|i| self.ports.get(i)).cloned();
+
+        self.ports = match serial::available_usb_ports() {
+            Ok(ports) => ports,
+            Err(e) => {
+                log::error!("Could not identify available USB ports: {e}");
+                Vec::new()
+            }
+        };
+
+        self.selected_port = selected
+            .and_then(|selected| self.ports.iter().position(|p| is_same_device(&selected, p)));
+    }
+
+    /// Open the selected port with the current settings and attach it to the given
+    /// [`FieldReciever`], replacing any existing connection.
+    ///
+    /// [`FieldReciever`]: FieldReciever
+    pub fn connect(&mut self, field_reciever: &mut FieldReciever) -> serialport::Result<()> {
+        let Some(port) = self.selected_port.and_then(|i| self.ports.get(i)) else {
+            return Err(serialport::Error::new(
+                serialport::ErrorKind::NoDevice,
+                "No port selected",
+            ));
+        };
+
+        let field_io =
+            serial::open_field_port(port, self.settings)?.with_command_retry(self.command_retry);
+        field_reciever.attach(field_io).map_err(|_| {
+            serialport::Error::new(serialport::ErrorKind::Unknown, "Field thread has died")
+        })?;
+
+        log::info!("Established serial connection to {}!", port.port_name);
+        self.target = Some((port.clone(), self.settings));
+        self.last_attempt = Some(Instant::now());
+        Ok(())
+    }
+
+    /// Stop keeping any port connected, and detach the [`FieldReciever`]'s device.
+    ///
+    /// [`FieldReciever`]: FieldReciever
+    pub fn disconnect(&mut self, field_reciever: &mut FieldReciever) {
+        self.target = None;
+
+        if field_reciever.detach().is_err() {
+            log::error!("Could not detach device, field thread has died");
+        }
+    }
+
+    /// If the given [`FieldReciever`] has lost its device, periodically re-enumerate the
+    /// available USB ports and reattach the target port once it reappears. The port may come back
+    /// under a different name, so it is matched by its USB IDs and serial number where possible.
+    ///
+    /// [`FieldReciever`]: FieldReciever
+    pub fn update(&mut self, field_reciever: &mut FieldReciever) {
+        let Some((target, settings)) = &mut self.target else {
+            return;
+        };
+
+        if field_reciever.is_connected() {
+            return;
+        }
+
+        let now = Instant::now();
+        if self
+            .last_attempt
+            .is_some_and(|t| now.duration_since(t) < RECONNECT_INTERVAL)
+        {
+            return;
+        }
+
+        self.last_attempt = Some(now);
+
+        let ports = match serial::available_usb_ports() {
+            Ok(ports) => ports,
+            Err(e) => {
+                log::error!("Could not identify available USB ports: {e}");
+                return;
+            }
+        };
+
+        let Some(port) = ports.into_iter().find(|port| is_same_device(target, port)) else {
+            log::warn!("Waiting for port {} to reappear", target.port_name);
+            return;
+        };
+
+        match serial::open_field_port(&port, *settings) {
+            Ok(field_io) => {
+                let field_io = field_io.with_command_retry(self.command_retry);
+                if field_reciever.attach(field_io).is_ok() {
+                    log::info!("Reconnected to {}", port.port_name);
+                    *target = port;
+                }
+            }
+
+            Err(e) => log::error!("Could not reopen {}: {e}", port.port_name),
+        }
+    }
+}
+
+/// Whether the two [`UsbSerialPortInfo`]s describe the same physical device.
+///
+/// [`UsbSerialPortInfo`]: UsbSerialPortInfo
+fn is_same_device(a: &UsbSerialPortInfo, b: &UsbSerialPortInfo) -> bool {
+    match (&a.usb_info.serial_number, &b.usb_info.serial_number) {
+        (Some(a_serial), Some(b_serial)) => {
+            a.usb_info.vid == b.usb_info.vid
+                && a.usb_info.pid == b.usb_info.pid
+                && a_serial == b_serial
+        }
+
+        _ => a.port_name == b.port_name,
+    }
+}
+
+/// A human readable description of a port, including its product name and serial number if
+/// known.
+pub fn describe_port(port: &UsbSerialPortInfo) -> String {
+    let product = port.usb_info.product.as_deref().unwrap_or("Unknown Device");
+
+    match &port.usb_info.serial_number {
+        Some(serial) => format!("{product} [{serial}] ({})", port.port_name),
+        None => format!("{product} ({})", port.port_name),
+    }
+}
@@ -0,0 +1,815 @@
+use crate::{
+    config::StandConfig,
+    serial::{SensorField, SensorValue},
+};
+use serde::Deserialize;
+use std::{
+    collections::{HashMap, VecDeque},
+    error::Error,
+    fmt::Display,
+    time::UNIX_EPOCH,
+};
+
+/// An arithmetic expression over fields, from which a derived field is computed as fields arrive.
+/// Expressions may use:
+///
+/// - numbers, e.g. `2.5`, and fields by name, e.g. `NPT1`, quoting any name which is not a
+///   single word, e.g. `"Scale Thrust"`.
+/// - `+`, `-`, `*`, `/`, negation, and parentheses.
+/// - `abs(x)`, `min(x, y)`, and `max(x, y)`.
+/// - `rate(x)`, the rate of change of `x` per second.
+/// - `avg(x, seconds)`, the moving average of `x` over the given number of seconds.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Expression {
+    text: String,
+    root: Node,
+    /// Names of the fields the expression reads, each given once.
+    fields: Vec<String>,
+    /// Number of `rate` and `avg` calls in the expression, each of which keeps a [`State`].
+    ///
+    /// [`State`]: State
+    states: usize,
+}
+
+/// A node of a parsed [`Expression`]. Calls which remember past values hold the index of their
+/// [`State`].
+///
+/// [`Expression`]: Expression
+/// [`State`]: State
+#[derive(Debug, Clone)]
+enum Node {
+    Number(f64),
+    Field(String),
+    Negate(Box<Node>),
+    Binary(Operator, Box<Node>, Box<Node>),
+    Abs(Box<Node>),
+    Min(Box<Node>, Box<Node>),
+    Max(Box<Node>, Box<Node>),
+    Rate(Box<Node>, usize),
+    Average(Box<Node>, f64, usize),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Operator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+}
+
+/// What a `rate` or `avg` call remembers between evaluations.
+#[derive(Debug, Clone)]
+enum State {
+    Rate {
+        last: Option<(f64, f64)>,
+        rate: Option<f64>,
+    },
+    Average {
+        samples: VecDeque<(f64, f64)>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Name(String),
+    Quoted(String),
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    LeftParen,
+    RightParen,
+    Comma,
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Number(n) => write!(f, "`{n}`"),
+            Token::Name(name) => write!(f, "`{name}`"),
+            Token::Quoted(name) => write!(f, "`\"{name}\"`"),
+            Token::Plus => write!(f, "`+`"),
+            Token::Minus => write!(f, "`-`"),
+            Token::Star => write!(f, "`*`"),
+            Token::Slash => write!(f, "`/`"),
+            Token::LeftParen => write!(f, "`(`"),
+            Token::RightParen => write!(f, "`)`"),
+            Token::Comma => write!(f, "`,`"),
+        }
+    }
+}
+
+impl Expression {
+    /// Parse an [`Expression`] from its text.
+    ///
+    /// [`Expression`]: Expression
+    pub fn parse(text: &str) -> Result<Self, ExpressionError> {
+        let mut parser = Parser {
+            tokens: tokenize(text)?,
+            next: 0,
+            end: text.len(),
+            fields: Vec::new(),
+            states: 0,
+        };
+
+        let root = parser.expression()?;
+        if let Some((position, token)) = parser.tokens.get(parser.next) {
+            return Err(ExpressionError::new(
+                *position,
+                format!("unexpected {token}"),
+            ));
+        }
+
+        Ok(Self {
+            text: text.to_string(),
+            root,
+            fields: parser.fields,
+            states: parser.states,
+        })
+    }
+
+    /// Names of the fields the [`Expression`] reads.
+    ///
+    /// [`Expression`]: Expression
+    pub fn fields(&self) -> &[String] {
+        &self.fields
+    }
+}
+
+impl TryFrom<String> for Expression {
+    type Error = ExpressionError;
+
+    fn try_from(text: String) -> Result<Self, Self::Error> {
+        Self::parse(&text)
+    }
+}
+
+impl Display for Expression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+/// Split the text of an [`Expression`] into [`Token`]s, each with its position in the text.
+///
+/// [`Expression`]: Expression
+/// [`Token`]: Token
+fn tokenize(text: &str) -> Result<Vec<(usize, Token)>, ExpressionError> {
+    let mut tokens = Vec::new();
+    let mut chars = text.char_indices().peekable();
+
+    while let Some(&(position, c)) = chars.peek() {
+        let token = match c {
+            c if c.is_whitespace() => {
+                chars.next();
+                continue;
+            }
+
+            '+' => Token::Plus,
+            '-' => Token::Minus,
+            '*' => Token::Star,
+            '/' => Token::Slash,
+            '(' => Token::LeftParen,
+            ')' => Token::RightParen,
+            ',' => Token::Comma,
+
+            '"' => {
+                chars.next();
+                let start = position + 1;
+                let Some((end, _)) = chars.find(|&(_, c)| c == '"') else {
+                    return Err(ExpressionError::new(position, "unclosed quote"));
+                };
+
+                tokens.push((position, Token::Quoted(text[start..end].to_string())));
+                continue;
+            }
+
+            c if c.is_ascii_digit() || c == '.' => {
+                let mut end = position;
+                while let Some(&(i, c)) = chars.peek()
+                    && (c.is_ascii_alphanumeric() || c == '.')
+                {
+                    end = i + c.len_utf8();
+                    chars.next();
+                }
+
+                let number = &text[position..end];
+                let Ok(number) = number.parse() else {
+                    return Err(ExpressionError::new(
+                        position,
+                        format!("invalid number `{number}`"),
+                    ));
+                };
+
+                tokens.push((position, Token::Number(number)));
+                continue;
+            }
+
+            c if c.is_alphabetic() || c == '_' => {
+                let mut end = position;
+                while let Some(&(i, c)) = chars.peek()
+                    && (c.is_alphanumeric() || c == '_')
+                {
+                    end = i + c.len_utf8();
+                    chars.next();
+                }
+
+                tokens.push((position, Token::Name(text[position..end].to_string())));
+                continue;
+            }
+
+            c => {
+                return Err(ExpressionError::new(position, format!("unexpected `{c}`")));
+            }
+        };
+
+        chars.next();
+        tokens.push((position, token));
+    }
+
+    Ok(tokens)
+}
+
+/// A recursive descent parser over the [`Token`]s of an [`Expression`].
+///
+/// [`Token`]: Token
+/// [`Expression`]: Expression
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    next: usize,
+    /// Length of the text, which is the position of errors at its end.
+    end: usize,
+    fields: Vec<String>,
+    states: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.next).map(|(_, token)| token)
+    }
+
+    fn position(&self) -> usize {
+        self.tokens
+            .get(self.next)
+            .map_or(self.end, |(position, _)| *position)
+    }
+
+    /// Take the next token if it is the given one.
+    fn eat(&mut self, token: &Token) -> bool {
+        let found = self.peek() == Some(token);
+        if found {
+            self.next += 1;
+        }
+
+        found
+    }
+
+    fn expect(&mut self, token: &Token, expected: &str) -> Result<(), ExpressionError> {
+        match self.eat(token) {
+            true => Ok(()),
+            false => Err(ExpressionError::new(
+                self.position(),
+                format!("expected {expected}"),
+            )),
+        }
+    }
+
+    /// `term (('+' | '-') term)*`
+    fn expression(&mut self) -> Result<Node, ExpressionError> {
+        let mut node = self.term()?;
+
+        loop {
+            let operator = match self.peek() {
+                Some(Token::Plus) => Operator::Add,
+                Some(Token::Minus) => Operator::Subtract,
+                _ => return Ok(node),
+            };
+
+            self.next += 1;
+            node = Node::Binary(operator, Box::new(node), Box::new(self.term()?));
+        }
+    }
+
+    /// `unary (('*' | '/') unary)*`
+    fn term(&mut self) -> Result<Node, ExpressionError> {
+        let mut node = self.unary()?;
+
+        loop {
+            let operator = match self.peek() {
+                Some(Token::Star) => Operator::Multiply,
+                Some(Token::Slash) => Operator::Divide,
+                _ => return Ok(node),
+            };
+
+            self.next += 1;
+            node = Node::Binary(operator, Box::new(node), Box::new(self.unary()?));
+        }
+    }
+
+    /// `'-' unary | primary`
+    fn unary(&mut self) -> Result<Node, ExpressionError> {
+        match self.eat(&Token::Minus) {
+            true => Ok(Node::Negate(Box::new(self.unary()?))),
+            false => self.primary(),
+        }
+    }
+
+    /// A number, field, call, or parenthesised expression.
+    fn primary(&mut self) -> Result<Node, ExpressionError> {
+        let position = self.position();
+        let Some((_, token)) = self.tokens.get(self.next).cloned() else {
+            return Err(ExpressionError::new(
+                position,
+                "unexpected end of expression",
+            ));
+        };
+        self.next += 1;
+
+        match token {
+            Token::Number(n) => Ok(Node::Number(n)),
+
+            Token::LeftParen => {
+                let node = self.expression()?;
+                self.expect(&Token::RightParen, "`)`")?;
+                Ok(node)
+            }
+
+            Token::Name(name) if self.eat(&Token::LeftParen) => self.call(position, &name),
+            Token::Name(name) | Token::Quoted(name) => Ok(self.field(name)),
+
+            token => Err(ExpressionError::new(
+                position,
+                format!("unexpected {token}"),
+            )),
+        }
+    }
+
+    fn field(&mut self, name: String) -> Node {
+        if !self.fields.contains(&name) {
+            self.fields.push(name.clone());
+        }
+
+        Node::Field(name)
+    }
+
+    /// The arguments and closing parenthesis of a call to the named function.
+    fn call(&mut self, position: usize, name: &str) -> Result<Node, ExpressionError> {
+        let first = Box::new(self.expression()?);
+
+        let node = match name {
+            "abs" => Node::Abs(first),
+            "min" | "max" => {
+                self.expect(&Token::Comma, "`,` and a second argument")?;
+                let second = Box::new(self.expression()?);
+
+                match name {
+                    "min" => Node::Min(first, second),
+                    _ => Node::Max(first, second),
+                }
+            }
+            "rate" => {
+                self.states += 1;
+                Node::Rate(first, self.states - 1)
+            }
+            "avg" => {
+                self.expect(&Token::Comma, "`,` and a window in seconds")?;
+
+                let window_position = self.position();
+                let window = match self.peek() {
+                    Some(&Token::Number(window)) if window > 0.0 => window,
+                    _ => {
+                        return Err(ExpressionError::new(
+                            window_position,
+                            "expected a positive window in seconds",
+                        ));
+                    }
+                };
+                self.next += 1;
+
+                self.states += 1;
+                Node::Average(first, window, self.states - 1)
+            }
+            _ => {
+                return Err(ExpressionError::new(
+                    position,
+                    format!("unknown function `{name}`"),
+                ));
+            }
+        };
+
+        self.expect(&Token::RightParen, "`)`")?;
+        Ok(node)
+    }
+}
+
+impl Node {
+    /// The value of the node given the latest value of each field, at the given time in seconds,
+    /// or [`None`] if a field it reads has not been recieved yet or a rate is not yet known.
+    ///
+    /// [`None`]: None
+    fn eval(&self, values: &HashMap<String, f64>, time: f64, states: &mut [State]) -> Option<f64> {
+        let value = match self {
+            Node::Number(n) => *n,
+            Node::Field(name) => *values.get(name)?,
+            Node::Negate(node) => -node.eval(values, time, states)?,
+
+            Node::Binary(operator, left, right) => {
+                let left = left.eval(values, time, states);
+                let right = right.eval(values, time, states);
+                let (left, right) = (left?, right?);
+
+                match operator {
+                    Operator::Add => left + right,
+                    Operator::Subtract => left - right,
+                    Operator::Multiply => left * right,
+                    Operator::Divide => left / right,
+                }
+            }
+
+            Node::Abs(node) => node.eval(values, time, states)?.abs(),
+
+            Node::Min(left, right) | Node::Max(left, right) => {
+                let left = left.eval(values, time, states);
+                let right = right.eval(values, time, states);
+                let (left, right) = (left?, right?);
+
+                match self {
+                    Node::Min(..) => left.min(right),
+                    _ => left.max(right),
+                }
+            }
+
+            Node::Rate(node, index) => {
+                let value = node.eval(values, time, states)?;
+                let State::Rate { last, rate } = &mut states[*index] else {
+                    return None;
+                };
+
+                match *last {
+                    Some((last_time, last_value)) if time > last_time => {
+                        *rate = Some((value - last_value) / (time - last_time));
+                        *last = Some((time, value));
+                    }
+                    // a newer value from the same moment replaces the older
+                    Some((last_time, _)) => *last = Some((last_time, value)),
+                    None => *last = Some((time, value)),
+                }
+
+                (*rate)?
+            }
+
+            Node::Average(node, window, index) => {
+                let value = node.eval(values, time, states)?;
+                let State::Average { samples } = &mut states[*index] else {
+                    return None;
+                };
+
+                samples.push_back((time, value));
+                while samples.front().is_some_and(|&(t, _)| t < time - window) {
+                    samples.pop_front();
+                }
+
+                samples.iter().map(|(_, v)| v).sum::<f64>() / samples.len() as f64
+            }
+        };
+
+        Some(value)
+    }
+
+    /// A fresh [`State`] for each `rate` and `avg` call under the node, in index order.
+    ///
+    /// [`State`]: State
+    fn states(&self, states: &mut Vec<State>) {
+        match self {
+            Node::Number(_) | Node::Field(_) => (),
+            Node::Negate(node) | Node::Abs(node) => node.states(states),
+            Node::Binary(_, left, right) | Node::Min(left, right) | Node::Max(left, right) => {
+                left.states(states);
+                right.states(states);
+            }
+            Node::Rate(node, _) => {
+                node.states(states);
+                states.push(State::Rate {
+                    last: None,
+                    rate: None,
+                });
+            }
+            Node::Average(node, ..) => {
+                node.states(states);
+                states.push(State::Average {
+                    samples: VecDeque::new(),
+                });
+            }
+        }
+    }
+}
+
+/// Computes the derived fields in a [`StandConfig`] from the fields they read, as they arrive.
+///
+/// [`StandConfig`]: StandConfig
+#[derive(Debug, Default)]
+pub struct DerivedFields {
+    channels: Vec<DerivedChannel>,
+    /// The latest value of every field recieved or derived.
+    values: HashMap<String, f64>,
+}
+
+#[derive(Debug)]
+struct DerivedChannel {
+    name: String,
+    expression: Expression,
+    states: Vec<State>,
+}
+
+impl DerivedFields {
+    /// [`DerivedFields`] computing every derived field in the given [`StandConfig`].
+    ///
+    /// [`DerivedFields`]: DerivedFields
+    /// [`StandConfig`]: StandConfig
+    pub fn new(config: &StandConfig) -> Self {
+        let channels = config
+            .derived
+            .iter()
+            .map(|derived| {
+                let mut states = Vec::with_capacity(derived.expression.states);
+                derived.expression.root.states(&mut states);
+
+                DerivedChannel {
+                    name: derived.name.clone(),
+                    expression: derived.expression.clone(),
+                    states,
+                }
+            })
+            .collect();
+
+        Self {
+            channels,
+            values: HashMap::new(),
+        }
+    }
+
+    /// Take in a newly recieved [`SensorField`], giving every derived field which reads it, or
+    /// reads a field derived from it. Derived fields have the time of the field they were derived
+    /// from.
+    ///
+    /// [`SensorField`]: SensorField
+    pub fn derive(&mut self, field: &SensorField) -> Vec<SensorField> {
+        if self.channels.is_empty() {
+            return Vec::new();
+        }
+
+        self.values.insert(field.name.clone(), field.value.to_num());
+
+        let time = field
+            .time
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64();
+
+        let mut derived: Vec<SensorField> = Vec::new();
+        for channel in &mut self.channels {
+            let reads_changed = channel.expression.fields().iter().any(|name| {
+                *name == field.name || derived.iter().any(|derived| derived.name == *name)
+            });
+
+            if !reads_changed {
+                continue;
+            }
+
+            let Some(value) = channel
+                .expression
+                .root
+                .eval(&self.values, time, &mut channel.states)
+                .filter(|value| value.is_finite())
+            else {
+                continue;
+            };
+
+            self.values.insert(channel.name.clone(), value);
+            derived.push(SensorField {
+                name: channel.name.clone(),
+                value: SensorValue::Float(value),
+                time: field.time,
+                stand_time: field.stand_time,
+            });
+        }
+
+        derived
+    }
+}
+
+/// An error in the text of an [`Expression`], at the given character position.
+///
+/// [`Expression`]: Expression
+#[derive(Debug)]
+pub struct ExpressionError {
+    position: usize,
+    message: String,
+}
+
+impl ExpressionError {
+    fn new(position: usize, message: impl Into<String>) -> Self {
+        Self {
+            position,
+            message: message.into(),
+        }
+    }
+}
+
+impl Display for ExpressionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Invalid expression at character {}: {}",
+            self.position + 1,
+            self.message
+        )
+    }
+}
+
+impl Error for ExpressionError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::DerivedConfig;
+    use std::time::{Duration, SystemTime};
+
+    /// An [`Expression`] along with the [`State`]s of its calls, evaluated over time.
+    ///
+    /// [`Expression`]: Expression
+    /// [`State`]: State
+    struct Evaluator {
+        expression: Expression,
+        states: Vec<State>,
+    }
+
+    impl Evaluator {
+        fn new(text: &str) -> Self {
+            let expression = Expression::parse(text).unwrap();
+            let mut states = Vec::new();
+            expression.root.states(&mut states);
+            assert_eq!(states.len(), expression.states);
+
+            Self { expression, states }
+        }
+
+        /// The value of the expression at the given time given the values of the given fields.
+        fn at(&mut self, time: f64, values: &[(&str, f64)]) -> Option<f64> {
+            let values = values
+                .iter()
+                .map(|&(name, value)| (name.to_string(), value))
+                .collect();
+
+            self.expression.root.eval(&values, time, &mut self.states)
+        }
+    }
+
+    /// The value of the given expression, which should not remember past values.
+    fn value(text: &str, values: &[(&str, f64)]) -> Option<f64> {
+        Evaluator::new(text).at(0.0, values)
+    }
+
+    /// A field with the given value, taken the given number of seconds after the epoch.
+    fn field(name: &str, value: f64, time: f64) -> SensorField {
+        SensorField {
+            time: UNIX_EPOCH + Duration::from_secs_f64(time),
+            ..SensorField::new(name.to_string(), SensorValue::Float(value))
+        }
+    }
+
+    #[test]
+    fn operators_follow_precedence() {
+        assert_eq!(value("1 + 2 * 3", &[]), Some(7.0));
+        assert_eq!(value("(1 + 2) * 3", &[]), Some(9.0));
+        assert_eq!(value("8 - 4 - 2", &[]), Some(2.0));
+        assert_eq!(value("8 / 4 / 2", &[]), Some(1.0));
+        assert_eq!(value("-2 * -3", &[]), Some(6.0));
+        assert_eq!(value("--2 - -.5", &[]), Some(2.5));
+        assert_eq!(value("abs(1 - 4) + min(2, 3) * max(2, 3)", &[]), Some(9.0));
+    }
+
+    #[test]
+    fn reads_fields_by_name_or_quoted() {
+        let values = [("NPT1", 500.0), ("Scale Thrust", 20.0), ("x_2", 3.0)];
+
+        assert_eq!(value("NPT1 - 2 * x_2", &values), Some(494.0));
+        assert_eq!(value("\"Scale Thrust\" / 4", &values), Some(5.0));
+        assert_eq!(value("\"NPT1\"", &values), Some(500.0));
+        assert_eq!(value("NPT1 + NPT3", &values), None);
+
+        let expression = Expression::parse("NPT1 - \"Scale Thrust\" + NPT1").unwrap();
+        assert_eq!(expression.fields(), ["NPT1", "Scale Thrust"]);
+    }
+
+    #[test]
+    fn errors_give_their_position() {
+        for (text, position) in [
+            ("", 0),
+            ("1 +", 3),
+            ("(1 + 2", 6),
+            ("1 2", 2),
+            ("NPT1 $ 2", 5),
+            ("\"Scale Thrust", 0),
+            ("1.2.3 + 1", 0),
+            ("sqrt(2)", 0),
+            ("min(1)", 5),
+            ("avg(NPT1, 0)", 10),
+            ("avg(NPT1, -1)", 10),
+            ("avg(NPT1, NPT3)", 10),
+            ("rate(NPT1, 1)", 9),
+            ("1 + )", 4),
+        ] {
+            let e = Expression::parse(text).unwrap_err();
+            assert_eq!(e.position, position, "{text:?}: {e}");
+        }
+    }
+
+    #[test]
+    fn rate_is_per_second_between_times() {
+        let mut rate = Evaluator::new("rate(NPT1)");
+
+        assert_eq!(rate.at(0.0, &[("NPT1", 100.0)]), None);
+        assert_eq!(rate.at(0.5, &[("NPT1", 150.0)]), Some(100.0));
+
+        // a newer value at the same time replaces the older without changing the rate
+        assert_eq!(rate.at(0.5, &[("NPT1", 200.0)]), Some(100.0));
+        assert_eq!(rate.at(1.0, &[("NPT1", 200.0)]), Some(0.0));
+    }
+
+    #[test]
+    fn average_is_over_its_window() {
+        let mut average = Evaluator::new("avg(NPT1, 1)");
+
+        assert_eq!(average.at(0.0, &[("NPT1", 3.0)]), Some(3.0));
+        assert_eq!(average.at(0.5, &[("NPT1", 5.0)]), Some(4.0));
+        assert_eq!(average.at(1.0, &[("NPT1", 7.0)]), Some(5.0));
+        assert_eq!(average.at(1.75, &[("NPT1", 9.0)]), Some(8.0));
+    }
+
+    #[test]
+    fn each_call_keeps_its_own_state() {
+        // the inner rate's state is used before the outer's, and the average's after both
+        let mut calls = Evaluator::new("rate(rate(NPT1)) + avg(rate(NPT3), 10)");
+        let mut at =
+            |time: f64, npt1: f64, npt3: f64| calls.at(time, &[("NPT1", npt1), ("NPT3", npt3)]);
+
+        assert_eq!(at(0.0, 0.0, 0.0), None);
+        assert_eq!(at(1.0, 1.0, 10.0), None);
+        assert_eq!(at(2.0, 4.0, 30.0), Some(2.0 + 15.0));
+        assert_eq!(at(3.0, 9.0, 60.0), Some(2.0 + 20.0));
+    }
+
+    #[test]
+    fn derives_fields_from_fields_and_each_other() {
+        let mut config = StandConfig::built_in();
+        config.derived.push(DerivedConfig {
+            name: "NP Drop Rate".to_string(),
+            expression: Expression::parse("rate(\"NP Line Drop\")").unwrap(),
+            unit: None,
+        });
+        let mut derived = DerivedFields::new(&config);
+
+        let values = |fields: Vec<SensorField>| -> Vec<(String, SensorValue, SystemTime)> {
+            fields
+                .into_iter()
+                .map(|f| (f.name, f.value, f.time))
+                .collect()
+        };
+
+        assert!(derived.derive(&field("NPT1", 500.0, 10.0)).is_empty());
+        assert_eq!(
+            values(derived.derive(&field("NPT3", 480.0, 10.5))),
+            [(
+                "NP Line Drop".to_string(),
+                SensorValue::Float(20.0),
+                UNIX_EPOCH + Duration::from_secs_f64(10.5)
+            )]
+        );
+        assert_eq!(
+            values(derived.derive(&field("NPT3", 470.0, 11.0))),
+            [
+                (
+                    "NP Line Drop".to_string(),
+                    SensorValue::Float(30.0),
+                    UNIX_EPOCH + Duration::from_secs(11)
+                ),
+                (
+                    "NP Drop Rate".to_string(),
+                    SensorValue::Float(20.0),
+                    UNIX_EPOCH + Duration::from_secs(11)
+                ),
+            ]
+        );
+
+        // nothing is derived until every field read has been recieved, or from non-finite values
+        assert!(derived.derive(&field("IPT3", 1.0, 11.5)).is_empty());
+        assert!(
+            derived
+                .derive(&field("IPT1", f64::INFINITY, 12.0))
+                .is_empty()
+        );
+    }
+}
@@ -1,9 +1,13 @@
-use crate::stand::{StandState, ValveState};
+use crate::{
+    config::StandConfig,
+    stand::{StandState, ValveMismatch, ValveState},
+};
 use eframe::egui::{self, Color32};
 
 const COLOR_OPEN: Color32 = Color32::from_rgb(0, 255, 0);
 const COLOR_CLOSED: Color32 = Color32::from_rgb(255, 0, 0);
 const COLOR_UNKNOWN: Color32 = Color32::from_rgb(128, 128, 128);
+const COLOR_MISMATCH: Color32 = Color32::from_rgb(255, 0, 255);
 
 /// A wrapper over an [`egui::ColorImage`] and [`egui::TextureHandle`] for handling a changing image
 /// and reloading its corrosponding texture.
@@ -56,27 +60,38 @@ impl Diagram {
         self.image = self.base_image.clone();
     }
 
-    /// Mark up the [`Diagram`] with the given [`StandState`].
+    /// Mark up the [`Diagram`] with the given [`StandState`], drawing each valve at the position
+    /// given in the [`StandConfig`]. Valves with a [`ValveMismatch`] are drawn in a distinct color
+    /// regardless of their reported state.
     ///
     /// [`Diagram`]: Diagram
     /// [`StandState`]: StandState
-    pub fn plot_valves(&mut self, stand_state: StandState) {
-        self.set_valve(405, 475, stand_state.valve_np1);
-        self.set_valve(400, 190, stand_state.valve_np2);
-        self.set_valve(365, 240, stand_state.valve_np3);
-        self.set_valve(175, 450, stand_state.valve_np4);
-        self.set_valve(665, 475, stand_state.valve_ip1);
-        self.set_valve(670, 195, stand_state.valve_ip2);
-        self.set_valve(735, 285, stand_state.valve_ip3);
+    /// [`StandConfig`]: StandConfig
+    /// [`ValveMismatch`]: ValveMismatch
+    pub fn plot_valves(
+        &mut self,
+        config: &StandConfig,
+        stand_state: &StandState,
+        mismatches: &[ValveMismatch],
+    ) {
+        for valve in &config.valves {
+            let Some([x, y]) = valve.diagram else {
+                continue;
+            };
+
+            let mismatched = mismatches.iter().any(|m| m.valve == valve.name);
+            self.set_valve(x, y, stand_state.valve(&valve.name), mismatched);
+        }
     }
 
-    pub fn set_valve(&mut self, x: usize, y: usize, valve: Option<ValveState>) {
+    pub fn set_valve(&mut self, x: usize, y: usize, valve: Option<ValveState>, mismatched: bool) {
         self.set_region(
             x,
             x + 40,
             y,
             y + 40,
             match valve {
+                _ if mismatched => COLOR_MISMATCH,
                 Some(ValveState::Open) => COLOR_OPEN,
                 Some(ValveState::Closed) => COLOR_CLOSED,
                 None => COLOR_UNKNOWN,
@@ -94,6 +109,11 @@ impl Diagram {
 
     pub fn set_pixel(&mut self, x: usize, y: usize, color: Color32) {
         let w = self.image.width();
-        self.image.pixels[y * w + x] = color;
+
+        if x < w
+            && let Some(pixel) = self.image.pixels.get_mut(y * w + x)
+        {
+            *pixel = color;
+        }
     }
 }
@@ -0,0 +1,119 @@
+use crate::{
+    redline::RedlineTrip,
+    sequence::SequenceState,
+    serial::{CommandFailure, CommandId, ValveCommand},
+    stand::{ModeTransitionError, StandMode},
+};
+use std::{fmt::Display, time::SystemTime};
+
+/// Something which happened to the stand or in the console, stamped with the host time it happened
+/// at, which is the same time base as [`SensorField::time`].
+///
+/// [`SensorField::time`]: crate::serial::SensorField::time
+#[derive(Debug, Clone)]
+pub struct StandEvent {
+    pub time: SystemTime,
+    pub kind: EventKind,
+}
+
+/// The kinds of [`StandEvent`].
+///
+/// [`StandEvent`]: StandEvent
+#[derive(Debug, Clone)]
+pub enum EventKind {
+    /// A command was written down serial for the first time.
+    CommandSent(CommandId, ValveCommand),
+
+    /// The stand acknowledged a command.
+    CommandAcknowledged(CommandId, ValveCommand),
+
+    /// The stand did not act on a command.
+    CommandFailed(CommandFailure),
+
+    /// The operator started the sequence with the given name.
+    SequenceStarted(String),
+
+    /// A running sequence reached the given step, e.g. `open NP2`.
+    SequenceStep(String),
+
+    /// A running sequence timed out waiting until the given condition.
+    SequenceTimedOut(String),
+
+    /// A running sequence stopped in the given state.
+    SequenceEnded(SequenceState),
+
+    /// The stand moved from the first mode to the second.
+    ModeChanged(StandMode, StandMode),
+
+    /// The stand could not move to the given mode.
+    ModeTransitionFailed(StandMode, ModeTransitionError),
+
+    /// The stand was safed, for the given reason.
+    Failsafe(String),
+
+    /// A redline tripped.
+    RedlineTripped(RedlineTrip),
+
+    /// The operator tared the named field, which now has the given offset subtracted from it.
+    Tared(String, f64),
+
+    /// The operator cleared the tare of the named field.
+    TareCleared(String),
+}
+
+impl StandEvent {
+    /// A [`StandEvent`] which happened just now.
+    ///
+    /// [`StandEvent`]: StandEvent
+    pub fn now(kind: EventKind) -> Self {
+        Self::at(SystemTime::now(), kind)
+    }
+
+    /// A [`StandEvent`] which happened at the given time.
+    ///
+    /// [`StandEvent`]: StandEvent
+    pub fn at(time: SystemTime, kind: EventKind) -> Self {
+        Self { time, kind }
+    }
+}
+
+impl EventKind {
+    /// A short name for the kind of event, which records may be filtered on.
+    pub fn name(&self) -> &'static str {
+        match self {
+            EventKind::CommandSent(..) => "command_sent",
+            EventKind::CommandAcknowledged(..) => "command_acknowledged",
+            EventKind::CommandFailed(_) => "command_failed",
+            EventKind::SequenceStarted(_) => "sequence_started",
+            EventKind::SequenceStep(_) => "sequence_step",
+            EventKind::SequenceTimedOut(_) => "sequence_timed_out",
+            EventKind::SequenceEnded(_) => "sequence_ended",
+            EventKind::ModeChanged(..) => "mode_changed",
+            EventKind::ModeTransitionFailed(..) => "mode_transition_failed",
+            EventKind::Failsafe(_) => "failsafe",
+            EventKind::RedlineTripped(_) => "redline_tripped",
+            EventKind::Tared(..) => "tared",
+            EventKind::TareCleared(_) => "tare_cleared",
+        }
+    }
+}
+
+impl Display for EventKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EventKind::CommandSent(id, command) => write!(f, "{command} (#{id})"),
+            EventKind::CommandAcknowledged(id, command) => write!(f, "{command} (#{id})"),
+            EventKind::CommandFailed(failure) => write!(f, "{failure}"),
+            EventKind::SequenceStarted(name) => write!(f, "{name}"),
+            EventKind::SequenceStep(step) => write!(f, "{step}"),
+            EventKind::SequenceTimedOut(condition) => write!(f, "waiting until {condition}"),
+            EventKind::SequenceEnded(state) => write!(f, "{state}"),
+            EventKind::ModeChanged(from, to) => write!(f, "{from} -> {to}"),
+            EventKind::ModeTransitionFailed(to, e) => write!(f, "{to}: {e}"),
+            EventKind::Failsafe(reason) => write!(f, "{reason}"),
+            EventKind::RedlineTripped(trip) => write!(f, "{trip}"),
+            EventKind::Tared(name, offset) => write!(f, "{name} offset by {offset}"),
+            EventKind::TareCleared(name) => write!(f, "{name}"),
+        }
+    }
+}
@@ -1,89 +1,587 @@
-use std::time::{Duration, SystemTime};
+use egui_plot::PlotPoint;
+use std::{
+    borrow::Cow,
+    time::{Duration, Instant, SystemTime},
+};
 
-#[derive(Debug, Clone)]
-pub struct ValueHistory<T>
-where
-    T: Clone + PartialEq,
-{
-    history: Vec<HistoricalValue<T>>,
+/// Capacity a [`Ring`] starts with, which doubles whenever it fills.
+///
+/// [`Ring`]: Ring
+const INITIAL_CAPACITY: usize = 256;
+
+/// How many values each [`Summary`] of a level summarises of the level below it.
+///
+/// [`Summary`]: Summary
+const SUMMARY_FACTOR: u64 = 8;
+
+/// How many levels of [`Summary`]s a [`ValueHistory`] keeps, the coarsest of which summarises
+/// `SUMMARY_FACTOR.pow(SUMMARY_LEVELS)` values each.
+///
+/// [`Summary`]: Summary
+/// [`ValueHistory`]: ValueHistory
+const SUMMARY_LEVELS: usize = 5;
+
+/// Pairs a monotonic [`Instant`] with the [`SystemTime`] it was taken at, so that host times
+/// stamped on fields can be placed on a clock which never jumps.
+///
+/// [`Instant`]: Instant
+/// [`SystemTime`]: SystemTime
+#[derive(Debug, Clone, Copy)]
+pub struct TimeBase {
+    origin: Instant,
+    origin_time: SystemTime,
 }
 
-impl<T> ValueHistory<T>
-where
-    T: Clone + PartialEq,
-{
-    /// Creates a new empty [`FieldHistory`].
+impl TimeBase {
+    /// A [`TimeBase`] starting now.
     ///
-    /// [`FieldHistory`]: FieldHistory
+    /// [`TimeBase`]: TimeBase
     pub fn new() -> Self {
+        Self {
+            origin: Instant::now(),
+            origin_time: SystemTime::now(),
+        }
+    }
+
+    /// The [`Instant`] corresponding to the given [`SystemTime`]. Times from before the
+    /// [`TimeBase`] started are given its start.
+    ///
+    /// [`Instant`]: Instant
+    /// [`SystemTime`]: SystemTime
+    /// [`TimeBase`]: TimeBase
+    pub fn instant(&self, time: SystemTime) -> Instant {
+        match time.duration_since(self.origin_time) {
+            Ok(since) => self.origin + since,
+            Err(_) => self.origin,
+        }
+    }
+
+    /// Seconds from the start of the [`TimeBase`] to the given [`Instant`], which is the x-axis
+    /// of the points in a [`ValueHistory`].
+    ///
+    /// [`TimeBase`]: TimeBase
+    /// [`Instant`]: Instant
+    /// [`ValueHistory`]: ValueHistory
+    pub fn seconds(&self, instant: Instant) -> f64 {
+        instant.saturating_duration_since(self.origin).as_secs_f64()
+    }
+
+    /// Seconds from the start of the [`TimeBase`] to now.
+    ///
+    /// [`TimeBase`]: TimeBase
+    pub fn now(&self) -> f64 {
+        self.seconds(Instant::now())
+    }
+}
+
+impl Default for TimeBase {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A history of numeric values, each stored as a [`PlotPoint`] of seconds since the history's
+/// [`TimeBase`] against the value, ready to be plotted.
+///
+/// Alongside the points the history keeps [`SUMMARY_LEVELS`] levels of [`Summary`]s, each of
+/// the lowest and highest of [`SUMMARY_FACTOR`] values of the level below, so that long spans can
+/// be decimated for plotting by [`ValueHistory::decimated`] without visiting every point.
+///
+/// [`PlotPoint`]: PlotPoint
+/// [`TimeBase`]: TimeBase
+/// [`SUMMARY_LEVELS`]: SUMMARY_LEVELS
+/// [`Summary`]: Summary
+/// [`SUMMARY_FACTOR`]: SUMMARY_FACTOR
+/// [`ValueHistory::decimated`]: ValueHistory::decimated
+#[derive(Debug, Clone)]
+pub struct ValueHistory {
+    base: TimeBase,
+    points: Ring<PlotPoint>,
+    /// Index of the oldest point kept, counting every point ever pushed.
+    first_index: u64,
+    /// Complete summaries of each level, the first level summarising points, and the index of
+    /// the oldest summary kept in each.
+    levels: [(Ring<Summary>, u64); SUMMARY_LEVELS],
+    /// The summary of each level still being built from the latest values.
+    partial: [Option<Summary>; SUMMARY_LEVELS],
+}
+
+/// The lowest and highest points of some consecutive points in a [`ValueHistory`].
+///
+/// [`ValueHistory`]: ValueHistory
+#[derive(Debug, Clone, Copy)]
+struct Summary {
+    min: PlotPoint,
+    max: PlotPoint,
+}
+
+impl Summary {
+    fn merge(summary: Option<Summary>, other: Summary) -> Summary {
+        let Some(mut summary) = summary else {
+            return other;
+        };
+
+        if other.min.y < summary.min.y {
+            summary.min = other.min;
+        }
+
+        if other.max.y > summary.max.y {
+            summary.max = other.max;
+        }
+
+        summary
+    }
+
+    /// The summarised points, oldest first, without repeating a point which is both lowest and
+    /// highest.
+    fn points(&self) -> impl Iterator<Item = PlotPoint> {
+        let (first, second) = match self.min.x <= self.max.x {
+            true => (self.min, self.max),
+            false => (self.max, self.min),
+        };
+
+        std::iter::once(first).chain((first != second).then_some(second))
+    }
+}
+
+impl ValueHistory {
+    /// Creates a new empty [`ValueHistory`] on the given [`TimeBase`].
+    ///
+    /// [`ValueHistory`]: ValueHistory
+    /// [`TimeBase`]: TimeBase
+    pub fn new(base: TimeBase) -> Self {
         ValueHistory {
-            history: Vec::new(),
+            base,
+            points: Ring::new(),
+            first_index: 0,
+            levels: Default::default(),
+            partial: [None; SUMMARY_LEVELS],
         }
     }
 
-    /// Pushes a new value onto the [`FieldHistory`], stamping it with the current time.
+    /// Pushes a new value onto the [`ValueHistory`], stamped with the given time, e.g. when the
+    /// stand took the reading. Values stamped before the newest value already in the history,
+    /// which estimated stand times may briefly be, are given the newest value's time so that the
+    /// history stays in order.
     ///
-    /// [`FieldHistory`]: FieldHistory
-    pub fn push(&mut self, value: T) {
-        self.history.push(HistoricalValue::from_now(value));
+    /// [`ValueHistory`]: ValueHistory
+    pub fn push(&mut self, value: f64, time: Instant) {
+        let mut x = self.base.seconds(time);
+        if let Some(newest) = self.points().last() {
+            x = x.max(newest.x);
+        }
+
+        let point = PlotPoint::new(x, value);
+        self.points.push(point);
+
+        // complete each level's summary once it covers its share of points
+        let count = self.first_index + self.points.len() as u64;
+        let mut summary = Summary {
+            min: point,
+            max: point,
+        };
+        let mut size = 1;
+
+        for (partial, (summaries, first_summary)) in self.partial.iter_mut().zip(&mut self.levels) {
+            size *= SUMMARY_FACTOR;
+            summary = Summary::merge(partial.take(), summary);
+
+            if !count.is_multiple_of(size) {
+                *partial = Some(summary);
+                break;
+            }
+
+            // a summary of values which have since been pruned is not kept
+            if count / size > *first_summary {
+                summaries.push(summary);
+            }
+        }
     }
 
-    /// Creates a [`Vec`] of "points", where the x-axis is the length of time between the present
-    /// moment and the time the value, which is the y-axis, was added to the [`ValueHistory`].
-    /// [`Duration`]s are of course always positive, but if you were to interperet the time of the
-    /// [`Duration`] as a delta-time it would be negative.
+    /// Every point in the [`ValueHistory`], oldest first, with x in seconds since its
+    /// [`TimeBase`] started.
     ///
-    /// [`Vec`]: Vec
+    /// [`ValueHistory`]: ValueHistory
+    /// [`TimeBase`]: TimeBase
+    pub fn points(&self) -> &[PlotPoint] {
+        self.points.as_slice()
+    }
+
+    /// The points in the [`ValueHistory`] from within the given [`Duration`] to now, oldest
+    /// first.
+    ///
+    /// [`ValueHistory`]: ValueHistory
     /// [`Duration`]: Duration
+    pub fn recent(&self, span: Duration) -> &[PlotPoint] {
+        let points = self.points();
+        let cutoff = self.base.now() - span.as_secs_f64();
+
+        &points[points.partition_point(|p| p.x < cutoff)..]
+    }
+
+    /// The points in the [`ValueHistory`] between the given times, in seconds since its
+    /// [`TimeBase`] started, along with the points either side so that a line through them
+    /// reaches the edges of a plot.
+    ///
+    /// If there are more than two points for each of the given number of buckets, e.g. one bucket
+    /// per pixel of the plot, the points are decimated to around that many runs of points, keeping
+    /// only the lowest and highest of each so that spikes still show. Otherwise the points are
+    /// borrowed at full resolution.
+    ///
     /// [`ValueHistory`]: ValueHistory
-    pub fn as_points(&self, span: Duration) -> Vec<(Duration, T)> {
-        let now = SystemTime::now();
-
-        self.history
-            .iter()
-            .map(|HistoricalValue { value, time }| {
-                (
-                    now.duration_since(*time)
-                        .expect("`time` will always be earlier than `now`"),
-                    value.to_owned(),
-                )
-            })
-            .filter(|&(t, _)| t <= span)
-            .collect()
-    }
-
-    /// Prune the [`ValueHistory`] to include only historical values form within the given
-    /// [`Duration`] to now.
+    /// [`TimeBase`]: TimeBase
+    pub fn decimated(&self, from: f64, to: f64, buckets: usize) -> Cow<'_, [PlotPoint]> {
+        let points = self.points();
+        let start = points.partition_point(|p| p.x < from).saturating_sub(1);
+        let end = (points.partition_point(|p| p.x <= to) + 1).min(points.len());
+
+        if end <= start || end - start <= 2 * buckets.max(1) {
+            return Cow::Borrowed(&points[start..end.max(start)]);
+        }
+
+        // the finest level with at most two summaries per bucket
+        let count = (end - start) as u64;
+        let mut size = SUMMARY_FACTOR;
+        let mut level = 0;
+        while level + 1 < SUMMARY_LEVELS && count / size > 2 * buckets as u64 {
+            size *= SUMMARY_FACTOR;
+            level += 1;
+        }
+
+        let mut decimated = Vec::with_capacity(4 * buckets + 2 * SUMMARY_FACTOR as usize);
+        self.extend_decimated(
+            Some(level),
+            self.first_index + start as u64,
+            self.first_index + end as u64,
+            &mut decimated,
+        );
+
+        Cow::Owned(decimated)
+    }
+
+    /// Extend the given points with those from the given index up to but excluding the other,
+    /// summarised at the given level wherever the level has a complete summary, and at finer
+    /// levels elsewhere. A level of [`None`] gives every point.
+    ///
+    /// [`None`]: None
+    fn extend_decimated(
+        &self,
+        level: Option<usize>,
+        start: u64,
+        end: u64,
+        decimated: &mut Vec<PlotPoint>,
+    ) {
+        let Some(level) = level else {
+            let first = (start - self.first_index) as usize;
+            let last = (end - self.first_index) as usize;
+            decimated.extend_from_slice(&self.points()[first..last]);
+            return;
+        };
+
+        let finer = level.checked_sub(1);
+        let size = SUMMARY_FACTOR.pow(level as u32 + 1);
+        let (summaries, first_summary) = &self.levels[level];
+        let last_summary = first_summary + summaries.len() as u64;
+
+        let from = start.div_ceil(size).max(*first_summary);
+        let to = (end / size).min(last_summary);
+
+        if from >= to {
+            self.extend_decimated(finer, start, end, decimated);
+            return;
+        }
+
+        self.extend_decimated(finer, start, from * size, decimated);
+
+        let summaries =
+            &summaries.as_slice()[(from - first_summary) as usize..(to - first_summary) as usize];
+        for summary in summaries {
+            decimated.extend(summary.points());
+        }
+
+        self.extend_decimated(finer, to * size, end, decimated);
+    }
+
+    /// Prune the [`ValueHistory`] to include only values from within the given [`Duration`] to
+    /// now. Summaries of any pruned values are pruned with them.
     ///
     /// [`ValueHistory`]: ValueHistory
     /// [`Duration`]: Duration
     pub fn prune(&mut self, span: Duration) {
-        let now = SystemTime::now();
-
-        self.history = self
-            .history
-            .iter()
-            .filter(|value| {
-                now.duration_since(value.time)
-                    .expect("`time` will always be earlier than `now`")
-                    <= span
-            })
-            .map(|x| x.to_owned())
-            .collect();
+        let pruned = self.points().len() - self.recent(span).len();
+        if pruned == 0 {
+            return;
+        }
+
+        self.points.drop_front(pruned);
+        self.first_index += pruned as u64;
+
+        let mut size = 1;
+        for (summaries, first_summary) in &mut self.levels {
+            size *= SUMMARY_FACTOR;
+
+            let first_kept = self.first_index.div_ceil(size);
+            let last = *first_summary + summaries.len() as u64;
+            let pruned = first_kept.min(last).saturating_sub(*first_summary);
+
+            summaries.drop_front(pruned as usize);
+            *first_summary += pruned;
+
+            // a level emptied of summaries starts again from the next one to be completed
+            if summaries.is_empty() {
+                *first_summary = first_kept;
+            }
+        }
     }
 }
 
+/// A ring buffer which writes every value twice, half a buffer apart, so that the values from
+/// oldest to newest are always one contiguous slice wherever the ring starts. Pushing is O(1)
+/// except when the buffer fills and doubles, and dropping values only moves the start.
 #[derive(Debug, Clone)]
-pub struct HistoricalValue<T> {
-    value: T,
-    time: SystemTime,
+struct Ring<T> {
+    /// Twice the capacity of the ring, the second half mirroring the first.
+    buffer: Vec<T>,
+    /// Index of the oldest value.
+    start: usize,
+    len: usize,
 }
 
-impl<T> HistoricalValue<T> {
-    pub fn from_now(value: T) -> Self {
-        HistoricalValue {
-            value,
-            time: SystemTime::now(),
+impl<T> Default for Ring<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Ring<T> {
+    fn new() -> Self {
+        Self {
+            buffer: Vec::new(),
+            start: 0,
+            len: 0,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn capacity(&self) -> usize {
+        self.buffer.len() / 2
+    }
+
+    fn as_slice(&self) -> &[T] {
+        &self.buffer[self.start..self.start + self.len]
+    }
+
+    /// Forget the given number of the oldest values.
+    fn drop_front(&mut self, count: usize) {
+        let count = count.min(self.len);
+        if count == 0 {
+            return;
+        }
+
+        self.start = (self.start + count) % self.capacity();
+        self.len -= count;
+    }
+}
+
+impl<T> Ring<T>
+where
+    T: Copy,
+{
+    fn push(&mut self, value: T) {
+        if self.len == self.capacity() {
+            self.grow(value);
+        }
+
+        let capacity = self.capacity();
+        let index = (self.start + self.len) % capacity;
+        self.buffer[index] = value;
+        self.buffer[index + capacity] = value;
+        self.len += 1;
+    }
+
+    /// Double the capacity of the ring, moving its values to the start and filling the rest with
+    /// the given value.
+    fn grow(&mut self, fill: T) {
+        let capacity = (self.capacity() * 2).max(INITIAL_CAPACITY);
+        let mut buffer = Vec::with_capacity(capacity * 2);
+
+        buffer.extend_from_slice(self.as_slice());
+        buffer.resize(capacity, fill);
+        buffer.extend_from_within(..capacity);
+
+        self.buffer = buffer;
+        self.start = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A [`TimeBase`] which started the given number of seconds ago, so that points can be
+    /// pushed at fixed offsets from its start without being in the future.
+    ///
+    /// [`TimeBase`]: TimeBase
+    fn base_started(ago: f64) -> TimeBase {
+        let ago = Duration::from_secs_f64(ago);
+        TimeBase {
+            origin: Instant::now() - ago,
+            origin_time: SystemTime::now() - ago,
+        }
+    }
+
+    /// A history of the given number of points, one a second, with pseudo-random values and
+    /// occasional spikes.
+    fn history(base: TimeBase, count: u64) -> ValueHistory {
+        let mut history = ValueHistory::new(base);
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+
+        for i in 0..count {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            let mut value = (state >> 40) as f64 / (1u64 << 24) as f64;
+            if i % 97 == 13 {
+                value += 50.0;
+            }
+
+            history.push(value, base.origin + Duration::from_secs(i));
+        }
+
+        history
+    }
+
+    /// Checks the decimated points are points of the history, in order, and that within each
+    /// run of `size` points aligned to the start of the history, the lowest and highest of the
+    /// decimated points match the lowest and highest of the history's points.
+    fn assert_decimated(history: &ValueHistory, from: f64, to: f64, buckets: usize, size: u64) {
+        let points = history.points();
+        let decimated = history.decimated(from, to, buckets);
+
+        assert!(decimated.len() < points.len());
+        assert!(decimated.windows(2).all(|w| w[0].x < w[1].x));
+        for point in decimated.iter() {
+            let index = points.partition_point(|p| p.x < point.x);
+            assert_eq!(points[index], *point);
+        }
+
+        let first_index = history.first_index;
+        let first_block = first_index
+            .div_ceil(size)
+            .max(from.ceil() as u64 / size + 1);
+        let last_block = (to.floor() as u64 + 1) / size;
+        assert!(first_block < last_block);
+
+        for block in first_block..last_block {
+            let block = &points[(block * size - first_index) as usize..][..size as usize];
+            let (from, to) = (block[0].x, block[block.len() - 1].x);
+            let kept: Vec<f64> = decimated
+                .iter()
+                .filter(|p| (from..=to).contains(&p.x))
+                .map(|p| p.y)
+                .collect();
+
+            let min = |ys: &mut dyn Iterator<Item = f64>| ys.fold(f64::INFINITY, f64::min);
+            let max = |ys: &mut dyn Iterator<Item = f64>| ys.fold(f64::NEG_INFINITY, f64::max);
+            assert_eq!(
+                min(&mut kept.iter().copied()),
+                min(&mut block.iter().map(|p| p.y))
+            );
+            assert_eq!(
+                max(&mut kept.iter().copied()),
+                max(&mut block.iter().map(|p| p.y))
+            );
+        }
+    }
+
+    #[test]
+    fn ring_wraps_and_grows() {
+        let mut ring = Ring::new();
+        let mut expected = Vec::new();
+
+        for i in 0..INITIAL_CAPACITY {
+            ring.push(i);
+            expected.push(i);
+        }
+        assert_eq!(ring.capacity(), INITIAL_CAPACITY);
+
+        // drop from the front and refill so that the ring wraps past the end of its buffer
+        ring.drop_front(100);
+        expected.drain(..100);
+        for i in INITIAL_CAPACITY..INITIAL_CAPACITY + 100 {
+            ring.push(i);
+            expected.push(i);
+        }
+        assert_eq!(ring.capacity(), INITIAL_CAPACITY);
+        assert!(ring.start + ring.len() > ring.capacity());
+        assert_eq!(ring.as_slice(), expected);
+
+        // pushing onto the full wrapped ring grows it with the values still in order
+        ring.push(1000);
+        expected.push(1000);
+        assert_eq!(ring.capacity(), 2 * INITIAL_CAPACITY);
+        assert_eq!(ring.start, 0);
+        assert_eq!(ring.as_slice(), expected);
+
+        ring.drop_front(usize::MAX);
+        assert!(ring.is_empty());
+        ring.push(1001);
+        assert_eq!(ring.as_slice(), [1001]);
+    }
+
+    #[test]
+    fn decimation_keeps_lowest_and_highest_of_each_run() {
+        let count = 4096;
+        let history = history(base_started(count as f64 + 10.0), count);
+
+        // 4096 points over 32 buckets is summarised at the second level, 64 points each, and
+        // 2502 points over 16 buckets at the third, 512 points each
+        assert_decimated(&history, 0.0, count as f64, 32, 64);
+        assert_decimated(&history, 1000.5, 3500.5, 16, 512);
+
+        // few enough points are given at full resolution, with one either side of the span
+        let points = history.points();
+        assert_eq!(*history.decimated(100.5, 110.5, 32), points[100..112]);
+    }
+
+    #[test]
+    fn prune_drops_summaries_of_pruned_points() {
+        let count = 5000;
+        let ago = count as f64 + 100.0;
+        let mut history = history(base_started(ago), count);
+
+        // keep points from 1234.5 seconds after the start, give or take the time the test takes
+        history.prune(Duration::from_secs_f64(ago - 1234.5));
+        assert_eq!(history.first_index, 1235);
+        assert_eq!(history.points()[0].x, 1235.0);
+        assert_eq!(history.points().len(), (count - 1235) as usize);
+
+        let mut size = 1;
+        for (summaries, first_summary) in &history.levels {
+            size *= SUMMARY_FACTOR;
+            assert!(first_summary * size >= history.first_index);
+            assert!(
+                summaries
+                    .as_slice()
+                    .iter()
+                    .all(|s| s.min.x >= 1235.0 && s.max.x >= 1235.0)
+            );
+        }
+
+        assert_decimated(&history, 0.0, count as f64, 32, 64);
+
+        // summaries completed after pruning are kept, and still match the points
+        let base = history.base;
+        for i in count..count + 1000 {
+            history.push(-(i as f64), base.origin + Duration::from_secs(i));
         }
+        assert_decimated(&history, 0.0, (count + 1000) as f64, 64, 64);
     }
 }
@@ -0,0 +1,516 @@
+use crate::serial::{SensorField, SensorValue};
+use std::{collections::HashMap, fmt::Display};
+
+/// Line the console sends to ask the stand to switch to binary frames.
+pub const PROTOCOL_REQUEST: &str = "PROTOCOL:BINARY";
+
+/// Text field the stand sends to agree to switch to binary frames, after which everything it sends
+/// is framed.
+pub const PROTOCOL_REPLY: &str = "PROTOCOL:u=1";
+
+/// Bytes every frame starts with.
+const SYNC: [u8; 2] = [0xA5, 0x5A];
+
+/// Bytes before a frame's payload: the sync bytes, length, and kind.
+const HEADER_LEN: usize = 5;
+
+/// Bytes after a frame's payload: the CRC.
+const CRC_LEN: usize = 2;
+
+/// Longest payload a frame may have, anything longer is taken as a corrupt length.
+const MAX_PAYLOAD_LEN: usize = 4096;
+
+const TABLE_FRAME: u8 = 0x01;
+const DATA_FRAME: u8 = 0x02;
+
+/// The protocol fields are exchanged with the stand in.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
+pub enum Protocol {
+    /// Newline separated `name:type=value` lines.
+    #[default]
+    Text,
+
+    /// Binary frames, see [`FrameDecoder`].
+    ///
+    /// [`FrameDecoder`]: FrameDecoder
+    Binary,
+}
+
+/// The fields of a single data frame, and when the stand sent it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DataFrame {
+    /// Microseconds since the stand started.
+    pub timestamp: u64,
+    pub fields: Vec<SensorField>,
+}
+
+/// Counts of frames decoded by a [`FrameDecoder`], and of each kind of error it has found.
+///
+/// [`FrameDecoder`]: FrameDecoder
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
+pub struct FrameStats {
+    /// Frames which passed their CRC.
+    pub frames: u64,
+
+    /// Frames which failed their CRC.
+    pub crc_errors: u64,
+
+    /// Frames with a length longer than any frame may be.
+    pub length_errors: u64,
+
+    /// Frames which passed their CRC but could not be parsed.
+    pub malformed: u64,
+
+    /// Data frames with a field ID not in the field table.
+    pub unknown_fields: u64,
+
+    /// Bytes thrown away while looking for the start of a frame.
+    pub skipped_bytes: u64,
+}
+
+/// Decodes [`DataFrame`]s from a stream of bytes, keeping the field table the stand has sent.
+/// Corrupt frames are skipped, resynchronizing on the next sync bytes, and counted in its
+/// [`FrameStats`].
+///
+/// Every frame looks like so, with all integers little endian:
+///
+/// `[0xA5 0x5A] [length: u16] [kind: u8] [payload: length bytes] [crc: u16]`
+///
+/// Where the CRC is CRC-16/CCITT-FALSE over the length, kind, and payload. A frame is one of two
+/// kinds:
+///
+/// - `0x01`, a field table, giving the name and type of the fields sent under each ID. Its payload
+///   is repeated `[id: u8] [type: u8] [name length: u8] [name]`, where the type is one of `u`,
+///   `i`, `f`, or `b` as in the text protocol. Entries replace any earlier entry with the same ID.
+/// - `0x02`, a data frame. Its payload is `[timestamp: u64]`, the microseconds since the stand
+///   started, followed by repeated `[id: u8] [value]`, where the value is 8 bytes for `u`, `i` and
+///   `f` fields and 1 byte for `b` fields.
+///
+/// The stand should resend its field table periodically so that the console may join mid-stream.
+///
+/// [`DataFrame`]: DataFrame
+/// [`FrameStats`]: FrameStats
+#[derive(Debug, Default)]
+pub struct FrameDecoder {
+    buf: Vec<u8>,
+    table: HashMap<u8, (String, u8)>,
+    stats: FrameStats,
+}
+
+impl FrameDecoder {
+    /// Gives the [`FrameStats`] of every byte pushed so far.
+    ///
+    /// [`FrameStats`]: FrameStats
+    pub fn stats(&self) -> FrameStats {
+        self.stats
+    }
+
+    /// Push the given bytes read from the stand, giving every [`DataFrame`] completed by them.
+    ///
+    /// [`DataFrame`]: DataFrame
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<DataFrame> {
+        self.buf.extend_from_slice(bytes);
+
+        let mut frames = Vec::new();
+
+        loop {
+            let start = self.buf.windows(SYNC.len()).position(|w| w == SYNC);
+
+            let Some(start) = start else {
+                // Keep a trailing first sync byte, which may be completed by the next push.
+                let keep = usize::from(self.buf.last() == Some(&SYNC[0]));
+                let skipped = self.buf.len() - keep;
+                self.stats.skipped_bytes += skipped as u64;
+                self.buf.drain(..skipped);
+                break;
+            };
+
+            self.stats.skipped_bytes += start as u64;
+            self.buf.drain(..start);
+
+            if self.buf.len() < HEADER_LEN {
+                break;
+            }
+
+            let len = u16::from_le_bytes([self.buf[2], self.buf[3]]) as usize;
+
+            if len > MAX_PAYLOAD_LEN {
+                self.stats.length_errors += 1;
+                self.skip_sync();
+                continue;
+            }
+
+            let frame_len = HEADER_LEN + len + CRC_LEN;
+
+            if self.buf.len() < frame_len {
+                break;
+            }
+
+            let crc = u16::from_le_bytes([self.buf[frame_len - 2], self.buf[frame_len - 1]]);
+
+            if crc16(&self.buf[SYNC.len()..frame_len - CRC_LEN]) != crc {
+                self.stats.crc_errors += 1;
+                self.skip_sync();
+                continue;
+            }
+
+            self.stats.frames += 1;
+
+            let frame: Vec<u8> = self.buf.drain(..frame_len).collect();
+            let kind = frame[4];
+            let payload = &frame[HEADER_LEN..frame_len - CRC_LEN];
+
+            match kind {
+                TABLE_FRAME => self.read_table(payload),
+                DATA_FRAME => frames.extend(self.read_data(payload)),
+                _ => self.stats.malformed += 1,
+            }
+        }
+
+        frames
+    }
+
+    /// Skip past the sync bytes at the start of the buffer, e.g. because they were not really the
+    /// start of a frame.
+    fn skip_sync(&mut self) {
+        self.stats.skipped_bytes += SYNC.len() as u64;
+        self.buf.drain(..SYNC.len());
+    }
+
+    fn read_table(&mut self, mut payload: &[u8]) {
+        while !payload.is_empty() {
+            let Some(&[id, value_type, name_len]) = payload.first_chunk() else {
+                self.stats.malformed += 1;
+                return;
+            };
+
+            let name = payload.get(3..3 + name_len as usize);
+
+            let Some(name) = name.and_then(|n| str::from_utf8(n).ok()) else {
+                self.stats.malformed += 1;
+                return;
+            };
+
+            if value_len(value_type).is_none() {
+                self.stats.malformed += 1;
+                return;
+            }
+
+            self.table.insert(id, (name.to_string(), value_type));
+            payload = &payload[3 + name_len as usize..];
+        }
+    }
+
+    fn read_data(&mut self, payload: &[u8]) -> Option<DataFrame> {
+        let Some((&timestamp, mut payload)) = payload.split_first_chunk::<8>() else {
+            self.stats.malformed += 1;
+            return None;
+        };
+
+        let mut frame = DataFrame {
+            timestamp: u64::from_le_bytes(timestamp),
+            fields: Vec::new(),
+        };
+
+        while let Some((&id, rest)) = payload.split_first() {
+            // Without the field's type the rest of the frame cannot be read.
+            let Some((name, value_type)) = self.table.get(&id) else {
+                self.stats.unknown_fields += 1;
+                break;
+            };
+
+            let len = value_len(*value_type).unwrap_or_default();
+
+            let Some(value) = rest.get(..len).map(|v| read_value(*value_type, v)) else {
+                self.stats.malformed += 1;
+                break;
+            };
+
+            frame.fields.push(SensorField::new(name.clone(), value));
+            payload = &rest[len..];
+        }
+
+        Some(frame)
+    }
+}
+
+/// Bytes taken by a value of the given type, or [`None`] if it is not a type.
+///
+/// [`None`]: None
+fn value_len(value_type: u8) -> Option<usize> {
+    match value_type {
+        b'u' | b'i' | b'f' => Some(8),
+        b'b' => Some(1),
+        _ => None,
+    }
+}
+
+/// Read a value of the given type, which must be one for which [`value_len`] gives the length of
+/// the given bytes.
+///
+/// [`value_len`]: value_len
+fn read_value(value_type: u8, bytes: &[u8]) -> SensorValue {
+    let word = || bytes.try_into().unwrap_or([0; 8]);
+
+    match value_type {
+        b'u' => SensorValue::UnsignedInt(u64::from_le_bytes(word())),
+        b'i' => SensorValue::SignedInt(i64::from_le_bytes(word())),
+        b'f' => SensorValue::Float(f64::from_le_bytes(word())),
+        _ => SensorValue::Boolean(bytes[0] != 0),
+    }
+}
+
+/// CRC-16/CCITT-FALSE of the given bytes.
+fn crc16(bytes: &[u8]) -> u16 {
+    let mut crc: u16 = 0xFFFF;
+
+    for &b in bytes {
+        crc ^= (b as u16) << 8;
+
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+
+    crc
+}
+
+/// Encodes fields into frames as the stand would, for simulating it. Assigns each field an ID the
+/// first time it is sent, sending a new field table whenever one is added.
+#[cfg(any(test, feature = "sim_io"))]
+#[derive(Debug, Default)]
+pub struct FrameEncoder {
+    ids: HashMap<String, (u8, u8)>,
+}
+
+#[cfg(any(test, feature = "sim_io"))]
+impl FrameEncoder {
+    /// Encode the given fields as a data frame with the given timestamp, preceded by a field
+    /// table if any are new. Fields beyond the 256 IDs available are not sent.
+    pub fn encode(&mut self, timestamp: u64, fields: &[(String, SensorValue)]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+
+        let new_fields = fields.iter().any(|(name, _)| !self.ids.contains_key(name));
+
+        for (name, value) in fields {
+            if !self.ids.contains_key(name) && self.ids.len() <= u8::MAX as usize {
+                self.ids
+                    .insert(name.clone(), (self.ids.len() as u8, value_type(*value)));
+            }
+        }
+
+        if new_fields {
+            bytes.extend(self.table());
+        }
+
+        let mut payload = timestamp.to_le_bytes().to_vec();
+
+        for (name, value) in fields {
+            let Some(&(id, _)) = self.ids.get(name) else {
+                continue;
+            };
+
+            payload.push(id);
+
+            match *value {
+                SensorValue::UnsignedInt(v) => payload.extend(v.to_le_bytes()),
+                SensorValue::SignedInt(v) => payload.extend(v.to_le_bytes()),
+                SensorValue::Float(v) => payload.extend(v.to_le_bytes()),
+                SensorValue::Boolean(v) => payload.push(v as u8),
+            }
+        }
+
+        bytes.extend(frame(DATA_FRAME, &payload));
+        bytes
+    }
+
+    /// Encode the full field table as a frame.
+    pub fn table(&self) -> Vec<u8> {
+        let mut payload = Vec::new();
+
+        for (name, &(id, value_type)) in &self.ids {
+            payload.extend([id, value_type, name.len() as u8]);
+            payload.extend(name.as_bytes());
+        }
+
+        frame(TABLE_FRAME, &payload)
+    }
+}
+
+#[cfg(any(test, feature = "sim_io"))]
+fn value_type(value: SensorValue) -> u8 {
+    match value {
+        SensorValue::UnsignedInt(_) => b'u',
+        SensorValue::SignedInt(_) => b'i',
+        SensorValue::Float(_) => b'f',
+        SensorValue::Boolean(_) => b'b',
+    }
+}
+
+/// Wrap the given payload in a frame of the given kind.
+#[cfg(any(test, feature = "sim_io"))]
+fn frame(kind: u8, payload: &[u8]) -> Vec<u8> {
+    let mut bytes = SYNC.to_vec();
+    bytes.extend((payload.len() as u16).to_le_bytes());
+    bytes.push(kind);
+    bytes.extend(payload);
+
+    let crc = crc16(&bytes[SYNC.len()..]);
+    bytes.extend(crc.to_le_bytes());
+    bytes
+}
+
+impl Display for Protocol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Protocol::Text => write!(f, "Text"),
+            Protocol::Binary => write!(f, "Binary"),
+        }
+    }
+}
+
+impl Display for FrameStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} frames, {} CRC errors, {} bad lengths, {} malformed, {} unknown fields, {} bytes skipped",
+            self.frames,
+            self.crc_errors,
+            self.length_errors,
+            self.malformed,
+            self.unknown_fields,
+            self.skipped_bytes
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The name and value of each of the given fields.
+    fn values(fields: &[SensorField]) -> Vec<(&str, SensorValue)> {
+        fields.iter().map(|f| (f.name.as_str(), f.value)).collect()
+    }
+
+    fn sample_fields() -> Vec<(String, SensorValue)> {
+        vec![
+            ("NPT1".to_string(), SensorValue::Float(421.5)),
+            ("Count".to_string(), SensorValue::UnsignedInt(7)),
+            ("Offset".to_string(), SensorValue::SignedInt(-3)),
+            ("NP2".to_string(), SensorValue::Boolean(true)),
+        ]
+    }
+
+    #[test]
+    fn crc_matches_ccitt_false() {
+        assert_eq!(crc16(b"123456789"), 0x29B1);
+    }
+
+    #[test]
+    fn round_trips_through_encoder() {
+        let mut encoder = FrameEncoder::default();
+        let mut decoder = FrameDecoder::default();
+        let fields = sample_fields();
+
+        let frames = decoder.push(&encoder.encode(1_500_000, &fields));
+
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].timestamp, 1_500_000);
+        assert_eq!(
+            values(&frames[0].fields),
+            fields
+                .iter()
+                .map(|(name, value)| (name.as_str(), *value))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            decoder.stats(),
+            FrameStats {
+                frames: 2,
+                ..FrameStats::default()
+            }
+        );
+    }
+
+    #[test]
+    fn flipped_bit_fails_crc_and_resyncs() {
+        let mut encoder = FrameEncoder::default();
+        let mut decoder = FrameDecoder::default();
+        decoder.push(&encoder.encode(0, &sample_fields()));
+
+        let mut corrupt = encoder.encode(1, &sample_fields());
+        corrupt[HEADER_LEN] ^= 0x10;
+        let good = encoder.encode(2, &sample_fields());
+
+        let frames = decoder.push(&[corrupt, good].concat());
+
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].timestamp, 2);
+        assert_eq!(decoder.stats().crc_errors, 1);
+        assert_eq!(decoder.stats().frames, 3);
+    }
+
+    #[test]
+    fn oversized_length_is_skipped() {
+        let mut encoder = FrameEncoder::default();
+        let mut decoder = FrameDecoder::default();
+
+        let mut bytes = SYNC.to_vec();
+        bytes.extend(u16::MAX.to_le_bytes());
+        bytes.extend([DATA_FRAME, 1, 2, 3]);
+        bytes.extend(encoder.encode(5, &sample_fields()));
+
+        let frames = decoder.push(&bytes);
+
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].timestamp, 5);
+        assert_eq!(decoder.stats().length_errors, 1);
+        assert_eq!(decoder.stats().crc_errors, 0);
+    }
+
+    #[test]
+    fn frame_split_across_pushes() {
+        let mut encoder = FrameEncoder::default();
+        let mut decoder = FrameDecoder::default();
+        let bytes = encoder.encode(9, &sample_fields());
+
+        let (last, rest) = bytes.split_last().unwrap();
+        for &b in rest {
+            assert!(decoder.push(&[b]).is_empty());
+        }
+
+        let frames = decoder.push(&[*last]);
+
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].fields.len(), sample_fields().len());
+        assert_eq!(decoder.stats().skipped_bytes, 0);
+    }
+
+    #[test]
+    fn unknown_field_id_ends_frame() {
+        let mut encoder = FrameEncoder::default();
+        let mut decoder = FrameDecoder::default();
+        decoder.push(&encoder.encode(0, &sample_fields()[..1]));
+
+        let mut payload = 3u64.to_le_bytes().to_vec();
+        payload.push(0);
+        payload.extend(12.5f64.to_le_bytes());
+        payload.push(200);
+        payload.extend(1u64.to_le_bytes());
+
+        let frames = decoder.push(&frame(DATA_FRAME, &payload));
+
+        assert_eq!(frames.len(), 1);
+        assert_eq!(
+            values(&frames[0].fields),
+            [("NPT1", SensorValue::Float(12.5))]
+        );
+        assert_eq!(decoder.stats().unknown_fields, 1);
+        assert_eq!(decoder.stats().malformed, 0);
+    }
+}
@@ -1,23 +1,54 @@
 use crate::{
+    calibration::Calibration,
+    clock::file_timestamp,
+    config::{StandConfig, ValveKind},
+    connection::{self, Connection},
     diagram::Diagram,
-    field_history::ValueHistory,
-    record::StandRecord,
-    sequence::{Command, CommandSequence, ValveHandle},
-    serial::{self, FieldReciever, SensorField, SensorValue},
-    stand::{StandMode, StandState},
+    event::{EventKind, StandEvent},
+    field_history::{TimeBase, ValueHistory},
+    frame::Protocol,
+    plot_layout::{MAX_HISTORY, PLOT_NAMES, PlotLayout},
+    script::{Param, ScriptLibrary},
+    sequence::{
+        Command, CommandSequence, MAX_WAIT, SequenceError, SequenceHandle, SequenceState,
+        ValveHandle,
+    },
+    serial::{self, FieldReciever, SensorField},
+    session::{PortMetadata, Session, SessionMetadata},
+    stand::{StandMode, StandState, ValveMismatch},
+    watchdog::WatchdogState,
 };
 use eframe::egui::{self, Color32};
+use serialport::FlowControl;
 use std::{
-    collections::HashMap,
-    hash::Hash,
-    sync::mpsc::SendError,
-    time::{Duration, SystemTime},
+    borrow::Cow,
+    collections::{BTreeMap, HashMap},
+    path::PathBuf,
+    time::{Duration, Instant, SystemTime},
 };
 
-const HISTORY_LENGTH: Duration = Duration::from_secs(60);
-
-/// Starts the graphical part of the app.
-pub fn start_gui(field_rx: FieldReciever) -> eframe::Result {
+/// Directory sessions are recorded under unless the operator enters another.
+const DEFAULT_SESSIONS_DIR: &str = "sessions";
+
+/// How long a session started automatically keeps recording after the stand is safed, so that
+/// the stand venting is recorded too.
+const RECORD_AFTER_SAFING: Duration = Duration::from_secs(10);
+
+/// Name of the sequence script run in place of any sequence the operator aborts.
+const ABORT_SEQUENCE: &str = "abort";
+
+/// Starts the graphical part of the app for the stand described by the given [`StandConfig`].
+///
+/// [`StandConfig`]: StandConfig
+pub fn start_gui(
+    config: StandConfig,
+    calibration: Option<Calibration>,
+    scripts: ScriptLibrary,
+    mut field_rx: FieldReciever,
+    connection: Connection,
+    plot_layout: PlotLayout,
+    plot_layout_path: PathBuf,
+) -> eframe::Result {
     let gui_options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
             .with_title("NILE Operator Console")
@@ -31,6 +62,14 @@ pub fn start_gui(field_rx: FieldReciever) -> eframe::Result {
     let diagram = Diagram::from_bytes(include_bytes!("../NILE P&ID.png"))
         .expect("Diagram should be valid image");
 
+    field_rx.commanded_valves_mut().settling_time = config.timing.valve_settling_time();
+
+    let selected_en = config
+        .valves_of_kind(ValveKind::Engine)
+        .chain(config.valves_of_kind(ValveKind::Timing))
+        .next()
+        .map(|v| ValveHandle::new(&v.name));
+
     eframe::run_native(
         "NILE Operator Console",
         gui_options,
@@ -40,13 +79,14 @@ pub fn start_gui(field_rx: FieldReciever) -> eframe::Result {
             Ok(Box::new(GuiApp {
                 serial_conn_has_died: false,
 
-                stand_state: StandState::default(),
+                stand_state: StandState::new(&config),
                 last_update_time: None,
                 stand_state_changed: true, // True so that stuff updates frame 1
+                valve_mismatches: Vec::new(),
 
                 ox_fail_popup: false,
 
-                selected_en: ValveHandle::Engine1,
+                selected_en,
                 fire_time_text: "0".to_string(),
                 fire_time: Duration::default(),
 
@@ -55,13 +95,32 @@ pub fn start_gui(field_rx: FieldReciever) -> eframe::Result {
                 target_ox_fuel_deviation: 0.5,
                 target_ox_fuel_deviation_text: "1.0".to_string(),
 
+                valve_settling_time_text: field_rx
+                    .commanded_valves()
+                    .settling_time
+                    .as_secs_f64()
+                    .to_string(),
+
                 field_reciever: field_rx,
+                connection,
+                time_base: TimeBase::new(),
                 field_histories: HashMap::new(),
+                plot_layout,
+                plot_layout_path,
 
                 diagram,
 
-                record_file_path: "Enter Path".to_string(),
-                record_file: None,
+                sessions_dir: DEFAULT_SESSIONS_DIR.to_string(),
+                test_id: String::new(),
+                operator: String::new(),
+                test_article: String::new(),
+                session: None,
+                stop_session_at: None,
+
+                config,
+                calibration,
+                scripts,
+                running_sequence: None,
             }))
         }),
     )
@@ -79,10 +138,14 @@ pub struct GuiApp {
     /// Whether or not the NILE test stand's state has changed in the last state update.
     stand_state_changed: bool,
 
+    /// Valves whose reported state disagrees with the state they were last commanded to.
+    valve_mismatches: Vec<ValveMismatch>,
+
     /// Whether or not to show the ox mode transition failure popup window.
     ox_fail_popup: bool,
 
-    selected_en: ValveHandle,
+    /// The engine or timing valve to open when firing, if the stand has any.
+    selected_en: Option<ValveHandle>,
     /// The text entered by the user for the duration of the engine burn.
     fire_time_text: String,
     /// The parsed time of the engine burn in the firing sequence.
@@ -93,18 +156,46 @@ pub struct GuiApp {
     target_ox_fuel_deviation: f32,
     target_ox_fuel_deviation_text: String,
 
+    /// The text entered by the user for the time valves have to settle before being flagged.
+    valve_settling_time_text: String,
+
     /// The I/O or simulation device from which we get field values and send commands.
     field_reciever: FieldReciever,
-    /// A history of field values used for
-    field_histories: HashMap<String, ValueHistory<SensorField>>,
+    /// Keeps the serial port connected, reopening it if lost.
+    connection: Connection,
+    /// The time base every field history and plot shares.
+    time_base: TimeBase,
+    /// A history of each field's values, for plotting.
+    field_histories: HashMap<String, ValueHistory>,
+    /// Which fields each plot shows, and over what window.
+    plot_layout: PlotLayout,
+    /// Where the plot layout is saved whenever the operator changes it.
+    plot_layout_path: PathBuf,
 
     /// The piping and instrumentation diagram which displays the valve states visually.
     diagram: Diagram,
 
-    /// CSV file path for saving/recording the history of all fields we get.
-    record_file_path: String,
-    /// The actual CSV record of fields.
-    record_file: Option<StandRecord>,
+    /// Directory new sessions are recorded under.
+    sessions_dir: String,
+    /// Details of the test entered by the operator, saved in each session's metadata.
+    test_id: String,
+    operator: String,
+    test_article: String,
+    /// The session currently recording, if any.
+    session: Option<Session>,
+    /// When to stop the current session, if it was started automatically and the stand has
+    /// since been safed.
+    stop_session_at: Option<Instant>,
+
+    /// Description of the stand's valves, sensors, and modes.
+    config: StandConfig,
+    /// Calibration applied to fields on the field thread, if any, recorded with each session.
+    calibration: Option<Calibration>,
+    /// Sequences which the operator may run, including those behind the Fire and Depressurize
+    /// buttons.
+    scripts: ScriptLibrary,
+    /// The name of and handle to the last sequence started, which may still be running.
+    running_sequence: Option<(String, SequenceHandle)>,
 }
 
 impl GuiApp {
@@ -117,25 +208,31 @@ impl GuiApp {
         match self.field_reciever.recieve_fields() {
             Ok(0) => return,
 
-            Ok(_) => {
-                self.last_update_time = Some(SystemTime::now());
-            }
+            Ok(_) => self.last_update_time = Some(SystemTime::now()),
 
             Err(_) => {
+                if !self.serial_conn_has_died {
+                    log::error!("Serial connection has died!");
+                }
+
                 self.serial_conn_has_died = true;
-                log::error!("Serial connection has died!");
             }
         }
     }
 
     /// Produces text with one line per sensor field showing each field's name and value.
     fn make_fields_table(&self) -> String {
-        let mut fields: Vec<(&String, &SensorValue)> = self.field_reciever.fields().collect();
-        fields.sort_unstable_by_key(|(k, _)| k.to_owned());
+        let mut fields: Vec<&SensorField> = self.field_reciever.fields().collect();
+        fields.sort_unstable_by_key(|f| f.name.to_owned());
 
         fields
             .into_iter()
-            .map(|(name, value)| format!("{name}: {value}"))
+            .map(
+                |SensorField { name, value, .. }| match self.config.unit(name) {
+                    Some(unit) => format!("{name}: {value} {unit}"),
+                    None => format!("{name}: {value}"),
+                },
+            )
             .fold(String::new(), |acc, s| format!("{acc}\n{s}"))
     }
 
@@ -143,42 +240,144 @@ impl GuiApp {
     ///
     /// [`GuiApp`]: GuiApp
     fn update_stand_state(&mut self) {
-        let fields: Vec<SensorField> = self
-            .field_reciever
-            .fields()
-            .map(|(name, &value)| SensorField {
-                name: name.clone(),
-                value,
-            })
-            .collect();
-
-        if let Some(record) = &mut self.record_file {
-            if let Err(e) = record.append_frame(&fields) {
-                log::error!("Failed to append record frame: {e}");
-            }
-        }
+        let fields: Vec<SensorField> = self.field_reciever.fields().cloned().collect();
 
         let old_state = self.stand_state.clone();
         self.stand_state.update(&fields);
-        self.stand_state_changed = old_state != self.stand_state;
 
-        for field in fields {
+        let old_mismatches = std::mem::take(&mut self.valve_mismatches);
+        self.valve_mismatches = self
+            .field_reciever
+            .commanded_valves()
+            .mismatches(&self.stand_state);
+
+        self.stand_state_changed =
+            old_state != self.stand_state || old_mismatches != self.valve_mismatches;
+
+        for field in self.field_reciever.recieved_fields() {
+            let time = self.time_base.instant(field.time);
+            let value = field.value.to_num();
+
             match self.field_histories.get_mut(&field.name) {
-                Some(hist) => {
-                    hist.push(field);
-                }
+                Some(hist) => hist.push(value, time),
 
                 None => {
-                    let mut hist = ValueHistory::new();
-                    let key = field.name.clone();
-                    hist.push(field);
-                    self.field_histories.insert(key, hist);
+                    let mut hist = ValueHistory::new(self.time_base);
+                    hist.push(value, time);
+                    self.field_histories.insert(field.name.clone(), hist);
                 }
             }
         }
 
+        let history_length = self.plot_layout.history();
         for (_, history) in self.field_histories.iter_mut() {
-            history.prune(HISTORY_LENGTH);
+            history.prune(history_length);
+        }
+    }
+
+    /// Enter [`StandMode::Safing`] once the field thread has safed the stand, e.g. because a
+    /// redline tripped. The field thread has already sent the commands which safe the stand, so
+    /// they are not sent again.
+    ///
+    /// [`StandMode::Safing`]: StandMode::Safing
+    fn handle_failsafe(&mut self) {
+        if let Some(reason) = self.field_reciever.take_failsafe() {
+            log::warn!("Stand was safed: {reason}");
+            self.transition_mode(StandMode::Safing);
+        }
+    }
+
+    /// Adds the controls for choosing, connecting to, and disconnecting from a serial port to the
+    /// given [`egui::Ui`].
+    ///
+    /// [`egui::Ui`]: egui::Ui
+    fn make_connection_panel(&mut self, ui: &mut egui::Ui) {
+        let connection = &mut self.connection;
+
+        ui.horizontal(|ui| {
+            let selected_text = connection
+                .selected_port
+                .and_then(|i| connection.ports.get(i))
+                .map(connection::describe_port)
+                .unwrap_or("Select Port".to_string());
+
+            egui::ComboBox::from_id_salt("Port")
+                .selected_text(selected_text)
+                .show_ui(ui, |ui| {
+                    for (i, port) in connection.ports.iter().enumerate() {
+                        ui.selectable_value(
+                            &mut connection.selected_port,
+                            Some(i),
+                            connection::describe_port(port),
+                        );
+                    }
+                });
+
+            if ui.button("Refresh").clicked() {
+                connection.refresh_ports();
+            }
+        });
+
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_label("Baud")
+                .selected_text(connection.settings.baud.to_string())
+                .show_ui(ui, |ui| {
+                    for baud in serial::COMMON_BAUD_RATES {
+                        ui.selectable_value(&mut connection.settings.baud, baud, baud.to_string());
+                    }
+                });
+
+            egui::ComboBox::from_label("Flow Control")
+                .selected_text(format!("{:?}", connection.settings.flow_control))
+                .show_ui(ui, |ui| {
+                    for flow_control in [
+                        FlowControl::None,
+                        FlowControl::Software,
+                        FlowControl::Hardware,
+                    ] {
+                        ui.selectable_value(
+                            &mut connection.settings.flow_control,
+                            flow_control,
+                            format!("{flow_control:?}"),
+                        );
+                    }
+                });
+
+            egui::ComboBox::from_label("Protocol")
+                .selected_text(connection.settings.protocol.to_string())
+                .show_ui(ui, |ui| {
+                    for protocol in [Protocol::Text, Protocol::Binary] {
+                        ui.selectable_value(
+                            &mut connection.settings.protocol,
+                            protocol,
+                            protocol.to_string(),
+                        );
+                    }
+                });
+
+            if ui.button("Connect").clicked()
+                && let Err(e) = connection.connect(&mut self.field_reciever)
+            {
+                log::error!("Could not open the selected port: {e}");
+            }
+
+            if self.field_reciever.is_connected() && ui.button("Disconnect").clicked() {
+                connection.disconnect(&mut self.field_reciever);
+            }
+        });
+
+        if self.field_reciever.is_connected() {
+            match self.field_reciever.protocol() {
+                Protocol::Text => ui.label("Recieving text fields"),
+                Protocol::Binary => ui.label(format!(
+                    "Recieving binary frames: {}",
+                    self.field_reciever.frame_stats()
+                )),
+            };
+
+            if let Some(clock) = self.field_reciever.clock_estimate() {
+                ui.label(format!("Stand clock: {clock}"));
+            }
         }
     }
 
@@ -223,100 +422,489 @@ impl GuiApp {
         );
     }
 
+    /// Append the given [`StandEvent`] to the record, if recording.
+    ///
+    /// [`StandEvent`]: StandEvent
+    fn log_event(&mut self, event: StandEvent) {
+        self.field_reciever.log_event(event);
+    }
+
+    /// Have the field thread cancel any running sequence, without running the abort sequence,
+    /// and safe the stand once it has stopped, logging the given reason in the record. The mode
+    /// changes to [`StandMode::Safing`] once the field thread reports the stand safed, or at once
+    /// if the field thread has died.
+    ///
+    /// [`StandMode::Safing`]: StandMode::Safing
+    fn failsafe(&mut self, reason: &str) {
+        if self.field_reciever.failsafe(reason).is_err() {
+            log::error!("Field thread has died, cannot safe the stand!");
+            self.serial_conn_has_died = true;
+            self.transition_mode(StandMode::Safing);
+        }
+    }
+
     /// Set the mode and perform setup behaviors.
     fn set_mode(&mut self, mode: StandMode) {
-        if mode == StandMode::Safing {
-            let seq = CommandSequence::new()
-                .then(Command::OpenValve(ValveHandle::NP3))
-                .then(Command::OpenValve(ValveHandle::IP3))
-                .then(Command::CloseValve(ValveHandle::NP1))
-                .then(Command::CloseValve(ValveHandle::NP2))
-                .then(Command::CloseValve(ValveHandle::NP4))
-                .then(Command::CloseValve(ValveHandle::IP1))
-                .then(Command::CloseValve(ValveHandle::IP2));
-
+        if let Some(seq) = mode_entry_sequence(&self.config, mode) {
             match self.field_reciever.run_sequence(seq) {
                 Ok(()) => (),
 
-                Err(SendError(_)) => {
+                Err(SequenceError::Send(_)) => {
                     self.serial_conn_has_died = true;
                 }
+
+                Err(e) => log::error!("Could not run mode entry sequence: {e}"),
             };
         }
 
-        if let Err(e) = self.stand_state.transition_mode(mode) {
-            if self.stand_state.mode() == StandMode::OxygenFilling
-                || mode == StandMode::OxygenFilling
+        self.transition_mode(mode);
+    }
+
+    /// Move the stand to the given mode, if it may be, without commanding anything. Redlines are
+    /// armed only in [`StandMode::PressurizationAndFiring`].
+    ///
+    /// [`StandMode::PressurizationAndFiring`]: StandMode::PressurizationAndFiring
+    fn transition_mode(&mut self, mode: StandMode) {
+        let old_mode = self.stand_state.mode();
+
+        match self.stand_state.transition_mode(mode, &self.config) {
+            Ok(()) => {
+                self.field_reciever
+                    .arm_redlines(mode == StandMode::PressurizationAndFiring);
+                self.update_session_for_mode(mode);
+                self.log_event(StandEvent::now(EventKind::ModeChanged(old_mode, mode)));
+            }
+
+            Err(e) => {
+                if old_mode == StandMode::OxygenFilling || mode == StandMode::OxygenFilling {
+                    self.handle_oxygen_filling_failure();
+                }
+
+                log::error!("Mode transition failed: {e}");
+                self.log_event(StandEvent::now(EventKind::ModeTransitionFailed(mode, e)));
+            }
+        }
+    }
+
+    /// Start recording a new session in the sessions directory, unless one is already recording.
+    /// Sessions started automatically are stopped [`RECORD_AFTER_SAFING`] after the stand is
+    /// safed.
+    ///
+    /// [`RECORD_AFTER_SAFING`]: RECORD_AFTER_SAFING
+    fn start_session(&mut self, automatic: bool) {
+        if self.session.is_some() {
+            return;
+        }
+
+        let metadata = SessionMetadata {
+            test_id: self.test_id.clone(),
+            operator: self.operator.clone(),
+            test_article: self.test_article.clone(),
+            engine: self.selected_en.as_ref().map(|en| en.name().to_string()),
+            fire_time: self.fire_time.as_secs_f64(),
+            target_ox_fuel_ratio: self.target_ox_fuel_ratio,
+            target_ox_fuel_deviation: self.target_ox_fuel_deviation,
+            console_version: env!("CARGO_PKG_VERSION").to_string(),
+            automatic,
+            started_at: String::new(),
+            stopped_at: None,
+            port: self
+                .connection
+                .target()
+                .zip(self.connection.target_settings())
+                .map(|(port, settings)| PortMetadata::new(port, settings)),
+            tares: self.field_reciever.tares().clone(),
+            calibration: self.calibration.clone(),
+        };
+
+        let started = Session::start(&self.sessions_dir, metadata).and_then(|session| {
+            self.field_reciever.start_recording(session.record_path())?;
+            Ok(session)
+        });
+
+        match started {
+            Ok(session) => {
+                self.session = Some(session);
+                self.stop_session_at = None;
+            }
+
+            Err(e) => log::error!(
+                "Failed to start session in {}! Not Recording! {e}",
+                self.sessions_dir
+            ),
+        }
+    }
+
+    /// Stop recording the current session, if any.
+    fn stop_session(&mut self) {
+        self.stop_session_at = None;
+        self.field_reciever.stop_recording();
+
+        if let Some(session) = self.session.take()
+            && let Err(e) = session.stop()
+        {
+            log::error!("Failed to write session metadata: {e}");
+        }
+    }
+
+    /// Start a session automatically when entering [`StandMode::PressurizationAndFiring`], and
+    /// schedule a session started that way to stop once the stand has been safed.
+    ///
+    /// [`StandMode::PressurizationAndFiring`]: StandMode::PressurizationAndFiring
+    fn update_session_for_mode(&mut self, mode: StandMode) {
+        match mode {
+            StandMode::PressurizationAndFiring => {
+                self.stop_session_at = None;
+                self.start_session(true);
+            }
+
+            StandMode::Safing
+                if self
+                    .session
+                    .as_ref()
+                    .is_some_and(|session| session.metadata().automatic) =>
             {
-                self.handle_oxygen_filling_failure();
+                self.stop_session_at = Some(Instant::now() + RECORD_AFTER_SAFING);
             }
 
-            log::error!("Mode transition failed: {e}");
+            _ => (),
+        }
+    }
+
+    /// The `$parameters` given to every sequence script, taken from the Fire controls.
+    fn script_params(&self) -> HashMap<&'static str, Param> {
+        let mut params = HashMap::from([("fire_time", Param::Duration(self.fire_time))]);
+
+        if let Some(engine) = &self.selected_en {
+            params.insert("engine", Param::Valve(engine.clone()));
+        }
+
+        if let Some(igniter) = self.config.valves_of_kind(ValveKind::Igniter).next() {
+            params.insert("igniter", Param::Valve(ValveHandle::new(&igniter.name)));
+        }
+
+        params
+    }
+
+    /// Validate the sequence script with the given name and build its [`CommandSequence`],
+    /// logging why if it cannot be run.
+    ///
+    /// [`CommandSequence`]: CommandSequence
+    fn script_sequence(&self, name: &str) -> Option<CommandSequence> {
+        self.scripts
+            .sequence(name, &self.config, &self.script_params())
+            .inspect_err(|e| log::error!("Could not run sequence '{name}': {e}"))
+            .ok()
+    }
+
+    /// Validate and run the sequence script with the given name in a new thread, unless another
+    /// sequence is already running. If the sequence is aborted, the [`ABORT_SEQUENCE`] is run in
+    /// its place.
+    ///
+    /// [`ABORT_SEQUENCE`]: ABORT_SEQUENCE
+    fn run_script(&mut self, name: &str) {
+        if let Some((running, handle)) = &self.running_sequence
+            && handle.state().is_active()
+        {
+            log::error!("Sequence '{running}' is already running, not running '{name}'!");
+            return;
+        }
+
+        let Some(seq) = self.script_sequence(name) else {
+            return;
+        };
+
+        let abort_sequence = match self.scripts.get(ABORT_SEQUENCE) {
+            Some(_) if name != ABORT_SEQUENCE => self.script_sequence(ABORT_SEQUENCE),
+            _ => None,
+        };
+
+        if !self.serial_conn_has_died {
+            log::info!("Running sequence '{name}'");
+            self.log_event(StandEvent::now(EventKind::SequenceStarted(
+                name.to_string(),
+            )));
+            let handle = self.field_reciever.run_sequence_par(seq, abort_sequence);
+            self.running_sequence = Some((name.to_string(), handle));
         }
     }
 
-    /// Adds an `egui` plot with the given dimensions to the [`egui::Ui`]. If the given width or
-    /// height is [`None`] then the plot will consume the available space. The plot will be filled
-    /// with field data from the stand.
+    /// Adds the progress of the running sequence, and controls to pause, resume, and abort it, to
+    /// the given [`egui::Ui`].
     ///
     /// [`egui::Ui`]: egui::Ui
-    fn make_fields_plot(
-        &mut self,
-        ui: &mut egui::Ui,
-        id: impl Hash,
-        height: Option<f32>,
-        width: Option<f32>,
-    ) {
-        let plot = egui_plot::Plot::new(id)
-            .legend(egui_plot::Legend::default())
-            .width(width.unwrap_or(ui.available_width()))
-            .height(height.unwrap_or(ui.available_height()));
-
-        plot.show(ui, |plot_ui| {
-            for (field_name, history) in
-                self.field_histories
-                    .iter_mut()
-                    .filter(|(k, _)| match k.as_str() {
-                        "NP1" | "NP2" | "NP3" | "NP4" | "IP1" | "IP2" | "IP3" => false,
-                        _ => true,
-                    })
-            {
-                let points: Vec<egui_plot::PlotPoint> = history
-                    .as_points(HISTORY_LENGTH)
-                    .into_iter()
-                    .map(|(dur, t)| egui_plot::PlotPoint::new(-dur.as_secs_f64(), t.value.to_num()))
-                    .collect();
-
-                plot_ui.line(egui_plot::Line::new(
-                    field_name,
-                    egui_plot::PlotPoints::Owned(points),
+    fn make_sequence_panel(&mut self, ui: &mut egui::Ui) {
+        let Some((name, handle)) = &self.running_sequence else {
+            return;
+        };
+
+        let state = handle.state();
+        let (step, steps) = handle.step();
+
+        ui.horizontal(|ui| {
+            if state.is_active() {
+                ui.label(format!(
+                    "Sequence '{name}' {state}: step {}/{steps}, {:.1}s remaining",
+                    step + 1,
+                    handle.time_remaining().as_secs_f64()
                 ));
+            } else {
+                ui.label(format!("Sequence '{name}' {state}"));
+            }
+
+            match state {
+                SequenceState::Running if ui.button("Pause").clicked() => handle.pause(),
+                SequenceState::Paused if ui.button("Resume").clicked() => handle.resume(),
+                _ => (),
+            }
+
+            if matches!(state, SequenceState::Running | SequenceState::Paused)
+                && ui
+                    .add(egui::Button::new("Abort").fill(Color32::from_rgb(182, 96, 96)))
+                    .clicked()
+            {
+                handle.abort();
+            }
+        });
+    }
+
+    /// Adds a menu of the loaded sequence scripts to the given [`egui::Ui`], along with a button
+    /// to reload them.
+    ///
+    /// [`egui::Ui`]: egui::Ui
+    fn make_sequences_menu(&mut self, ui: &mut egui::Ui) {
+        let mut to_run = None;
+
+        ui.menu_button("Sequences", |ui| {
+            for (name, script) in self.scripts.scripts() {
+                match script {
+                    Ok(_) => {
+                        if ui.button(name).clicked() {
+                            to_run = Some(name.to_string());
+                            ui.close();
+                        }
+                    }
+
+                    Err(e) => {
+                        ui.add_enabled(false, egui::Button::new(name))
+                            .on_disabled_hover_text(e.to_string());
+                    }
+                }
+            }
+
+            ui.separator();
+
+            if ui.button("Reload").clicked() {
+                if let Err(e) = self.scripts.reload(&self.config) {
+                    log::error!("Could not reload sequences: {e}");
+                }
+
+                ui.close();
             }
         });
+
+        if let Some(name) = to_run {
+            self.run_script(&name);
+        }
+    }
+
+    /// Adds a row to the given [`egui::Ui`] for each field the [`Calibration`] allows taring,
+    /// with buttons to tare it and clear its tare. Fields cannot be tared while firing.
+    ///
+    /// [`egui::Ui`]: egui::Ui
+    /// [`Calibration`]: Calibration
+    fn make_tare_controls(&self, ui: &mut egui::Ui) {
+        let Some(calibration) = &self.calibration else {
+            return;
+        };
+
+        let enabled = self.stand_state.mode() != StandMode::PressurizationAndFiring;
+
+        for name in calibration.tareable() {
+            ui.horizontal(|ui| {
+                if ui
+                    .add_enabled(enabled, egui::Button::new(format!("Tare {name}")))
+                    .clicked()
+                {
+                    self.field_reciever.tare(name);
+                }
+
+                if let Some(offset) = self.field_reciever.tares().get(name) {
+                    let unit = self.config.unit(name).unwrap_or_default();
+                    ui.label(format!("{offset:+.3} {unit}"));
+
+                    if ui
+                        .add_enabled(enabled, egui::Button::new("Clear"))
+                        .clicked()
+                    {
+                        self.field_reciever.clear_tare(name);
+                    }
+                }
+            });
+        }
+    }
+
+    /// Adds the plot with the given index into [`PLOT_NAMES`] to the given [`egui::Ui`], with
+    /// controls to pick its channels, window, and whether to group them by unit. Changes to the
+    /// [`PlotLayout`] are saved straight away.
+    ///
+    /// [`PLOT_NAMES`]: PLOT_NAMES
+    /// [`egui::Ui`]: egui::Ui
+    /// [`PlotLayout`]: PlotLayout
+    fn make_fields_plot(&mut self, ui: &mut egui::Ui, index: usize, height: Option<f32>) {
+        let height = height.unwrap_or(ui.available_height());
+
+        let mut available: Vec<String> = self
+            .config
+            .sensors
+            .iter()
+            .map(|s| s.name.clone())
+            .chain(self.config.derived.iter().map(|d| d.name.clone()))
+            .chain(self.field_histories.keys().cloned())
+            .filter(|name| self.config.valve(name).is_none())
+            .collect();
+        available.sort_unstable();
+        available.dedup();
+
+        let Some(plot) = self.plot_layout.plots.get_mut(index) else {
+            return;
+        };
+
+        let mut changed = false;
+        let controls = ui.horizontal(|ui| {
+            ui.menu_button("Channels", |ui| {
+                for name in &available {
+                    let mut shown = plot.channels.contains(name);
+
+                    if ui.checkbox(&mut shown, name).changed() {
+                        plot.set_shown(name, shown);
+                        changed = true;
+                    }
+                }
+            });
+
+            changed |= ui
+                .checkbox(&mut plot.group_by_unit, "Group by unit")
+                .changed();
+
+            changed |= ui
+                .add(
+                    egui::DragValue::new(&mut plot.window)
+                        .range(1.0..=self.plot_layout.history)
+                        .suffix("s"),
+                )
+                .changed();
+        });
+
+        // one group per unit, or a single group of every channel
+        let mut groups: BTreeMap<Option<&str>, Vec<&String>> = BTreeMap::new();
+        for channel in &plot.channels {
+            let unit = match plot.group_by_unit {
+                true => self.config.unit(channel),
+                false => None,
+            };
+
+            groups.entry(unit).or_default().push(channel);
+        }
+
+        let plot_height = (height - controls.response.rect.height()) / groups.len().max(1) as f32
+            - ui.spacing().item_spacing.y;
+        let link_id = ui.id().with(("plot link", index));
+        let now = self.time_base.now();
+
+        for (i, (unit, channels)) in groups.iter().enumerate() {
+            let mut plot_ui = egui_plot::Plot::new((PLOT_NAMES[index], i))
+                .legend(egui_plot::Legend::default())
+                .width(ui.available_width())
+                .height(plot_height.max(32.0))
+                .include_x(now - plot.window)
+                .include_x(now)
+                .x_axis_formatter(move |mark, _| {
+                    let decimals = (-mark.step_size.log10()).ceil().max(0.0) as usize;
+                    format!("{:.*}", decimals, mark.value - now)
+                })
+                .label_formatter(move |name, point| {
+                    format!("{name}\n{:.3} s\n{:.3}", point.x - now, point.y)
+                })
+                .link_axis(link_id, [true, false])
+                .link_cursor(link_id, [true, false]);
+
+            if let Some(unit) = unit {
+                plot_ui = plot_ui.y_axis_label(*unit);
+            }
+
+            plot_ui.show(ui, |plot_ui| {
+                // follow the window unless the operator has zoomed or dragged the plot
+                let (from, to) = match plot_ui.auto_bounds().x {
+                    true => (now - plot.window, now),
+                    false => {
+                        let bounds = plot_ui.plot_bounds();
+                        (bounds.min()[0], bounds.max()[0])
+                    }
+                };
+                let buckets = plot_ui.transform().frame().width().max(1.0) as usize;
+
+                for &channel in channels {
+                    let Some(history) = self.field_histories.get(channel) else {
+                        continue;
+                    };
+
+                    let points = match history.decimated(from, to, buckets) {
+                        Cow::Borrowed(points) => egui_plot::PlotPoints::Borrowed(points),
+                        Cow::Owned(points) => egui_plot::PlotPoints::Owned(points),
+                    };
+
+                    let legend = match self.config.unit(channel) {
+                        Some(unit) => format!("{channel} ({unit})"),
+                        None => channel.clone(),
+                    };
+
+                    plot_ui.line(egui_plot::Line::new(legend, points));
+                }
+            });
+        }
+
+        if groups.is_empty() {
+            ui.allocate_space(egui::vec2(ui.available_width(), plot_height.max(0.0)));
+        }
+
+        if changed && let Err(e) = self.plot_layout.save(&self.plot_layout_path) {
+            log::error!("{e}");
+        }
     }
 }
 
 impl eframe::App for GuiApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        if self.serial_conn_has_died || ctx.input(|i| i.viewport().close_requested()) {
-            // unfortunately this doesn't close stuff on its own, and the thread which hosts the
-            // window must exit, meaning we cant do a nice connection retry thing.
+        if ctx.input(|i| i.viewport().close_requested()) {
             ctx.send_viewport_cmd(egui::ViewportCommand::Close);
             return;
         } else {
             self.recieve_fields();
+            self.handle_failsafe();
+            self.connection.update(&mut self.field_reciever);
             ctx.request_repaint();
         }
 
         self.update_stand_state();
 
+        // the field thread stops recording by itself if the record cannot be written
+        if self
+            .stop_session_at
+            .is_some_and(|stop_at| Instant::now() >= stop_at)
+            || (self.session.is_some() && self.field_reciever.record_path().is_none())
+        {
+            self.stop_session();
+        }
+
         if self.ox_fail_popup {
             self.show_oxygen_filling_failure_popup(ctx);
         }
 
         if self.stand_state_changed {
             self.diagram.reset_image();
-            self.diagram.plot_valves(self.stand_state);
+            self.diagram
+                .plot_valves(&self.config, &self.stand_state, &self.valve_mismatches);
             self.diagram.reload_texture(ctx);
         }
 
@@ -336,40 +924,74 @@ impl eframe::App for GuiApp {
                         },
 
                         None => ui.label("NILE Stand Telemetry (No Data)"),
+                    };
+
+                    if !self.field_reciever.is_connected() {
+                        ui.colored_label(
+                            Color32::from_rgb(255, 96, 96),
+                            match self.connection.target() {
+                                Some(port) => {
+                                    format!("Connection lost! Reconnecting to {}", port.port_name)
+                                }
+                                None => "Not connected".to_string(),
+                            },
+                        );
+                    }
+
+                    match self.field_reciever.watchdog_state() {
+                        WatchdogState::Nominal => (),
+
+                        WatchdogState::Warning(staleness) => {
+                            ui.colored_label(
+                                Color32::from_rgb(255, 160, 0),
+                                format!(
+                                    "Telemetry stale! Safing in {:.1}s",
+                                    self.config
+                                        .timing
+                                        .watchdog_safe_after()
+                                        .saturating_sub(staleness)
+                                        .as_secs_f64()
+                                ),
+                            );
+                        }
+
+                        WatchdogState::Tripped { .. } => {
+                            ui.horizontal(|ui| {
+                                ui.colored_label(
+                                    Color32::from_rgb(255, 96, 96),
+                                    "Telemetry was lost, stand has been safed!",
+                                );
+
+                                if ui.button("Reset Watchdog").clicked() {
+                                    self.field_reciever.reset_watchdog();
+                                }
+                            });
+                        }
                     }
                 });
 
+                egui::CollapsingHeader::new("Connection")
+                    .default_open(!self.field_reciever.is_connected())
+                    .show(right, |ui| self.make_connection_panel(ui));
+
                 right.horizontal_wrapped(|ui| {
                     ui.label("Stand Mode: ");
 
                     ui.centered_and_justified(|ui| {
                         ui.menu_button(self.stand_state.mode().to_string(), |ui| {
-                            if ui.button(StandMode::CheckOut.to_string()).clicked() {
-                                self.set_mode(StandMode::CheckOut);
-                                ui.close();
-                            }
-
-                            if ui.button(StandMode::OxygenFilling.to_string()).clicked() {
-                                self.set_mode(StandMode::OxygenFilling);
-                                ui.close();
-                            }
-
-                            if ui
-                                .button(StandMode::PressurizationAndFiring.to_string())
-                                .clicked()
-                            {
-                                self.set_mode(StandMode::PressurizationAndFiring);
-                                ui.close();
-                            }
-
-                            if ui.button(StandMode::Safing.to_string()).clicked() {
-                                self.set_mode(StandMode::Safing);
-                                ui.close();
+                            for mode in StandMode::ALL {
+                                if ui.button(mode.to_string()).clicked() {
+                                    self.set_mode(mode);
+                                    ui.close();
+                                }
                             }
                         })
                     })
                 });
 
+                right.horizontal(|ui| self.make_sequences_menu(ui));
+                self.make_sequence_panel(right);
+
                 right.vertical(|ui| {
                     ui.columns_const(|[left, right]| {
                         egui::ScrollArea::both()
@@ -379,6 +1001,7 @@ impl eframe::App for GuiApp {
                                     Some(egui::TextStyle::Monospace);
                                 ui.label(self.make_fields_table());
                             });
+                        self.make_tare_controls(left);
 
                         right.label("Target Ox/Fuel Ratio:");
                         right.text_edit_singleline(&mut self.target_ox_fuel_ratio_text);
@@ -395,17 +1018,34 @@ impl eframe::App for GuiApp {
                                 Err(_) => self.target_ox_fuel_deviation,
                             };
 
+                        right.label("Plot History (Seconds):");
+                        let mut history = self.plot_layout.history;
+                        if right
+                            .add(egui::DragValue::new(&mut history).range(1.0..=MAX_HISTORY))
+                            .changed()
+                        {
+                            self.plot_layout.set_history(history);
+
+                            if let Err(e) = self.plot_layout.save(&self.plot_layout_path) {
+                                log::error!("{e}");
+                            }
+                        }
+
+                        right.label("Valve Settling Time (Seconds):");
+                        right.text_edit_singleline(&mut self.valve_settling_time_text);
+                        if let Ok(t) = self.valve_settling_time_text.parse()
+                            && let Ok(t) = Duration::try_from_secs_f64(t)
+                        {
+                            self.field_reciever.commanded_valves_mut().settling_time = t;
+                        }
+
                         right.style_mut().visuals.code_bg_color =
                             match self.field_histories.get("Ox/Fuel Ratio") {
                                 Some(hist) => {
-                                    let points: Vec<f64> = hist
-                                        .as_points(Duration::from_secs(3))
-                                        .iter()
-                                        .map(|(_, val)| val.value.to_num())
-                                        .collect();
-                                    let window =
-                                        &points[points.len().saturating_sub(4)..points.len()];
-                                    let ratio = window.iter().sum::<f64>() / window.len() as f64;
+                                    let points = hist.recent(Duration::from_secs(3));
+                                    let window = &points[points.len().saturating_sub(4)..];
+                                    let ratio = window.iter().map(|p| p.y).sum::<f64>()
+                                        / window.len() as f64;
                                     ox_fuel_color(
                                         self.target_ox_fuel_ratio,
                                         self.target_ox_fuel_deviation,
@@ -419,35 +1059,126 @@ impl eframe::App for GuiApp {
                     });
                 });
 
+                if !self.valve_mismatches.is_empty() {
+                    right.vertical(|ui| {
+                        ui.label("Valve Alarms:");
+
+                        for mismatch in &self.valve_mismatches {
+                            ui.colored_label(Color32::from_rgb(255, 0, 255), mismatch.to_string());
+                        }
+                    });
+                }
+
+                if !self.field_reciever.redline_trips().is_empty() {
+                    right.vertical(|ui| {
+                        ui.horizontal(|ui| {
+                            ui.label("Redline Trips:");
+
+                            if ui.button("Clear").clicked() {
+                                self.field_reciever.clear_redline_trips();
+                            }
+                        });
+
+                        for trip in self.field_reciever.redline_trips() {
+                            ui.colored_label(Color32::from_rgb(255, 96, 96), trip.to_string());
+                        }
+                    });
+                }
+
+                if !self.field_reciever.command_failures().is_empty() {
+                    right.vertical(|ui| {
+                        ui.horizontal(|ui| {
+                            ui.label("Command Failures:");
+
+                            if ui.button("Clear").clicked() {
+                                self.field_reciever.clear_command_failures();
+                            }
+                        });
+
+                        egui::ScrollArea::vertical()
+                            .id_salt("Command Failures")
+                            .max_height(64.0)
+                            .show(ui, |ui| {
+                                for failure in self.field_reciever.command_failures() {
+                                    ui.colored_label(
+                                        Color32::from_rgb(255, 96, 96),
+                                        failure.to_string(),
+                                    );
+                                }
+                            });
+                    });
+                }
+
                 right.vertical(|ui| {
-                    ui.label("Record Fields To:");
-                    ui.text_edit_singleline(&mut self.record_file_path);
+                    ui.label("Record Sessions To:");
+                    ui.text_edit_singleline(&mut self.sessions_dir);
+
+                    egui::Grid::new("Session Details").show(ui, |ui| {
+                        ui.label("Test ID:");
+                        ui.text_edit_singleline(&mut self.test_id);
+                        ui.end_row();
+
+                        ui.label("Operator:");
+                        ui.text_edit_singleline(&mut self.operator);
+                        ui.end_row();
+
+                        ui.label("Test Article:");
+                        ui.text_edit_singleline(&mut self.test_article);
+                        ui.end_row();
+                    });
+
+                    // handle start/stop of session
+                    match &self.session {
+                        Some(session) => {
+                            ui.label(format!("Recording to {}", session.dir().display()));
+
+                            if let Some(stop_at) = self.stop_session_at {
+                                ui.label(format!(
+                                    "Stopping in {:.0}s",
+                                    stop_at
+                                        .saturating_duration_since(Instant::now())
+                                        .as_secs_f64()
+                                ));
+                            }
 
-                    // handle open/close of record
-                    match self.record_file {
-                        Some(_) => {
                             if ui.button("Stop Recording").clicked() {
-                                self.record_file = None;
+                                self.stop_session();
                             }
                         }
 
                         None => {
+                            ui.label(format!(
+                                "Recording starts on entering {}",
+                                StandMode::PressurizationAndFiring
+                            ));
+
                             if ui.button("Start Recording").clicked() {
-                                let names: Vec<String> = self
-                                    .field_reciever
-                                    .fields()
-                                    .map(|(k, _)| k.to_owned())
-                                    .collect();
-
-                                if let Ok(record) =
-                                    StandRecord::open(self.record_file_path.as_str(), names)
-                                {
-                                    self.record_file = Some(record);
-                                } else {
-                                    log::error!(
-                                        "Failed to open record file at {}! Not Recording!",
-                                        self.record_file_path
-                                    );
+                                self.start_session(false);
+                            }
+                        }
+                    };
+
+                    ui.separator();
+
+                    // handle start/stop of raw capture
+                    match self.field_reciever.capture_path() {
+                        Some(path) => {
+                            ui.label(format!("Capturing to {}", path.display()));
+
+                            if ui.button("Stop Capture").clicked() {
+                                self.field_reciever.stop_capture();
+                            }
+                        }
+
+                        None => {
+                            if ui.button("Start Capture").clicked() {
+                                let path = format!(
+                                    "capture_{}.nilecap",
+                                    file_timestamp(SystemTime::now())
+                                );
+
+                                if let Err(e) = self.field_reciever.start_capture(&path) {
+                                    log::error!("Failed to start capture at {path}: {e}");
                                 }
                             }
                         }
@@ -455,16 +1186,11 @@ impl eframe::App for GuiApp {
                 });
 
                 right.vertical(|ui| {
-                    self.make_fields_plot(
-                        ui,
-                        "upper".to_string(),
-                        Some(ui.available_height() / 2.1),
-                        None,
-                    );
+                    self.make_fields_plot(ui, 0, Some(ui.available_height() / 2.1));
 
                     ui.columns_const(|[left, right]| {
-                        self.make_fields_plot(left, "left".to_string(), None, None);
-                        self.make_fields_plot(right, "right".to_string(), None, None);
+                        self.make_fields_plot(left, 1, None);
+                        self.make_fields_plot(right, 2, None);
                     });
                 });
 
@@ -485,7 +1211,13 @@ impl eframe::App for GuiApp {
                 }
 
                 egui::TopBottomPanel::bottom("Controls Panel").show_inside(left, |ui| {
-                    for valve in self.stand_state.mode().manual_control_valves() {
+                    let manual_valves = self
+                        .stand_state
+                        .mode()
+                        .manual_control_valves(&self.config)
+                        .to_vec();
+
+                    for valve in manual_valves {
                         ui.horizontal(|ui| {
                             ui.columns_const(|[left, right]| {
                                 left.centered_and_justified(|ui| {
@@ -496,7 +1228,7 @@ impl eframe::App for GuiApp {
 
                                     if res.clicked() {
                                         self.field_reciever
-                                            .send_command(serial::ValveCommand::Open(valve))
+                                            .send_command(serial::ValveCommand::Open(valve.clone()))
                                             .expect("Expected to be able to send command");
                                     }
                                 });
@@ -509,7 +1241,9 @@ impl eframe::App for GuiApp {
 
                                     if res.clicked() {
                                         self.field_reciever
-                                            .send_command(serial::ValveCommand::Close(valve))
+                                            .send_command(serial::ValveCommand::Close(
+                                                valve.clone(),
+                                            ))
                                             .expect("Expected to be able to send command");
                                     }
                                 });
@@ -522,27 +1256,7 @@ impl eframe::App for GuiApp {
                             ui.horizontal_wrapped(|ui| {
                                 ui.centered_and_justified(|ui| {
                                     if ui.button("Depressurize System").clicked() {
-                                        let seq = CommandSequence::new()
-                                            .then(Command::OpenValve(ValveHandle::NP3))
-                                            .then(Command::OpenValve(ValveHandle::IP3))
-                                            .then(Command::Wait(Duration::from_secs(1)))
-                                            .then(Command::OpenValve(ValveHandle::NP4))
-                                            .then(Command::Wait(Duration::from_secs(5)))
-                                            .then(Command::CloseValve(ValveHandle::NP4))
-                                            .then(Command::Wait(Duration::from_secs(1)))
-                                            .then(Command::OpenValve(ValveHandle::IP2))
-                                            .then(Command::Wait(Duration::from_secs(5)))
-                                            .then(Command::CloseValve(ValveHandle::IP2))
-                                            .then(Command::Wait(Duration::from_secs(1)))
-                                            .then(Command::OpenValve(ValveHandle::NP2))
-                                            .then(Command::Wait(Duration::from_secs(5)))
-                                            .then(Command::CloseValve(ValveHandle::NP2))
-                                            .then(Command::Wait(Duration::from_secs(1)))
-                                            .then(Command::Done);
-
-                                        if !self.serial_conn_has_died {
-                                            self.field_reciever.run_sequence_par(seq);
-                                        }
+                                        self.run_script("depressurize");
                                     }
                                 });
                             });
@@ -554,31 +1268,29 @@ impl eframe::App for GuiApp {
                                 let fire_time_text_res =
                                     ui.text_edit_singleline(&mut self.fire_time_text);
 
-                                if let Ok(t) = self.fire_time_text.parse() {
-                                    self.fire_time = Duration::from_secs_f64(t);
+                                if let Ok(t) = self.fire_time_text.parse()
+                                    && let Ok(t) = Duration::try_from_secs_f64(t)
+                                    && t <= MAX_WAIT
+                                {
+                                    self.fire_time = t;
                                 } else if fire_time_text_res.lost_focus() {
                                     self.fire_time_text = "0".to_string();
                                 }
 
-                                ui.menu_button(self.selected_en.to_string(), |ui| {
-                                    if ui.button(ValveHandle::Engine1.to_string()).clicked() {
-                                        self.selected_en = ValveHandle::Engine1;
-                                    }
-
-                                    if ui.button(ValveHandle::Engine2.to_string()).clicked() {
-                                        self.selected_en = ValveHandle::Engine2;
-                                    }
-
-                                    if ui.button(ValveHandle::Engine3.to_string()).clicked() {
-                                        self.selected_en = ValveHandle::Engine3;
-                                    }
-
-                                    if ui.button(ValveHandle::TimingOx.to_string()).clicked() {
-                                        self.selected_en = ValveHandle::TimingOx;
-                                    }
-
-                                    if ui.button(ValveHandle::TimingFuel.to_string()).clicked() {
-                                        self.selected_en = ValveHandle::TimingFuel;
+                                let selected_text = match &self.selected_en {
+                                    Some(valve) => valve.to_string(),
+                                    None => "No Engine".to_string(),
+                                };
+
+                                ui.menu_button(selected_text, |ui| {
+                                    for valve in self
+                                        .config
+                                        .valves_of_kind(ValveKind::Engine)
+                                        .chain(self.config.valves_of_kind(ValveKind::Timing))
+                                    {
+                                        if ui.button(&valve.name).clicked() {
+                                            self.selected_en = Some(ValveHandle::new(&valve.name));
+                                        }
                                     }
                                 });
 
@@ -589,34 +1301,19 @@ impl eframe::App for GuiApp {
                                             .min_size(ui.available_size()),
                                     )
                                     .clicked()
+                                    && let Some(selected_en) = self.selected_en.clone()
                                 {
-                                    if self.selected_en == ValveHandle::TimingFuel {
-                                        self.field_reciever
-                                            .send_command(serial::ValveCommand::Open("TMI"))
-                                            .expect("Expected to be able to send command");
-                                    } else if self.selected_en == ValveHandle::TimingOx {
+                                    let kind =
+                                        self.config.valve(selected_en.name()).map(|v| v.kind);
+
+                                    if kind == Some(ValveKind::Timing) {
                                         self.field_reciever
-                                            .send_command(serial::ValveCommand::Open("TMN"))
+                                            .send_command(serial::ValveCommand::Open(
+                                                selected_en.name().to_string(),
+                                            ))
                                             .expect("Expected to be able to send command");
                                     } else {
-                                        let wait_time = Duration::from_millis(500);
-                                        let seq = CommandSequence::new()
-                                            .then(Command::OpenValve(ValveHandle::Match))
-                                            .then(Command::Wait(wait_time))
-                                            .then(Command::OpenValve(self.selected_en))
-                                            .then(Command::Wait(self.fire_time))
-                                            .then(Command::Wait(Duration::from_secs(3)))
-                                            .then(Command::CloseValve(ValveHandle::NP2))
-                                            .then(Command::CloseValve(ValveHandle::IP2))
-                                            .then(Command::OpenValve(ValveHandle::NP3))
-                                            .then(Command::OpenValve(ValveHandle::IP3))
-                                            .then(Command::Wait(Duration::from_secs(2)))
-                                            .then(Command::CloseValve(self.selected_en))
-                                            .then(Command::Done);
-
-                                        if !self.serial_conn_has_died {
-                                            self.field_reciever.run_sequence_par(seq);
-                                        }
+                                        self.run_script("fire");
                                     }
                                 }
                             });
@@ -635,7 +1332,7 @@ impl eframe::App for GuiApp {
                                 )
                                 .clicked()
                             {
-                                self.set_mode(StandMode::Safing);
+                                self.failsafe("operator");
                             }
                         });
                     });
@@ -645,6 +1342,34 @@ impl eframe::App for GuiApp {
     }
 }
 
+/// Builds the [`CommandSequence`] which commands the valves the [`StandConfig`] gives for entering
+/// the given [`StandMode`], or [`None`] if there are none.
+///
+/// [`CommandSequence`]: CommandSequence
+/// [`StandConfig`]: StandConfig
+/// [`StandMode`]: StandMode
+fn mode_entry_sequence(config: &StandConfig, mode: StandMode) -> Option<CommandSequence> {
+    let mode_config = config.mode(mode);
+
+    if mode_config.open_on_entry.is_empty() && mode_config.close_on_entry.is_empty() {
+        return None;
+    }
+
+    let seq = mode_config
+        .open_on_entry
+        .iter()
+        .map(|v| Command::OpenValve(ValveHandle::new(v)))
+        .chain(
+            mode_config
+                .close_on_entry
+                .iter()
+                .map(|v| Command::CloseValve(ValveHandle::new(v))),
+        )
+        .fold(CommandSequence::new(), CommandSequence::then);
+
+    Some(seq)
+}
+
 /// Computes the color of the "Ox/Fuel" label which is used to indicate a good/not good state of the
 /// Ox/Fuel ratio.
 fn ox_fuel_color(target: f32, deviation: f32, ratio: f32) -> Color32 {
@@ -1,25 +1,57 @@
 #![feature(ascii_char)]
 #![feature(iterator_try_collect)]
 
-use serialport::SerialPort;
+use crate::{
+    calibration::{Calibration, Calibrator},
+    config::StandConfig,
+    connection::Connection,
+    derived::DerivedFields,
+    plot_layout::{PlotLayout, PlotLayoutError},
+    safety::SafetyConfig,
+    script::ScriptLibrary,
+    serial::start_field_thread,
+};
 use std::{
-    io::{self, Write},
-    process::exit,
+    fs,
+    path::{Path, PathBuf},
 };
 
-#[cfg(not(feature = "sim_io"))]
-use crate::serial::start_field_thread;
+/// Stand config loaded from the working directory if present and no other is given.
+const STAND_CONFIG_PATH: &str = "stand.toml";
 
-#[cfg(feature = "sim_io")]
-use crate::serial::start_simulation_field_thread;
+/// Sensor calibration loaded from the working directory if present and no other is given.
+const CALIBRATION_PATH: &str = "calibration.toml";
+
+/// Directory sequence scripts are loaded from if present and no other is given.
+const SEQUENCES_DIR: &str = "sequences";
 
+/// Plot layout loaded from and saved to the working directory if no other is given.
+const PLOT_LAYOUT_PATH: &str = "plots.toml";
+
+mod analysis;
+mod calibration;
+mod capture;
+mod clock;
+mod config;
+mod connection;
+mod derived;
 mod diagram;
+mod event;
 mod field_history;
+mod frame;
 mod gui;
+mod plot_layout;
 mod record;
+mod redline;
+mod safety;
+mod script;
 mod sequence;
 mod serial;
+mod session;
+#[cfg(feature = "sim_io")]
+mod sim;
 mod stand;
+mod watchdog;
 
 fn main() -> eframe::Result {
     simplelog::TermLogger::init(
@@ -30,90 +62,344 @@ fn main() -> eframe::Result {
     )
     .expect("Could not initialize logging");
 
-    #[cfg(feature = "sim_io")]
-    {
-        let sim_device = sim_field_io(
-            b"NP1:b=FALSE\nNP2:b=FALSE\nNP3:b=FALSE\nNP4:b=FALSE\nIP1:b=FALSE\nIP2:b=FALSE\nIP3:b=FALSE\nPT0:f=3.1415\nPT1:f=3\nPT2:f=2.718\nPT3:f=2\nScale Thrust:f=1.0\nScale Thrust Rate:f=1.0\nScale Ox:f=1.0\nScale Ox Rate:f=1.0\nScale Fuel:f=1.0\nScale Fuel Rate:f=1.0\nOx/Fuel Ratio:f=1.0\n",
-        );
-        let field_rx = start_simulation_field_thread(sim_device);
-        gui::start_gui(field_rx)
+    let mut config = match load_stand_config() {
+        Ok(config) => config,
+        Err(e) => {
+            log::error!("{e}");
+            std::process::exit(1);
+        }
+    };
+
+    let calibration = match load_calibration(&config) {
+        Ok(calibration) => calibration,
+        Err(e) => {
+            log::error!("{e}");
+            std::process::exit(1);
+        }
+    };
+
+    if let Some(calibration) = &calibration {
+        calibration.apply_units(&mut config);
     }
 
-    #[cfg(not(feature = "sim_io"))]
-    {
-        let io_device = get_field_io_device();
-        let field_rx = start_field_thread(io_device);
-        gui::start_gui(field_rx)
+    if std::env::args().nth(1).as_deref() == Some("analyze") {
+        std::process::exit(run_analysis(&config));
+    }
+
+    let scripts = load_scripts(&config);
+    let command_retry = config.timing.command_retry();
+
+    let mut field_rx = start_field_thread(
+        config.field_names(),
+        calibration
+            .as_ref()
+            .map(Calibrator::new)
+            .unwrap_or_default(),
+        DerivedFields::new(&config),
+        SafetyConfig::new(&config),
+    );
+
+    if let Some(path) = arg_value("--replay") {
+        attach_replay(&mut field_rx, &path, command_retry);
+    } else {
+        #[cfg(feature = "sim_io")]
+        attach_sim_stand(&mut field_rx, &config, command_retry);
     }
+
+    let plot_layout_path = PathBuf::from(arg_value("--plots").unwrap_or(PLOT_LAYOUT_PATH.into()));
+    let plot_layout = load_plot_layout(&plot_layout_path, &config);
+
+    gui::start_gui(
+        config,
+        calibration,
+        scripts,
+        field_rx,
+        Connection::new(command_retry),
+        plot_layout,
+        plot_layout_path,
+    )
 }
 
-/// Creates a dumby simulation [`FieldIO`] device which just reads off the given slice.
+/// Loads the [`StandConfig`] from the path following a `--stand` argument, or else from
+/// [`STAND_CONFIG_PATH`] if it exists, or else uses the one built into the console.
 ///
-/// [`FieldIO`]: FieldIO
-#[cfg(feature = "sim_io")]
-fn sim_field_io<'a>(buf: &'a [u8]) -> serial::FieldIO<&'a [u8]> {
-    serial::FieldIO::new(buf)
-}
+/// [`StandConfig`]: StandConfig
+/// [`STAND_CONFIG_PATH`]: STAND_CONFIG_PATH
+fn load_stand_config() -> Result<StandConfig, config::ConfigError> {
+    if let Some(path) = arg_value("--stand") {
+        log::info!("Loading stand config from {path}");
+        return StandConfig::load(path);
+    }
 
-/// Prompt the user to select one of the available USB serial connections and return it. This
-/// function handles errors itself, logging them and exiting the program as a whole.
-#[cfg(not(feature = "sim_io"))]
-fn get_field_io_device() -> serial::FieldIO<Box<dyn SerialPort>> {
-    let usb_ports = match serial::available_usb_ports() {
-        Ok(ports) => ports,
+    if Path::new(STAND_CONFIG_PATH).exists() {
+        log::info!("Loading stand config from {STAND_CONFIG_PATH}");
+        StandConfig::load(STAND_CONFIG_PATH)
+    } else {
+        log::info!("Using built in stand config");
+        Ok(StandConfig::built_in())
+    }
+}
 
-        Err(err) => {
-            log::error!("Could not identify available USB ports: {err}");
-            exit(1);
+/// Loads the [`Calibration`] from the path following a `--calibration` argument, or else from
+/// [`CALIBRATION_PATH`] if it exists. Fields are not calibrated if there is neither.
+///
+/// [`Calibration`]: Calibration
+/// [`CALIBRATION_PATH`]: CALIBRATION_PATH
+fn load_calibration(
+    config: &StandConfig,
+) -> Result<Option<Calibration>, calibration::CalibrationError> {
+    let path = match arg_value("--calibration") {
+        Some(path) => path,
+        None if Path::new(CALIBRATION_PATH).exists() => CALIBRATION_PATH.to_string(),
+        None => {
+            log::info!("No calibration, fields are used as sent");
+            return Ok(None);
         }
     };
 
-    println!("Available ports:");
+    let calibration = Calibration::load(&path, config)?;
+    log::info!(
+        "Loaded calibration version '{}' from {path}",
+        calibration.version
+    );
+    Ok(Some(calibration))
+}
+
+/// Loads the [`PlotLayout`] saved at the given path, or else the default for the given
+/// [`StandConfig`] if none has been saved or it cannot be loaded.
+///
+/// [`PlotLayout`]: PlotLayout
+/// [`StandConfig`]: StandConfig
+fn load_plot_layout(path: &Path, config: &StandConfig) -> PlotLayout {
+    match PlotLayout::load(path, config) {
+        Ok(layout) => {
+            log::info!("Loaded plot layout from {}", path.display());
+            layout
+        }
+
+        Err(PlotLayoutError::Io(e)) if e.kind() == std::io::ErrorKind::NotFound => {
+            PlotLayout::default_for(config)
+        }
+
+        Err(e) => {
+            log::error!("{e}, using default plot layout");
+            PlotLayout::default_for(config)
+        }
+    }
+}
+
+/// Loads the sequence scripts from the directory following a `--sequences` argument, or else from
+/// [`SEQUENCES_DIR`] if it exists, or else uses those built into the console.
+///
+/// [`SEQUENCES_DIR`]: SEQUENCES_DIR
+fn load_scripts(config: &StandConfig) -> ScriptLibrary {
+    let dir = match arg_value("--sequences") {
+        Some(dir) => dir,
+        None if Path::new(SEQUENCES_DIR).is_dir() => SEQUENCES_DIR.to_string(),
+        None => {
+            log::info!("Using built in sequences");
+            return ScriptLibrary::built_in(config);
+        }
+    };
 
-    for i in 0..usb_ports.len() {
-        let port = &usb_ports[i];
-        let name = match &port.usb_info.product {
-            None => "",
-            Some(s) => s,
-        };
+    match ScriptLibrary::load(&dir, config) {
+        Ok(scripts) => {
+            log::info!("Loaded sequences from {dir}");
+            scripts
+        }
 
-        println!("\tPort name [{i}]: {} ({})", name, port.port_name);
+        Err(e) => {
+            log::error!("Could not load sequences from {dir}, using built in sequences: {e}");
+            ScriptLibrary::built_in(config)
+        }
     }
+}
+
+/// Analyses the record, or the record in the session directory, given after the `analyze`
+/// subcommand, printing a summary of the burn in it as text, or as JSON if given a `--json`
+/// argument. The summary and plots are also written to the directory following an `--out`
+/// argument, or else to a directory next to the record, or in the session directory. Gives the
+/// exit code of the console.
+fn run_analysis(config: &StandConfig) -> i32 {
+    let Some(record_path) = std::env::args().nth(2).filter(|arg| !arg.starts_with("--")) else {
+        log::error!(
+            "Usage: analyze <record.csv | session dir> [--json] [--out <dir>] [--stand <config>]"
+        );
+        return 2;
+    };
 
-    write!(io::stdout(), "Select Port number (enter 'r' to refresh): ").unwrap();
-    io::stdout().flush().unwrap();
+    let (record_path, default_out_dir) = if Path::new(&record_path).is_dir() {
+        let session_dir = PathBuf::from(&record_path);
+        (
+            session_dir.join(session::RECORD_FILE),
+            session_dir.join("analysis"),
+        )
+    } else {
+        let record_path = PathBuf::from(&record_path);
+        let stem = record_path
+            .file_stem()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+        let out_dir = record_path.with_file_name(format!("{stem}_analysis"));
+        (record_path, out_dir)
+    };
+    let out_dir = arg_value("--out")
+        .map(PathBuf::from)
+        .unwrap_or(default_out_dir);
+
+    let analysed = analysis::RecordData::load(&record_path).and_then(|record| {
+        let summary = analysis::analyze(&record, config)?;
+
+        fs::create_dir_all(&out_dir).map_err(analysis::AnalysisError::Io)?;
+        let plots = analysis::write_plots(&record, &config.analysis, &summary, &out_dir)?;
 
-    let mut buffer = String::new();
+        Ok((summary, plots))
+    });
 
-    match io::stdin().read_line(&mut buffer) {
-        Ok(n) => log::debug!("Read {n} bytes from stdin"),
-        Err(err) => {
-            log::error!("Failed to read from stdin: {err}");
-            exit(1);
+    let (summary, plots) = match analysed {
+        Ok(analysed) => analysed,
+        Err(e) => {
+            log::error!("{e}");
+            return 1;
         }
     };
 
-    if buffer.as_str() == "r\n" {
-        return get_field_io_device();
+    let text = summary.to_string();
+    let json = serde_json::to_string_pretty(&summary).expect("Summary should serialize");
+
+    if let Err(e) = fs::write(out_dir.join("summary.txt"), &text)
+        .and_then(|()| fs::write(out_dir.join("summary.json"), &json))
+    {
+        log::error!("Could not write summary to {}: {e}", out_dir.display());
+        return 1;
+    }
+
+    if std::env::args().any(|arg| arg == "--json") {
+        println!("{json}");
+    } else {
+        print!("{text}");
     }
 
-    let port_number: Option<usize> = buffer.trim().parse().ok();
-    let selected_port = match port_number.and_then(|n| usb_ports.get(n)) {
-        Some(port) => port,
-        None => {
-            println!("Please enter the port number of a port in the above list");
-            exit(1);
+    for plot in plots {
+        log::info!("Wrote {plot}");
+    }
+
+    0
+}
+
+/// Gives the command line argument following the given flag, if present.
+fn arg_value(flag: &str) -> Option<String> {
+    std::env::args().skip_while(|arg| arg != flag).nth(1)
+}
+
+/// Attaches a [`ReplayStand`] playing the capture at the given path to the field thread, at the
+/// speed following a `--replay-speed` argument, or else in real time.
+///
+/// [`ReplayStand`]: capture::ReplayStand
+fn attach_replay(
+    field_rx: &mut serial::FieldReciever,
+    path: &str,
+    command_retry: serial::CommandRetry,
+) {
+    let speed = match arg_value("--replay-speed").map(|s| s.parse::<f64>()) {
+        Some(Ok(speed)) if speed.is_finite() && speed > 0.0 => speed,
+        None => 1.0,
+        Some(_) => {
+            log::error!("--replay-speed should be a positive multiple of real time");
+            std::process::exit(1);
         }
     };
 
-    let field_reader = match serial::open_field_port(selected_port, 115200) {
-        Ok(p) => p,
-        Err(err) => {
-            log::error!("Could not open the selected port: {err}");
-            exit(1);
+    let capture = match capture::Capture::load(path) {
+        Ok(capture) => capture,
+        Err(e) => {
+            log::error!("{e}");
+            std::process::exit(1);
+        }
+    };
+
+    log::info!(
+        "Replaying {:.1}s capture taken at {} UTC from {path} at {speed}x",
+        capture.duration().as_secs_f64(),
+        clock::file_timestamp(capture.started_at)
+    );
+
+    field_rx
+        .attach(
+            serial::FieldIO::new(capture::ReplayStand::new(capture, speed))
+                .with_command_retry(command_retry),
+        )
+        .expect("Field thread should have just started");
+}
+
+/// Attaches a simulated stand to the field thread. This plays the scenario file following a
+/// `--sim` argument if given, or else runs a [`SimulatedStand`] sending fields at the rate following
+/// a `--sim-rate` argument, or at [`DEFAULT_SIM_RATE`], with the [`FaultSchedule`] following a
+/// `--faults` argument injected if given. The simulated stand is asked for binary frames if given
+/// a `--binary` argument.
+///
+/// [`SimulatedStand`]: sim::SimulatedStand
+/// [`DEFAULT_SIM_RATE`]: sim::DEFAULT_SIM_RATE
+/// [`FaultSchedule`]: sim::FaultSchedule
+#[cfg(feature = "sim_io")]
+fn attach_sim_stand(
+    field_rx: &mut serial::FieldReciever,
+    config: &StandConfig,
+    command_retry: serial::CommandRetry,
+) {
+    let attached = match arg_value("--sim") {
+        Some(path) => match sim::ScriptedStand::load(&path) {
+            Ok(stand) => {
+                log::info!("Playing simulated stand scenario from {path}");
+                field_rx.attach(serial::FieldIO::new(stand).with_command_retry(command_retry))
+            }
+
+            Err(e) => {
+                log::error!("{e}");
+                std::process::exit(1);
+            }
+        },
+
+        None => {
+            let rate = match arg_value("--sim-rate").map(|r| r.parse::<f64>()) {
+                Some(Ok(rate)) if rate.is_finite() && rate > 0.0 && rate <= sim::MAX_SIM_RATE => {
+                    rate
+                }
+                None => sim::DEFAULT_SIM_RATE,
+                Some(_) => {
+                    log::error!(
+                        "--sim-rate should be a positive number of fields per second, at most {}",
+                        sim::MAX_SIM_RATE
+                    );
+                    std::process::exit(1);
+                }
+            };
+
+            let faults = match arg_value("--faults").map(sim::FaultSchedule::load) {
+                Some(Ok(faults)) => faults,
+                None => sim::FaultSchedule::default(),
+                Some(Err(e)) => {
+                    log::error!("{e}");
+                    std::process::exit(1);
+                }
+            };
+
+            log::info!("Running simulated stand at {rate} Hz");
+            let protocol = if std::env::args().any(|arg| arg == "--binary") {
+                frame::Protocol::Binary
+            } else {
+                frame::Protocol::Text
+            };
+
+            let stand = sim::SimulatedStand::new(config, rate).with_faults(faults);
+            field_rx.attach(
+                serial::FieldIO::new(stand)
+                    .with_command_retry(command_retry)
+                    .with_protocol(protocol),
+            )
         }
     };
 
-    log::info!("Established serial connection!");
-    field_reader
+    attached.expect("Field thread should have just started");
 }
@@ -0,0 +1,244 @@
+use crate::config::StandConfig;
+use serde::{Deserialize, Serialize};
+use std::{error::Error, fmt::Display, fs, io, path::Path, time::Duration};
+
+/// Names of the plots the console shows, the first above the other two.
+pub const PLOT_NAMES: [&str; 3] = ["upper", "left", "right"];
+
+/// Seconds of history a plot shows unless the operator picks another window.
+pub const DEFAULT_PLOT_WINDOW: f64 = 60.0;
+
+/// Seconds of history kept for plotting unless the operator picks another length.
+pub const DEFAULT_HISTORY: f64 = 600.0;
+
+/// Longest history, in seconds, the operator may pick, which is long enough to plot a whole fill.
+pub const MAX_HISTORY: f64 = 3600.0;
+
+/// Which channels each plot shows and how, and how much history is kept for them, saved between
+/// sessions.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlotLayout {
+    /// Seconds of history kept, which is the longest window any plot may show.
+    #[serde(default = "default_history")]
+    pub history: f64,
+
+    #[serde(rename = "plot", default)]
+    pub plots: Vec<PlotConfig>,
+}
+
+/// Settings of a single plot.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlotConfig {
+    /// Which of the [`PLOT_NAMES`] this plot is.
+    ///
+    /// [`PLOT_NAMES`]: PLOT_NAMES
+    pub name: String,
+
+    /// Names of the fields shown, in the order they were picked.
+    #[serde(default)]
+    pub channels: Vec<String>,
+
+    /// Seconds of history shown.
+    #[serde(default = "default_window")]
+    pub window: f64,
+
+    /// Whether to split the plot into stacked plots sharing a time axis, one per unit, so that
+    /// e.g. pressures and thrust each get their own y-axis.
+    #[serde(default)]
+    pub group_by_unit: bool,
+}
+
+fn default_window() -> f64 {
+    DEFAULT_PLOT_WINDOW
+}
+
+fn default_history() -> f64 {
+    DEFAULT_HISTORY
+}
+
+impl PlotLayout {
+    /// Load a [`PlotLayout`] from the TOML file at the given path, adding any of the
+    /// [`PLOT_NAMES`] it is missing as in [`PlotLayout::default_for`].
+    ///
+    /// [`PlotLayout`]: PlotLayout
+    /// [`PLOT_NAMES`]: PLOT_NAMES
+    /// [`PlotLayout::default_for`]: PlotLayout::default_for
+    pub fn load<P>(path: P, config: &StandConfig) -> Result<Self, PlotLayoutError>
+    where
+        P: AsRef<Path>,
+    {
+        let text = fs::read_to_string(path).map_err(PlotLayoutError::Io)?;
+        let mut layout: PlotLayout = toml::from_str(&text).map_err(PlotLayoutError::Parse)?;
+
+        let defaults = Self::default_for(config);
+        layout
+            .plots
+            .retain(|plot| PLOT_NAMES.contains(&plot.name.as_str()));
+
+        for plot in defaults.plots {
+            if !layout.plots.iter().any(|p| p.name == plot.name) {
+                layout.plots.push(plot);
+            }
+        }
+
+        layout.plots.sort_by_key(|plot| {
+            PLOT_NAMES
+                .iter()
+                .position(|&name| name == plot.name)
+                .unwrap_or(PLOT_NAMES.len())
+        });
+
+        layout.set_history(layout.history);
+
+        Ok(layout)
+    }
+
+    /// Save the [`PlotLayout`] as TOML to the given path.
+    ///
+    /// [`PlotLayout`]: PlotLayout
+    pub fn save<P>(&self, path: P) -> Result<(), PlotLayoutError>
+    where
+        P: AsRef<Path>,
+    {
+        let text = toml::to_string_pretty(self).map_err(PlotLayoutError::Serialize)?;
+        fs::write(path, text).map_err(PlotLayoutError::Io)
+    }
+
+    /// The [`PlotLayout`] used when none has been saved: every sensor and derived field in the
+    /// [`StandConfig`] on every plot, over the [`DEFAULT_PLOT_WINDOW`].
+    ///
+    /// [`PlotLayout`]: PlotLayout
+    /// [`StandConfig`]: StandConfig
+    /// [`DEFAULT_PLOT_WINDOW`]: DEFAULT_PLOT_WINDOW
+    pub fn default_for(config: &StandConfig) -> Self {
+        let channels: Vec<String> = config
+            .sensors
+            .iter()
+            .map(|s| s.name.clone())
+            .chain(config.derived.iter().map(|d| d.name.clone()))
+            .collect();
+
+        Self {
+            history: DEFAULT_HISTORY,
+            plots: PLOT_NAMES
+                .iter()
+                .map(|name| PlotConfig {
+                    name: name.to_string(),
+                    channels: channels.clone(),
+                    window: DEFAULT_PLOT_WINDOW,
+                    group_by_unit: false,
+                })
+                .collect(),
+        }
+    }
+
+    /// How much history to keep for plotting.
+    pub fn history(&self) -> Duration {
+        Duration::from_secs_f64(self.history)
+    }
+
+    /// Set how many seconds of history to keep, up to [`MAX_HISTORY`], shortening the window of
+    /// any plot which would show more. Lengths which are not finite, e.g. from a hand edited
+    /// layout, are reset to [`DEFAULT_HISTORY`] and [`DEFAULT_PLOT_WINDOW`].
+    ///
+    /// [`MAX_HISTORY`]: MAX_HISTORY
+    /// [`DEFAULT_HISTORY`]: DEFAULT_HISTORY
+    /// [`DEFAULT_PLOT_WINDOW`]: DEFAULT_PLOT_WINDOW
+    pub fn set_history(&mut self, history: f64) {
+        let finite_or = |value: f64, default| if value.is_finite() { value } else { default };
+
+        self.history = finite_or(history, DEFAULT_HISTORY).clamp(1.0, MAX_HISTORY);
+
+        for plot in &mut self.plots {
+            plot.window = finite_or(plot.window, DEFAULT_PLOT_WINDOW).clamp(1.0, self.history);
+        }
+    }
+}
+
+impl PlotConfig {
+    /// Show or hide the field with the given name.
+    pub fn set_shown(&mut self, channel: &str, shown: bool) {
+        let position = self.channels.iter().position(|c| c == channel);
+
+        match (position, shown) {
+            (None, true) => self.channels.push(channel.to_string()),
+            (Some(i), false) => {
+                self.channels.remove(i);
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Errors which may occur loading or saving a [`PlotLayout`].
+///
+/// [`PlotLayout`]: PlotLayout
+#[derive(Debug)]
+pub enum PlotLayoutError {
+    Io(io::Error),
+    Parse(toml::de::Error),
+    Serialize(toml::ser::Error),
+}
+
+impl Display for PlotLayoutError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PlotLayoutError::Io(e) => write!(f, "Could not read or write plot layout: {e}"),
+            PlotLayoutError::Parse(e) => write!(f, "Could not parse plot layout: {e}"),
+            PlotLayoutError::Serialize(e) => write!(f, "Could not serialize plot layout: {e}"),
+        }
+    }
+}
+
+impl Error for PlotLayoutError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Load a [`PlotLayout`] for the built in config from the given TOML text.
+    ///
+    /// [`PlotLayout`]: PlotLayout
+    fn load(name: &str, text: &str) -> PlotLayout {
+        let path = std::env::temp_dir().join(format!("nile_{name}_{}.toml", std::process::id()));
+        fs::write(&path, text).unwrap();
+
+        let layout = PlotLayout::load(&path, &StandConfig::built_in());
+        let _ = fs::remove_file(&path);
+        layout.unwrap()
+    }
+
+    #[test]
+    fn non_finite_lengths_are_reset() {
+        let layout = load(
+            "non_finite_plots",
+            "history = nan\n\n[[plot]]\nname = \"upper\"\nwindow = nan\n\n\
+             [[plot]]\nname = \"left\"\nwindow = inf\n",
+        );
+
+        assert_eq!(layout.history, DEFAULT_HISTORY);
+        assert_eq!(layout.history(), Duration::from_secs_f64(DEFAULT_HISTORY));
+        assert_eq!(layout.plots.len(), PLOT_NAMES.len());
+
+        for plot in &layout.plots {
+            assert_eq!(plot.window, DEFAULT_PLOT_WINDOW, "{} window", plot.name);
+        }
+    }
+
+    #[test]
+    fn windows_are_clamped_to_history() {
+        let mut layout = load(
+            "clamped_plots",
+            "history = 1e9\n\n[[plot]]\nname = \"upper\"\nwindow = 5000\n",
+        );
+
+        assert_eq!(layout.history, MAX_HISTORY);
+        assert_eq!(layout.plots[0].window, MAX_HISTORY);
+
+        layout.set_history(30.0);
+        assert!(layout.plots.iter().all(|plot| plot.window == 30.0));
+
+        layout.set_history(f64::NEG_INFINITY);
+        assert_eq!(layout.history, DEFAULT_HISTORY);
+    }
+}
@@ -1,79 +1,131 @@
 use std::{
     fs::File,
-    io::{self, Write},
-    path::Path,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
     time::{Duration, SystemTime},
 };
 
-use crate::serial::{SensorField, SensorValue};
+use crate::{
+    event::StandEvent,
+    serial::{SensorField, SensorValue},
+};
+
+/// Header of every [`StandRecord`] CSV. Records are written in a long format, one row per field
+/// recieved, so that fields first seen partway through recording are still recorded.
+///
+/// [`StandRecord`]: StandRecord
+pub const RECORD_HEADER: &str = "Time (Seconds),Stand Time (Seconds),Field,Value";
 
-/// A record of the stand's state saved to disk.
+/// Header of the events CSV written alongside each [`StandRecord`].
+///
+/// [`StandRecord`]: StandRecord
+pub const EVENTS_HEADER: &str = "Time (Seconds),Event,Detail";
+
+/// A record of the stand's state saved to disk, along with a log of [`StandEvent`]s on the same
+/// time base in a second CSV at [`events_path`]. Writes are buffered, so the record should be
+/// flushed periodically with [`StandRecord::flush`].
+///
+/// [`StandEvent`]: StandEvent
+/// [`events_path`]: events_path
+/// [`StandRecord::flush`]: StandRecord::flush
 #[derive(Debug)]
 pub struct StandRecord {
-    file: File,
-    field_names: Vec<String>,
+    file: BufWriter<File>,
+    events_file: BufWriter<File>,
     start_time: SystemTime,
 }
 
+/// The path of the events CSV written alongside the record at the given path, e.g.
+/// `burn.events.csv` for `burn.csv`.
+pub fn events_path<P>(record_path: P) -> PathBuf
+where
+    P: AsRef<Path>,
+{
+    record_path.as_ref().with_extension("events.csv")
+}
+
 impl StandRecord {
     /// Open a new [`StandRecord`] at the given path. The [`StandRecord`] creates a CSV, so the
-    /// extension in the given path may want to reflect that, though this is not enforced.
+    /// extension in the given path may want to reflect that, though this is not enforced. The
+    /// events CSV is created at the matching [`events_path`].
     ///
+    /// [`events_path`]: events_path
     /// [`StandRecord`]: StandRecord
-    pub fn open<P>(path: P, field_names: Vec<String>) -> io::Result<StandRecord>
+    pub fn open<P>(path: P) -> io::Result<StandRecord>
     where
         P: AsRef<Path>,
     {
-        let mut file = File::create(path.as_ref())?;
-        let field_names_row = field_names
-            .iter()
-            .fold("Time (Seconds)".to_string(), |acc, i| format!("{acc},{i}"));
-        let row = format!("{field_names_row}\n");
-
-        file.write_all(&mut row.into_bytes())?;
+        let mut file = BufWriter::new(File::create(path.as_ref())?);
+        file.write_all(format!("{RECORD_HEADER}\n").as_bytes())?;
         file.flush()?;
 
+        let mut events_file = BufWriter::new(File::create(events_path(&path))?);
+        events_file.write_all(format!("{EVENTS_HEADER}\n").as_bytes())?;
+        events_file.flush()?;
+
         Ok(StandRecord {
             file,
-            field_names,
+            events_file,
             start_time: SystemTime::now(),
         })
     }
 
-    /// Append the given [`SensorField`]s' values to the [`StandRecord`], timestamped with the
-    /// current time since opening the [`StandRecord`]. Note that field who's names do not match
-    /// those given in the [`StandRecord::open`] function will not be recorded.
+    /// Append a row for the given [`SensorField`] to the [`StandRecord`], timestamped with the
+    /// field's time since opening the [`StandRecord`], and its stand time if the stand stamps its
+    /// fields.
     ///
     /// [`SensorField`]: SensorField
     /// [`StandRecord`]: StandRecord
-    /// [`StandRecord::open`]: StandRecord::open
-    pub fn append_frame(&mut self, fields: &[SensorField]) -> io::Result<()> {
-        let now = SystemTime::now()
+    pub fn append_field(&mut self, field: &SensorField) -> io::Result<()> {
+        let time = field
+            .time
             .duration_since(self.start_time)
             .unwrap_or(Duration::from_secs(0));
+        let stand_time = field.stand_time.map(|t| t.to_string()).unwrap_or_default();
+        let name = csv_text(&field.name);
 
-        let row = self
-            .field_names
-            .iter()
-            .fold(format!("{}", now.as_secs_f64()), |acc, i| {
-                let field = fields.iter().find(|f| f.name.as_str() == i.as_str());
+        let value = match field.value {
+            SensorValue::UnsignedInt(v) => v.to_string(),
+            SensorValue::SignedInt(v) => v.to_string(),
+            SensorValue::Float(v) => v.to_string(),
+            SensorValue::Boolean(v) => v.to_string(),
+        };
 
-                match field {
-                    Some(f) => match f.value {
-                        SensorValue::UnsignedInt(v) => format!("{acc},{v}"),
-                        SensorValue::SignedInt(v) => format!("{acc},{v}"),
-                        SensorValue::Float(v) => format!("{acc},{v}"),
-                        SensorValue::Boolean(v) => format!("{acc},{v}"),
-                    },
+        let row = format!("{},{stand_time},{name},{value}\n", time.as_secs_f64());
+        self.file.write_all(row.as_bytes())
+    }
 
-                    None => format!("{acc},"),
-                }
-            });
+    /// Append the given [`StandEvent`] to the events CSV, timestamped with its time since opening
+    /// the [`StandRecord`], so that it lines up with the record's frames.
+    ///
+    /// [`StandEvent`]: StandEvent
+    /// [`StandRecord`]: StandRecord
+    pub fn append_event(&mut self, event: &StandEvent) -> io::Result<()> {
+        let time = event
+            .time
+            .duration_since(self.start_time)
+            .unwrap_or(Duration::from_secs(0));
+        let row = format!(
+            "{},{},{}\n",
+            time.as_secs_f64(),
+            event.kind.name(),
+            csv_text(&event.kind.to_string())
+        );
+        self.events_file.write_all(row.as_bytes())
+    }
 
-        let row = format!("{row}\n");
-        self.file.write_all(&mut row.as_bytes())?;
+    /// Write any buffered rows of the record and its events to disk.
+    pub fn flush(&mut self) -> io::Result<()> {
         self.file.flush()?;
+        self.events_file.flush()
+    }
+}
 
-        Ok(())
+/// The given text as a CSV cell, quoted with any quotes in it doubled if it needs to be.
+fn csv_text(text: &str) -> String {
+    if text.contains([',', '"', '\n']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
     }
 }
@@ -0,0 +1,189 @@
+use crate::{config::RedlineConfig, serial::SensorField};
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    time::{Duration, SystemTime},
+};
+
+/// Shortest time between the two samples a field's rate of change is computed from, so that the
+/// rate is not dominated by noise between consecutive samples.
+const RATE_WINDOW: Duration = Duration::from_millis(100);
+
+/// The limit of a redline which was exceeded.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RedlineLimit {
+    Min(f64),
+    Max(f64),
+    Rate(f64),
+}
+
+/// A record of a redline tripping.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RedlineTrip {
+    pub field: String,
+    pub limit: RedlineLimit,
+
+    /// The value, or rate of change for a [`RedlineLimit::Rate`], which exceeded the limit.
+    ///
+    /// [`RedlineLimit::Rate`]: RedlineLimit::Rate
+    pub value: f64,
+}
+
+/// Checks field values against the configured [`RedlineConfig`]s.
+///
+/// [`RedlineConfig`]: RedlineConfig
+#[derive(Debug, Clone)]
+pub struct RedlineMonitor {
+    redlines: Vec<RedlineConfig>,
+
+    /// The sample each field's rate of change is currently measured from, and when it was taken.
+    rate_samples: HashMap<String, (f64, SystemTime)>,
+
+    /// The rate of change of each field with a rate limit measured by the last update, so that a
+    /// rate is only checked against the sample it was measured from.
+    rates: HashMap<String, f64>,
+}
+
+impl RedlineMonitor {
+    pub fn new(redlines: Vec<RedlineConfig>) -> Self {
+        Self {
+            redlines,
+            rate_samples: HashMap::new(),
+            rates: HashMap::new(),
+        }
+    }
+
+    /// Update the rates of change of fields with the latest values, each taken at the time it is
+    /// stamped with. Should be called whenever new values are recieved, whether or not the
+    /// redlines are being checked.
+    pub fn update(&mut self, fields: &[SensorField]) {
+        self.rates.clear();
+
+        for SensorField {
+            name, value, time, ..
+        } in fields
+        {
+            if !self
+                .redlines
+                .iter()
+                .any(|r| &r.field == name && r.max_rate.is_some())
+            {
+                continue;
+            }
+
+            let value = value.to_num();
+
+            match self.rate_samples.get_mut(name) {
+                Some((last_value, last_time)) => {
+                    // a value stamped before the last sample has no elapsed time to rate it over
+                    let elapsed = time.duration_since(*last_time).unwrap_or_default();
+
+                    if elapsed >= RATE_WINDOW {
+                        self.rates
+                            .insert(name.clone(), (value - *last_value) / elapsed.as_secs_f64());
+                        *last_value = value;
+                        *last_time = *time;
+                    }
+                }
+
+                None => {
+                    self.rate_samples.insert(name.clone(), (value, *time));
+                }
+            }
+        }
+    }
+
+    /// Check the given field values, and the rates of change measured from them by the last
+    /// update, against every redline armed at the given time, giving the first to trip. `sequence_start` is when the last sequence was started, if any, which
+    /// redlines with an arming window are armed relative to.
+    pub fn check(
+        &self,
+        now: SystemTime,
+        sequence_start: Option<SystemTime>,
+        fields: &[SensorField],
+    ) -> Option<RedlineTrip> {
+        for redline in self
+            .redlines
+            .iter()
+            .filter(|r| is_armed(r, now, sequence_start))
+        {
+            let trip = |limit, value| {
+                Some(RedlineTrip {
+                    field: redline.field.clone(),
+                    limit,
+                    value,
+                })
+            };
+
+            let value = fields
+                .iter()
+                .find(|f| f.name == redline.field)
+                .map(|f| f.value.to_num());
+
+            if let Some(v) = value {
+                if let Some(min) = redline.min
+                    && v < min
+                {
+                    return trip(RedlineLimit::Min(min), v);
+                }
+
+                if let Some(max) = redline.max
+                    && v > max
+                {
+                    return trip(RedlineLimit::Max(max), v);
+                }
+            }
+
+            if let Some(max_rate) = redline.max_rate
+                && let Some(&rate) = self.rates.get(&redline.field)
+                && rate.abs() > max_rate
+            {
+                return trip(RedlineLimit::Rate(max_rate), rate);
+            }
+        }
+
+        None
+    }
+}
+
+/// Whether the given redline is armed at the given time.
+fn is_armed(redline: &RedlineConfig, now: SystemTime, sequence_start: Option<SystemTime>) -> bool {
+    if redline.armed_after.is_none() && redline.armed_until.is_none() {
+        return true;
+    }
+
+    let Some(start) = sequence_start else {
+        return false;
+    };
+
+    let since_start = now.duration_since(start).unwrap_or_default().as_secs_f64();
+
+    redline.armed_after.is_none_or(|t| since_start >= t)
+        && redline.armed_until.is_none_or(|t| since_start < t)
+}
+
+impl Display for RedlineTrip {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.limit {
+            RedlineLimit::Min(min) => {
+                write!(
+                    f,
+                    "{} redline: {} below min of {min}",
+                    self.field, self.value
+                )
+            }
+            RedlineLimit::Max(max) => {
+                write!(
+                    f,
+                    "{} redline: {} above max of {max}",
+                    self.field, self.value
+                )
+            }
+            RedlineLimit::Rate(rate) => write!(
+                f,
+                "{} redline: changing at {:.2}/s, above max of {rate}/s",
+                self.field, self.value
+            ),
+        }
+    }
+}
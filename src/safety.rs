@@ -0,0 +1,430 @@
+use crate::{
+    config::{RedlineConfig, StandConfig},
+    redline::{RedlineMonitor, RedlineTrip},
+    sequence::SequenceHandle,
+    serial::{SensorField, ValveCommand},
+    stand::StandMode,
+    watchdog::{Watchdog, WatchdogAction, WatchdogConfig, WatchdogState},
+};
+use std::time::{Duration, Instant};
+
+/// Longest the failsafe waits for a cancelled sequence to stop before safing the stand anyway.
+const SEQUENCE_STOP_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// What the field thread needs to know to safe the stand by itself, without waiting on the GUI.
+#[derive(Debug, Clone, Default)]
+pub struct SafetyConfig {
+    pub redlines: Vec<RedlineConfig>,
+    pub watchdog: WatchdogConfig,
+
+    /// The commands which safe the stand, sent whenever it is failsafed.
+    pub safing_commands: Vec<ValveCommand>,
+}
+
+impl SafetyConfig {
+    /// The [`SafetyConfig`] of the stand described by the given [`StandConfig`], which safes the
+    /// stand with the commands sent on entering [`StandMode::Safing`].
+    ///
+    /// [`SafetyConfig`]: SafetyConfig
+    /// [`StandConfig`]: StandConfig
+    /// [`StandMode::Safing`]: StandMode::Safing
+    pub fn new(config: &StandConfig) -> Self {
+        let safing = config.mode(StandMode::Safing);
+
+        Self {
+            redlines: config.redlines.clone(),
+            watchdog: config.timing.watchdog(),
+            safing_commands: safing
+                .open_on_entry
+                .iter()
+                .map(|v| ValveCommand::Open(v.clone()))
+                .chain(
+                    safing
+                        .close_on_entry
+                        .iter()
+                        .map(|v| ValveCommand::Close(v.clone())),
+                )
+                .collect(),
+        }
+    }
+}
+
+/// Watches the fields passing through the field thread for a reason to safe the stand, and
+/// safes it, so that the stand is safed however busy the GUI is.
+///
+/// Redlines are checked against every field as it is read, at the time the field was taken, while
+/// they are armed. Tripping a redline disarms them until they are armed again. A [`Watchdog`]
+/// safes the stand if fields stop arriving.
+///
+/// [`Watchdog`]: Watchdog
+#[derive(Debug)]
+pub struct SafetyMonitor {
+    redlines: RedlineMonitor,
+    redlines_armed: bool,
+    watchdog: Watchdog,
+
+    /// The last sequence started, which is cancelled when the stand is safed and which redlines
+    /// with an arming window are armed relative to.
+    sequence: Option<SequenceHandle>,
+
+    safing_commands: Vec<ValveCommand>,
+}
+
+impl SafetyMonitor {
+    /// Create a new [`SafetyMonitor`] with its redlines disarmed.
+    ///
+    /// [`SafetyMonitor`]: SafetyMonitor
+    pub fn new(config: SafetyConfig) -> Self {
+        Self {
+            redlines: RedlineMonitor::new(config.redlines),
+            redlines_armed: false,
+            watchdog: Watchdog::new(config.watchdog),
+            sequence: None,
+            safing_commands: config.safing_commands,
+        }
+    }
+
+    /// Arm or disarm the redlines, e.g. on entering or leaving
+    /// [`StandMode::PressurizationAndFiring`].
+    ///
+    /// [`StandMode::PressurizationAndFiring`]: StandMode::PressurizationAndFiring
+    pub fn arm_redlines(&mut self, armed: bool) {
+        self.redlines_armed = armed;
+    }
+
+    /// Watch the given sequence, in place of any sequence watched before it.
+    pub fn watch_sequence(&mut self, sequence: SequenceHandle) {
+        self.sequence = Some(sequence);
+    }
+
+    /// Check the given field against the armed redlines at the time it was taken, giving the
+    /// redline it tripped, if any, after which the redlines are disarmed.
+    pub fn check_redlines(&mut self, field: &SensorField) -> Option<RedlineTrip> {
+        let fields = std::slice::from_ref(field);
+        self.redlines.update(fields);
+
+        if !self.redlines_armed {
+            return None;
+        }
+
+        let sequence_start = self.sequence.as_ref().map(SequenceHandle::started);
+        let trip = self.redlines.check(field.time, sequence_start, fields)?;
+        self.redlines_armed = false;
+
+        Some(trip)
+    }
+
+    /// Tell the [`Watchdog`] that fields were just recieved.
+    ///
+    /// [`Watchdog`]: Watchdog
+    pub fn feed_watchdog(&mut self) {
+        self.watchdog.feed(Instant::now());
+    }
+
+    /// Update the [`Watchdog`] for the current time, giving whether the stand should be safed.
+    /// `link_alive` should be false if there is no device to read fields from.
+    ///
+    /// [`Watchdog`]: Watchdog
+    pub fn update_watchdog(&mut self, link_alive: bool) -> WatchdogAction {
+        self.watchdog.update(Instant::now(), link_alive)
+    }
+
+    pub fn watchdog_state(&self) -> WatchdogState {
+        self.watchdog.state()
+    }
+
+    /// Return a tripped [`Watchdog`] to [`WatchdogState::Nominal`].
+    ///
+    /// [`Watchdog`]: Watchdog
+    /// [`WatchdogState::Nominal`]: WatchdogState::Nominal
+    pub fn reset_watchdog(&mut self) {
+        self.watchdog.reset();
+    }
+
+    /// Cancel the watched sequence, without running its abort sequence, and wait for it to stop
+    /// before giving the commands which safe the stand, so that the sequence cannot undo them.
+    /// The redlines are disarmed.
+    pub fn safe(&mut self) -> Vec<ValveCommand> {
+        if let Some(sequence) = &self.sequence {
+            if sequence.state().is_active() {
+                log::warn!("Cancelling sequence to safe the stand");
+            }
+
+            // also stops an abort sequence which is about to start
+            sequence.cancel();
+
+            if !sequence.wait_stopped(SEQUENCE_STOP_TIMEOUT) {
+                log::error!("Sequence did not stop in time, safing anyway!");
+            }
+        }
+
+        self.redlines_armed = false;
+        self.safing_commands.clone()
+    }
+}
+
+#[cfg(all(test, feature = "sim_io"))]
+mod tests {
+    use super::*;
+    use crate::{
+        calibration::Calibrator,
+        derived::DerivedFields,
+        redline::RedlineLimit,
+        sequence::{Command, CommandSequence, SequenceState},
+        serial::{FieldIO, FieldReciever, FieldSender, SensorValue, field_channel},
+        sim::ScriptedStand,
+    };
+    use std::{
+        io::{self, Read, Write},
+        sync::{Arc, Mutex},
+        time::Instant,
+    };
+
+    /// A device which keeps a copy of every byte written to it before passing them on.
+    struct Tapped<D> {
+        device: D,
+        written: Arc<Mutex<Vec<u8>>>,
+    }
+
+    impl<D: Read> Read for Tapped<D> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.device.read(buf)
+        }
+    }
+
+    impl<D: Write> Write for Tapped<D> {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.written.lock().unwrap().extend_from_slice(buf);
+            self.device.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.device.flush()
+        }
+    }
+
+    /// A field channel for the built in stand with the given redlines, armed, attached to a
+    /// [`ScriptedStand`] playing the given scenario. Also gives every byte written to the stand.
+    ///
+    /// [`ScriptedStand`]: ScriptedStand
+    fn scripted(
+        scenario: &str,
+        redlines: Vec<RedlineConfig>,
+    ) -> (FieldSender, FieldReciever, Arc<Mutex<Vec<u8>>>) {
+        let config = StandConfig::built_in();
+        let safety = SafetyConfig {
+            redlines,
+            ..SafetyConfig::new(&config)
+        };
+
+        let (mut sender, mut reciever) = field_channel(
+            config.field_names(),
+            Calibrator::default(),
+            DerivedFields::default(),
+            safety,
+        );
+
+        let written = Arc::new(Mutex::new(Vec::new()));
+        let stand = Tapped {
+            device: ScriptedStand::parse(scenario).unwrap(),
+            written: written.clone(),
+        };
+
+        reciever.attach(FieldIO::new(stand)).unwrap();
+        reciever.arm_redlines(true);
+        sender.handle_controls();
+
+        (sender, reciever, written)
+    }
+
+    /// Run the field thread's loop for the given time, then recieve what it sent.
+    fn run_for(sender: &mut FieldSender, reciever: &mut FieldReciever, time: Duration) {
+        let start = Instant::now();
+
+        while start.elapsed() < time {
+            sender.step().unwrap();
+        }
+
+        reciever.recieve_fields().unwrap();
+    }
+
+    /// The commands written to the stand, without their IDs.
+    fn commands_written(written: &Mutex<Vec<u8>>) -> Vec<String> {
+        String::from_utf8_lossy(&written.lock().unwrap())
+            .lines()
+            .filter_map(|line| line.split_once('#'))
+            .map(|(command, _)| command.to_string())
+            .collect()
+    }
+
+    /// The safing commands of the built in stand, as written to the stand.
+    fn safing_commands() -> Vec<String> {
+        SafetyConfig::new(&StandConfig::built_in())
+            .safing_commands
+            .iter()
+            .map(ValveCommand::to_string)
+            .collect()
+    }
+
+    /// A redline on `NPT1` with no limits, armed at all times.
+    fn npt1_redline() -> RedlineConfig {
+        RedlineConfig {
+            field: "NPT1".to_string(),
+            min: None,
+            max: None,
+            max_rate: None,
+            armed_after: None,
+            armed_until: None,
+        }
+    }
+
+    /// A redline at a maximum of 750 on `NPT1`, armed at all times.
+    fn npt1_max_redline() -> RedlineConfig {
+        RedlineConfig {
+            max: Some(750.0),
+            ..npt1_redline()
+        }
+    }
+
+    #[test]
+    fn out_of_range_value_safes_the_stand_once() {
+        let scenario = "0 NPT1:f=100.0\n0 NPT1:f=800.0\n0 NPT1:f=900.0\n";
+        let (mut sender, mut reciever, written) = scripted(scenario, vec![npt1_max_redline()]);
+        run_for(&mut sender, &mut reciever, Duration::from_millis(100));
+
+        assert_eq!(
+            reciever.redline_trips(),
+            [RedlineTrip {
+                field: "NPT1".to_string(),
+                limit: RedlineLimit::Max(750.0),
+                value: 800.0,
+            }]
+        );
+        assert_eq!(reciever.take_failsafe().as_deref(), Some("redline"));
+        assert_eq!(reciever.take_failsafe(), None);
+
+        // the later value is past the redline too, but the stand is only safed once
+        assert_eq!(commands_written(&written), safing_commands());
+
+        // the stand acknowledged each command and reports each valve in its safed state
+        assert!(reciever.command_failures().is_empty());
+        for command in SafetyConfig::new(&StandConfig::built_in()).safing_commands {
+            let (valve, open) = match command {
+                ValveCommand::Open(valve) => (valve, true),
+                ValveCommand::Close(valve) => (valve, false),
+            };
+
+            let reported = reciever.fields().find(|f| f.name == valve).unwrap();
+            assert_eq!(reported.value, SensorValue::Boolean(open), "{valve}");
+        }
+    }
+
+    #[test]
+    fn disarmed_redlines_do_not_trip() {
+        let scenario = "0 NPT1:f=800.0\n0.05 NPT1:f=900.0\n";
+        let (mut sender, mut reciever, written) = scripted(scenario, vec![npt1_max_redline()]);
+        reciever.arm_redlines(false);
+        run_for(&mut sender, &mut reciever, Duration::from_millis(100));
+
+        assert_eq!(reciever.fields().count(), 1);
+        assert!(reciever.redline_trips().is_empty());
+        assert_eq!(reciever.take_failsafe(), None);
+        assert!(commands_written(&written).is_empty());
+    }
+
+    #[test]
+    fn rates_are_measured_between_field_times() {
+        // both values arrive together, but the stand stamped them 0.2s apart
+        let scenario = "0 Stand Time:f=10.0\n\
+                        0.3 NPT1:f=100.0@10.0\n\
+                        0.3 NPT1:f=400.0@10.2\n";
+        let redline = RedlineConfig {
+            max_rate: Some(1000.0),
+            ..npt1_redline()
+        };
+
+        let (mut sender, mut reciever, _) = scripted(scenario, vec![redline]);
+        run_for(&mut sender, &mut reciever, Duration::from_millis(400));
+
+        let [trip] = reciever.redline_trips() else {
+            panic!("expected one trip, got {:?}", reciever.redline_trips());
+        };
+        assert_eq!(trip.limit, RedlineLimit::Rate(1000.0));
+        assert!((trip.value - 1500.0).abs() < 1.0, "rate of {}", trip.value);
+    }
+
+    #[test]
+    fn rates_are_only_checked_on_the_sample_they_were_measured_from() {
+        // the first rate is measured while disarmed, and is past the limit
+        let scenario = "0 Stand Time:f=10.0\n\
+                        0.3 NPT1:f=100.0@10.0\n\
+                        0.3 NPT1:f=400.0@10.2\n\
+                        0.35 NPT3:f=1.0\n\
+                        0.4 NPT1:f=401.0@10.4\n\
+                        0.6 NPT1:f=800.0@10.6\n";
+        let redline = RedlineConfig {
+            max_rate: Some(1000.0),
+            ..npt1_redline()
+        };
+
+        let (mut sender, mut reciever, _) = scripted(scenario, vec![redline]);
+        reciever.arm_redlines(false);
+        run_for(&mut sender, &mut reciever, Duration::from_millis(320));
+        reciever.arm_redlines(true);
+        run_for(&mut sender, &mut reciever, Duration::from_millis(130));
+
+        // neither the other field nor the slow rise trips on the stale rate
+        assert!(reciever.redline_trips().is_empty());
+
+        run_for(&mut sender, &mut reciever, Duration::from_millis(200));
+        let [trip] = reciever.redline_trips() else {
+            panic!("expected one trip, got {:?}", reciever.redline_trips());
+        };
+        assert_eq!(trip.limit, RedlineLimit::Rate(1000.0));
+        assert!((trip.value - 1995.0).abs() < 1.0, "rate of {}", trip.value);
+    }
+
+    #[test]
+    fn arming_windows_follow_field_times() {
+        // both values arrive 0.2s after the sequence starts, but the first was taken 0.1s after it
+        // started, before its redline was armed
+        let scenario = "0 Stand Time:f=10.0\n\
+                        0.2 NPT1:f=800.0@10.1\n\
+                        0.2 NPT1:f=810.0@10.19\n";
+        let redline = RedlineConfig {
+            max: Some(750.0),
+            armed_after: Some(0.15),
+            ..npt1_redline()
+        };
+
+        let (mut sender, mut reciever, written) = scripted(scenario, vec![redline]);
+        let sequence = CommandSequence::new().then(Command::Wait(Duration::from_secs(60)));
+        let handle = reciever.run_sequence_par(sequence, None);
+        run_for(&mut sender, &mut reciever, Duration::from_millis(300));
+
+        let [trip] = reciever.redline_trips() else {
+            panic!("expected one trip, got {:?}", reciever.redline_trips());
+        };
+        assert_eq!(trip.value, 810.0);
+
+        // the sequence was stopped before the stand was safed
+        assert_eq!(handle.state(), SequenceState::Aborted);
+        assert!(handle.wait_stopped(Duration::ZERO));
+        assert_eq!(commands_written(&written), safing_commands());
+    }
+
+    #[test]
+    fn operator_failsafe_cancels_the_sequence_and_safes_the_stand() {
+        let (mut sender, mut reciever, written) = scripted("0 NPT1:f=100.0\n", Vec::new());
+        let sequence = CommandSequence::new().then(Command::Wait(Duration::from_secs(60)));
+        let handle = reciever.run_sequence_par(sequence, None);
+
+        reciever.failsafe("operator").unwrap();
+        run_for(&mut sender, &mut reciever, Duration::from_millis(50));
+
+        assert_eq!(reciever.take_failsafe().as_deref(), Some("operator"));
+        assert!(reciever.redline_trips().is_empty());
+        assert_eq!(handle.state(), SequenceState::Aborted);
+        assert_eq!(commands_written(&written), safing_commands());
+    }
+}
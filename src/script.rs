@@ -0,0 +1,1000 @@
+use crate::{
+    config::{StandConfig, ValveKind},
+    sequence::{
+        Command, CommandSequence, Comparison, Condition, MAX_WAIT, TimeoutAction, ValveHandle,
+        WaitUntil,
+    },
+    stand::ValveState,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    ffi::OsStr,
+    fmt::Display,
+    fs, io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// File extension of sequence scripts.
+pub const SCRIPT_EXTENSION: &str = "seq";
+
+/// Sequences built into the console, used when no sequence directory is present.
+const BUILT_IN_SCRIPTS: [(&str, &str); 4] = [
+    ("abort", include_str!("../sequences/abort.seq")),
+    (
+        "depressurize",
+        include_str!("../sequences/depressurize.seq"),
+    ),
+    ("fire", include_str!("../sequences/fire.seq")),
+    ("pressurize", include_str!("../sequences/pressurize.seq")),
+];
+
+/// A [`CommandSequence`] written in the console's sequence scripting language. Scripts have one
+/// command per line, with anything after a `#` being a comment:
+///
+/// - `open <valve>` and `close <valve>` command a valve by the name given in the [`StandConfig`].
+/// - `wait <duration>` waits for a duration such as `500ms`, `1.5s`, or `2` (seconds), of at
+///   most a day.
+/// - `wait until <condition> timeout <duration> else <action>` waits until a condition on the
+///   stand's telemetry holds, such as `NPT1 > 400 psi` or `NP2 is open`. If it does not hold
+///   within the timeout then the action is taken, being one of `abort`, `skip`, or
+///   `run <sequence>` which runs the named sequence in place of the rest of this one.
+/// - `done` ends the sequence, and must be the last command if present.
+///
+/// A valve or duration may instead be given as a `$parameter`, which is filled in by the console
+/// when the script is run, e.g. `open $engine`.
+///
+/// [`CommandSequence`]: CommandSequence
+/// [`StandConfig`]: StandConfig
+#[derive(Debug, Clone)]
+pub struct Script {
+    steps: Vec<Step>,
+}
+
+/// A single command in a [`Script`], and the line it was given on.
+///
+/// [`Script`]: Script
+#[derive(Debug, Clone)]
+struct Step {
+    line: usize,
+    op: Op,
+}
+
+#[derive(Debug, Clone)]
+enum Op {
+    Open(Arg<ValveHandle>),
+    Close(Arg<ValveHandle>),
+    Wait(Arg<Duration>),
+    WaitUntil {
+        condition: ScriptCondition,
+        timeout: Arg<Duration>,
+        on_timeout: OnTimeout,
+    },
+    Done,
+}
+
+/// A [`Condition`] as written in a [`Script`].
+///
+/// [`Condition`]: Condition
+/// [`Script`]: Script
+#[derive(Debug, Clone)]
+enum ScriptCondition {
+    Compare {
+        field: String,
+        comparison: Comparison,
+        value: f64,
+        unit: Option<String>,
+    },
+
+    Valve {
+        valve: Arg<ValveHandle>,
+        state: ValveState,
+    },
+}
+
+/// A [`TimeoutAction`] as written in a [`Script`], naming the sequence to run.
+///
+/// [`TimeoutAction`]: TimeoutAction
+/// [`Script`]: Script
+#[derive(Debug, Clone)]
+enum OnTimeout {
+    Abort,
+    Skip,
+    Run(String),
+}
+
+/// An argument to a command which is either given in the script or filled in when it is run.
+#[derive(Debug, Clone)]
+enum Arg<T> {
+    Given(T),
+    Param(String),
+}
+
+/// A value given for a `$parameter` when running a [`Script`].
+///
+/// [`Script`]: Script
+#[derive(Debug, Clone)]
+pub enum Param {
+    Valve(ValveHandle),
+    Duration(Duration),
+}
+
+impl Script {
+    /// Parse a [`Script`] from its text, giving the line of the first syntax error if there is
+    /// one.
+    ///
+    /// [`Script`]: Script
+    pub fn parse(text: &str) -> Result<Self, ScriptError> {
+        let mut steps: Vec<Step> = Vec::new();
+
+        for (i, line) in text.lines().enumerate() {
+            let line_number = i + 1;
+            let line = line.split('#').next().unwrap_or_default().trim();
+
+            if line.is_empty() {
+                continue;
+            }
+
+            if let Some(done) = steps.iter().find(|s| matches!(s.op, Op::Done)) {
+                return Err(ScriptError::syntax(
+                    line_number,
+                    format!("command after `done` on line {}", done.line),
+                ));
+            }
+
+            let (keyword, arg) = match line.split_once(char::is_whitespace) {
+                Some((keyword, arg)) => (keyword, arg.trim()),
+                None => (line, ""),
+            };
+
+            let op = match keyword.to_ascii_lowercase().as_str() {
+                "open" => Op::Open(parse_valve(line_number, arg)?),
+                "close" => Op::Close(parse_valve(line_number, arg)?),
+                "wait" => match arg.split_once(char::is_whitespace) {
+                    Some((until, rest)) if until.eq_ignore_ascii_case("until") => {
+                        parse_wait_until(line_number, rest.trim())?
+                    }
+                    _ => Op::Wait(parse_duration(line_number, arg)?),
+                },
+
+                "done" if arg.is_empty() => Op::Done,
+                "done" => {
+                    return Err(ScriptError::syntax(
+                        line_number,
+                        "`done` takes no arguments",
+                    ));
+                }
+
+                _ => {
+                    return Err(ScriptError::syntax(
+                        line_number,
+                        format!("unknown command `{keyword}`"),
+                    ));
+                }
+            };
+
+            steps.push(Step {
+                line: line_number,
+                op,
+            });
+        }
+
+        Ok(Self { steps })
+    }
+
+    /// Check that every valve named in the [`Script`] is defined in the given [`StandConfig`].
+    ///
+    /// [`Script`]: Script
+    /// [`StandConfig`]: StandConfig
+    pub fn validate(&self, config: &StandConfig) -> Result<(), ScriptError> {
+        for step in &self.steps {
+            match &step.op {
+                Op::Open(Arg::Given(valve)) | Op::Close(Arg::Given(valve))
+                    if config.valve(valve.name()).is_none() =>
+                {
+                    return Err(ScriptError::invalid(
+                        step.line,
+                        format!("unknown valve `{valve}`"),
+                    ));
+                }
+
+                Op::WaitUntil { condition, .. } => step.validate_condition(condition, config)?,
+
+                _ => (),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Gives the names of the sequences this [`Script`] may run, and the lines they are named on.
+    ///
+    /// [`Script`]: Script
+    fn sequence_references(&self) -> impl Iterator<Item = (usize, &str)> {
+        self.steps.iter().filter_map(|step| match &step.op {
+            Op::WaitUntil {
+                on_timeout: OnTimeout::Run(name),
+                ..
+            } => Some((step.line, name.as_str())),
+            _ => None,
+        })
+    }
+
+    /// Validate the [`Script`] and build the [`CommandSequence`] it describes, filling in its
+    /// `$parameters` from those given and building any sequence it names with `sequence`.
+    ///
+    /// [`Script`]: Script
+    /// [`CommandSequence`]: CommandSequence
+    fn to_sequence(
+        &self,
+        config: &StandConfig,
+        params: &HashMap<&str, Param>,
+        sequence: &mut dyn FnMut(&str) -> Result<CommandSequence, ScriptError>,
+    ) -> Result<CommandSequence, ScriptError> {
+        self.validate(config)?;
+
+        let mut seq = CommandSequence::new();
+
+        for step in &self.steps {
+            let command = match &step.op {
+                Op::Open(valve) => Command::OpenValve(step.valve(valve, config, params)?),
+                Op::Close(valve) => Command::CloseValve(step.valve(valve, config, params)?),
+                Op::Wait(duration) => Command::Wait(step.duration(duration, params)?),
+
+                Op::WaitUntil {
+                    condition,
+                    timeout,
+                    on_timeout,
+                } => Command::WaitUntil(WaitUntil {
+                    condition: match condition {
+                        ScriptCondition::Compare {
+                            field,
+                            comparison,
+                            value,
+                            ..
+                        } => Condition::Compare {
+                            field: field.clone(),
+                            comparison: *comparison,
+                            value: *value,
+                        },
+
+                        ScriptCondition::Valve { valve, state } => Condition::Valve {
+                            valve: step.reported_valve(valve, config, params)?,
+                            state: *state,
+                        },
+                    },
+
+                    timeout: step.duration(timeout, params)?,
+
+                    on_timeout: match on_timeout {
+                        OnTimeout::Abort => TimeoutAction::Abort,
+                        OnTimeout::Skip => TimeoutAction::Skip,
+                        OnTimeout::Run(name) => {
+                            TimeoutAction::Run(sequence(name).map_err(|e| {
+                                ScriptError::invalid(
+                                    step.line,
+                                    format!("in sequence '{name}': {e}"),
+                                )
+                            })?)
+                        }
+                    },
+                }),
+
+                Op::Done => Command::Done,
+            };
+
+            seq = seq.then(command);
+        }
+
+        Ok(seq)
+    }
+}
+
+impl Step {
+    /// Check that a condition's field is one the stand sends, and that any unit given matches the
+    /// field's.
+    fn validate_condition(
+        &self,
+        condition: &ScriptCondition,
+        config: &StandConfig,
+    ) -> Result<(), ScriptError> {
+        match condition {
+            ScriptCondition::Compare { field, unit, .. } => {
+                if !config.has_field(field) {
+                    return Err(ScriptError::invalid(
+                        self.line,
+                        format!("unknown field `{field}`"),
+                    ));
+                }
+
+                match (unit, config.unit(field)) {
+                    (Some(unit), Some(field_unit)) if unit != field_unit => {
+                        Err(ScriptError::invalid(
+                            self.line,
+                            format!("`{field}` is in {field_unit}, not {unit}"),
+                        ))
+                    }
+
+                    (Some(unit), None) => Err(ScriptError::invalid(
+                        self.line,
+                        format!("`{field}` has no unit, not {unit}"),
+                    )),
+
+                    _ => Ok(()),
+                }
+            }
+
+            ScriptCondition::Valve {
+                valve: Arg::Given(valve),
+                ..
+            } if !is_reported_valve(config, valve) => Err(ScriptError::invalid(
+                self.line,
+                format!("the stand does not report the state of `{valve}`"),
+            )),
+
+            ScriptCondition::Valve { .. } => Ok(()),
+        }
+    }
+
+    /// Resolve a valve argument, checking that the stand reports its state.
+    fn reported_valve(
+        &self,
+        arg: &Arg<ValveHandle>,
+        config: &StandConfig,
+        params: &HashMap<&str, Param>,
+    ) -> Result<ValveHandle, ScriptError> {
+        let valve = self.valve(arg, config, params)?;
+
+        if is_reported_valve(config, &valve) {
+            Ok(valve)
+        } else {
+            Err(ScriptError::invalid(
+                self.line,
+                format!("the stand does not report the state of `{valve}`"),
+            ))
+        }
+    }
+
+    /// Resolve a valve argument, checking that a parameter was given as a valve on the stand.
+    fn valve(
+        &self,
+        arg: &Arg<ValveHandle>,
+        config: &StandConfig,
+        params: &HashMap<&str, Param>,
+    ) -> Result<ValveHandle, ScriptError> {
+        match arg {
+            Arg::Given(valve) => Ok(valve.clone()),
+
+            Arg::Param(name) => match params.get(name.as_str()) {
+                Some(Param::Valve(valve)) if config.valve(valve.name()).is_some() => {
+                    Ok(valve.clone())
+                }
+
+                Some(Param::Valve(valve)) => Err(ScriptError::invalid(
+                    self.line,
+                    format!("`${name}` is unknown valve `{valve}`"),
+                )),
+
+                Some(Param::Duration(_)) => Err(ScriptError::invalid(
+                    self.line,
+                    format!("`${name}` is a duration, not a valve"),
+                )),
+
+                None => Err(ScriptError::invalid(
+                    self.line,
+                    format!("no value given for `${name}`"),
+                )),
+            },
+        }
+    }
+
+    /// Resolve a duration argument.
+    fn duration(
+        &self,
+        arg: &Arg<Duration>,
+        params: &HashMap<&str, Param>,
+    ) -> Result<Duration, ScriptError> {
+        match arg {
+            Arg::Given(duration) => Ok(*duration),
+
+            Arg::Param(name) => match params.get(name.as_str()) {
+                Some(Param::Duration(duration)) if *duration <= MAX_WAIT => Ok(*duration),
+
+                Some(Param::Duration(_)) => Err(ScriptError::invalid(
+                    self.line,
+                    format!("`${name}` is longer than {}s", MAX_WAIT.as_secs()),
+                )),
+
+                Some(Param::Valve(_)) => Err(ScriptError::invalid(
+                    self.line,
+                    format!("`${name}` is a valve, not a duration"),
+                )),
+
+                None => Err(ScriptError::invalid(
+                    self.line,
+                    format!("no value given for `${name}`"),
+                )),
+            },
+        }
+    }
+}
+
+fn is_reported_valve(config: &StandConfig, valve: &ValveHandle) -> bool {
+    config
+        .valve(valve.name())
+        .is_some_and(|v| v.kind == ValveKind::Reported)
+}
+
+/// Parse the rest of a `wait until <condition> timeout <duration> else <action>` command.
+fn parse_wait_until(line: usize, arg: &str) -> Result<Op, ScriptError> {
+    let Some((condition, rest)) = rsplit_keyword(arg, "timeout") else {
+        return Err(ScriptError::syntax(line, "expected `timeout <duration>`"));
+    };
+
+    let Some((timeout, action)) = rsplit_keyword(rest, "else") else {
+        return Err(ScriptError::syntax(
+            line,
+            "expected `else abort`, `else skip`, or `else run <sequence>`",
+        ));
+    };
+
+    let on_timeout = match action.split_once(char::is_whitespace) {
+        _ if action.eq_ignore_ascii_case("abort") => OnTimeout::Abort,
+        _ if action.eq_ignore_ascii_case("skip") => OnTimeout::Skip,
+        Some((run, name)) if run.eq_ignore_ascii_case("run") => OnTimeout::Run(name.trim().into()),
+        _ => {
+            return Err(ScriptError::syntax(
+                line,
+                format!("unknown timeout action `{action}`"),
+            ));
+        }
+    };
+
+    Ok(Op::WaitUntil {
+        condition: parse_condition(line, condition)?,
+        timeout: parse_duration(line, timeout)?,
+        on_timeout,
+    })
+}
+
+/// Split the given text around the last occurence of the given keyword as a whole word, trimming
+/// either side.
+fn rsplit_keyword<'a>(text: &'a str, keyword: &str) -> Option<(&'a str, &'a str)> {
+    let lower = text.to_ascii_lowercase();
+
+    lower.rmatch_indices(keyword).find_map(|(i, _)| {
+        let before = &text[..i];
+        let after = &text[i + keyword.len()..];
+
+        let is_word = before.ends_with(char::is_whitespace)
+            && (after.is_empty() || after.starts_with(char::is_whitespace));
+
+        is_word.then(|| (before.trim(), after.trim()))
+    })
+}
+
+/// Parse a condition such as `NPT1 > 400 psi` or `NP2 is open`.
+fn parse_condition(line: usize, text: &str) -> Result<ScriptCondition, ScriptError> {
+    if let Some((valve, state)) = rsplit_keyword(text, "is") {
+        let state = match state.to_ascii_lowercase().as_str() {
+            "open" => ValveState::Open,
+            "closed" => ValveState::Closed,
+            _ => {
+                return Err(ScriptError::syntax(
+                    line,
+                    format!("expected `open` or `closed`, found `{state}`"),
+                ));
+            }
+        };
+
+        return Ok(ScriptCondition::Valve {
+            valve: parse_valve(line, valve)?,
+            state,
+        });
+    }
+
+    const COMPARISONS: [(&str, Comparison); 6] = [
+        (">=", Comparison::GreaterOrEqual),
+        ("<=", Comparison::LessOrEqual),
+        ("==", Comparison::Equal),
+        ("!=", Comparison::NotEqual),
+        (">", Comparison::Greater),
+        ("<", Comparison::Less),
+    ];
+
+    let Some((field, comparison, value)) = COMPARISONS.iter().find_map(|(op, comparison)| {
+        text.split_once(op)
+            .map(|(field, value)| (field.trim(), *comparison, value.trim()))
+    }) else {
+        return Err(ScriptError::syntax(
+            line,
+            format!("expected a comparison or `is open`/`is closed` in `{text}`"),
+        ));
+    };
+
+    if field.is_empty() {
+        return Err(ScriptError::syntax(line, "expected a field name"));
+    }
+
+    let (number, unit) = match value.split_once(char::is_whitespace) {
+        Some((number, unit)) => (number, Some(unit.trim().to_string())),
+        None => (value, None),
+    };
+
+    let Ok(value) = number.parse::<f64>() else {
+        return Err(ScriptError::syntax(
+            line,
+            format!("invalid number `{number}`"),
+        ));
+    };
+
+    Ok(ScriptCondition::Compare {
+        field: field.to_string(),
+        comparison,
+        value,
+        unit,
+    })
+}
+
+/// Parse the name of a `$parameter`, if the given argument is one.
+fn parse_param(line: usize, arg: &str) -> Result<Option<String>, ScriptError> {
+    let Some(name) = arg.strip_prefix('$') else {
+        return Ok(None);
+    };
+
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err(ScriptError::syntax(
+            line,
+            format!("invalid parameter name `{arg}`"),
+        ));
+    }
+
+    Ok(Some(name.to_string()))
+}
+
+fn parse_valve(line: usize, arg: &str) -> Result<Arg<ValveHandle>, ScriptError> {
+    if arg.is_empty() {
+        return Err(ScriptError::syntax(line, "expected a valve name"));
+    }
+
+    Ok(match parse_param(line, arg)? {
+        Some(param) => Arg::Param(param),
+        None => Arg::Given(ValveHandle::new(arg)),
+    })
+}
+
+/// Parse a duration such as `500ms`, `1.5s`, or `2`, which is taken to be in seconds. Durations
+/// longer than [`MAX_WAIT`] are rejected.
+///
+/// [`MAX_WAIT`]: MAX_WAIT
+fn parse_duration(line: usize, arg: &str) -> Result<Arg<Duration>, ScriptError> {
+    if arg.is_empty() {
+        return Err(ScriptError::syntax(line, "expected a duration"));
+    }
+
+    if let Some(param) = parse_param(line, arg)? {
+        return Ok(Arg::Param(param));
+    }
+
+    let (number, scale) = if let Some(ms) = arg.strip_suffix("ms") {
+        (ms, 1e-3)
+    } else if let Some(s) = arg.strip_suffix('s') {
+        (s, 1.0)
+    } else {
+        (arg, 1.0)
+    };
+
+    let duration = number
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .and_then(|n| Duration::try_from_secs_f64(n * scale).ok());
+
+    match duration {
+        Some(duration) if duration <= MAX_WAIT => Ok(Arg::Given(duration)),
+        Some(_) => Err(ScriptError::syntax(
+            line,
+            format!("duration `{arg}` is longer than {}s", MAX_WAIT.as_secs()),
+        )),
+        None => Err(ScriptError::syntax(
+            line,
+            format!("invalid duration `{arg}`"),
+        )),
+    }
+}
+
+/// The [`Script`]s available to run, each loaded from a file in a directory and named by its
+/// file stem. Scripts which failed to load are kept along with their error so that it may be
+/// shown to the operator.
+///
+/// [`Script`]: Script
+#[derive(Debug, Default)]
+pub struct ScriptLibrary {
+    /// Directory the scripts were loaded from, or [`None`] if they are built in.
+    dir: Option<PathBuf>,
+    scripts: Vec<(String, Result<Script, ScriptError>)>,
+}
+
+impl ScriptLibrary {
+    /// Load every script in the given directory, validating each against the given
+    /// [`StandConfig`].
+    ///
+    /// [`StandConfig`]: StandConfig
+    pub fn load<P>(dir: P, config: &StandConfig) -> io::Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let mut paths: Vec<PathBuf> = fs::read_dir(dir.as_ref())?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| path.extension() == Some(OsStr::new(SCRIPT_EXTENSION)))
+            .collect();
+        paths.sort();
+
+        let scripts = paths
+            .into_iter()
+            .map(|path| {
+                let name = path
+                    .file_stem()
+                    .map(|s| s.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+
+                let script = fs::read_to_string(&path)
+                    .map_err(ScriptError::Io)
+                    .and_then(|text| Script::parse(&text))
+                    .and_then(|script| script.validate(config).map(|()| script));
+
+                (name, script)
+            })
+            .collect();
+
+        Ok(Self::new(Some(dir.as_ref().to_path_buf()), scripts))
+    }
+
+    /// The [`Script`]s built into the console, validated against the given [`StandConfig`].
+    ///
+    /// [`Script`]: Script
+    /// [`StandConfig`]: StandConfig
+    pub fn built_in(config: &StandConfig) -> Self {
+        let scripts = BUILT_IN_SCRIPTS
+            .into_iter()
+            .map(|(name, text)| {
+                let script =
+                    Script::parse(text).and_then(|script| script.validate(config).map(|()| script));
+
+                (name.to_string(), script)
+            })
+            .collect();
+
+        Self::new(None, scripts)
+    }
+
+    /// Create a [`ScriptLibrary`] of the given scripts, checking that every sequence they name
+    /// exists and logging those which failed to load.
+    ///
+    /// [`ScriptLibrary`]: ScriptLibrary
+    fn new(dir: Option<PathBuf>, mut scripts: Vec<(String, Result<Script, ScriptError>)>) -> Self {
+        let names: HashSet<String> = scripts.iter().map(|(name, _)| name.clone()).collect();
+
+        for (_, script) in scripts.iter_mut() {
+            let unknown = script.as_ref().ok().and_then(|script| {
+                script
+                    .sequence_references()
+                    .find(|(_, name)| !names.contains(*name))
+                    .map(|(line, name)| {
+                        ScriptError::invalid(line, format!("unknown sequence `{name}`"))
+                    })
+            });
+
+            if let Some(e) = unknown {
+                *script = Err(e);
+            }
+        }
+
+        for (name, script) in &scripts {
+            if let Err(e) = script {
+                log::error!("Sequence '{name}' failed to load: {e}");
+            }
+        }
+
+        Self { dir, scripts }
+    }
+
+    /// Load the scripts again from the directory they were loaded from, if any.
+    pub fn reload(&mut self, config: &StandConfig) -> io::Result<()> {
+        if let Some(dir) = &self.dir {
+            *self = Self::load(dir.clone(), config)?;
+        }
+
+        Ok(())
+    }
+
+    /// Gives every script's name along with the script, or the error it failed to load with.
+    pub fn scripts(&self) -> impl Iterator<Item = (&str, Result<&Script, &ScriptError>)> {
+        self.scripts
+            .iter()
+            .map(|(name, script)| (name.as_str(), script.as_ref()))
+    }
+
+    /// Gives the script with the given name, or the error it failed to load with. Gives
+    /// [`None`] if there is no such script.
+    pub fn get(&self, name: &str) -> Option<Result<&Script, &ScriptError>> {
+        self.scripts
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, script)| script.as_ref())
+    }
+
+    /// Validate the script with the given name and build the [`CommandSequence`] it describes,
+    /// filling in its `$parameters` from those given.
+    ///
+    /// [`CommandSequence`]: CommandSequence
+    pub fn sequence(
+        &self,
+        name: &str,
+        config: &StandConfig,
+        params: &HashMap<&str, Param>,
+    ) -> Result<CommandSequence, ScriptError> {
+        self.sequence_within(name, config, params, &mut Vec::new())
+    }
+
+    /// Build the sequence with the given name, as named from within each of the given sequences.
+    fn sequence_within(
+        &self,
+        name: &str,
+        config: &StandConfig,
+        params: &HashMap<&str, Param>,
+        within: &mut Vec<String>,
+    ) -> Result<CommandSequence, ScriptError> {
+        if within.iter().any(|n| n == name) {
+            return Err(ScriptError::Recursive(name.to_string()));
+        }
+
+        let script = match self.get(name) {
+            Some(Ok(script)) => script,
+            Some(Err(_)) => return Err(ScriptError::FailedToLoad(name.to_string())),
+            None => return Err(ScriptError::NotFound(name.to_string())),
+        };
+
+        within.push(name.to_string());
+        let seq = script.to_sequence(config, params, &mut |n| {
+            self.sequence_within(n, config, params, within)
+        });
+        within.pop();
+
+        seq
+    }
+}
+
+/// Errors from loading, validating, or running a [`Script`].
+///
+/// [`Script`]: Script
+#[derive(Debug)]
+pub enum ScriptError {
+    Io(io::Error),
+
+    /// The script's text could not be parsed.
+    Syntax {
+        line: usize,
+        message: String,
+    },
+
+    /// The script parsed, but cannot be run on the stand as given.
+    Invalid {
+        line: usize,
+        message: String,
+    },
+
+    NotFound(String),
+    FailedToLoad(String),
+
+    /// The sequence ends up running itself.
+    Recursive(String),
+}
+
+impl ScriptError {
+    fn syntax(line: usize, message: impl Into<String>) -> Self {
+        ScriptError::Syntax {
+            line,
+            message: message.into(),
+        }
+    }
+
+    fn invalid(line: usize, message: impl Into<String>) -> Self {
+        ScriptError::Invalid {
+            line,
+            message: message.into(),
+        }
+    }
+}
+
+impl Display for ScriptError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ScriptError::Io(e) => write!(f, "Could not read sequence: {e}"),
+            ScriptError::Syntax { line, message } => {
+                write!(f, "Syntax error on line {line}: {message}")
+            }
+            ScriptError::Invalid { line, message } => write!(f, "Error on line {line}: {message}"),
+            ScriptError::NotFound(name) => write!(f, "No sequence named '{name}'"),
+            ScriptError::FailedToLoad(name) => write!(f, "Sequence '{name}' failed to load"),
+            ScriptError::Recursive(name) => write!(f, "Sequence '{name}' runs itself"),
+        }
+    }
+}
+
+impl Error for ScriptError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The parameters the console fills in when running Fire.
+    fn fire_params(fire_time: Duration) -> HashMap<&'static str, Param> {
+        HashMap::from([
+            ("igniter", Param::Valve(ValveHandle::new("MCH"))),
+            ("engine", Param::Valve(ValveHandle::new("EN1"))),
+            ("fire_time", Param::Duration(fire_time)),
+        ])
+    }
+
+    /// The line of the given error, if it has one.
+    fn error_line(e: &ScriptError) -> Option<usize> {
+        match e {
+            ScriptError::Syntax { line, .. } | ScriptError::Invalid { line, .. } => Some(*line),
+            _ => None,
+        }
+    }
+
+    /// The duration waited by a script of a single `wait`.
+    fn wait(text: &str) -> Result<Duration, ScriptError> {
+        let script = Script::parse(text)?;
+
+        match &script.steps[..] {
+            [
+                Step {
+                    op: Op::Wait(Arg::Given(duration)),
+                    ..
+                },
+            ] => Ok(*duration),
+            steps => panic!("expected a single wait, parsed {steps:?}"),
+        }
+    }
+
+    #[test]
+    fn bundled_sequences_load_and_build() {
+        let config = StandConfig::built_in();
+        let params = fire_params(Duration::from_secs(2));
+
+        for library in [
+            ScriptLibrary::built_in(&config),
+            ScriptLibrary::load("sequences", &config).unwrap(),
+        ] {
+            let names: Vec<&str> = library.scripts().map(|(name, _)| name).collect();
+            assert_eq!(names, ["abort", "depressurize", "fire", "pressurize"]);
+
+            for name in names {
+                library.sequence(name, &config, &params).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn errors_give_their_line() {
+        let syntax = [
+            ("open NP1\n\n# comment\nvent NP2", 4),
+            ("open NP1\ndone\nclose NP1", 3),
+            ("done now", 1),
+            ("open\n", 1),
+            ("open $bad-name", 1),
+            ("wait\n", 1),
+            ("close NP1\nwait until NPT1 > 400 psi\n", 2),
+            ("wait until NPT1 > 400 timeout 1s else explode", 1),
+            ("wait until NPT1 400 timeout 1s else skip", 1),
+            ("wait until NPT1 > lots timeout 1s else skip", 1),
+            ("wait until NP1 is ajar timeout 1s else skip", 1),
+        ];
+
+        for (text, line) in syntax {
+            let e = Script::parse(text).unwrap_err();
+            assert!(matches!(e, ScriptError::Syntax { .. }), "{text:?}: {e}");
+            assert_eq!(error_line(&e), Some(line), "{text:?}: {e}");
+        }
+
+        let config = StandConfig::built_in();
+        let invalid = [
+            ("open NP1\nopen XX9", 2),
+            ("wait 1s\nwait until NOPE > 1 timeout 1s else skip", 2),
+            ("wait until NPT1 > 1 kg timeout 1s else skip", 1),
+            ("wait until EN1 is open timeout 1s else skip", 1),
+        ];
+
+        for (text, line) in invalid {
+            let e = Script::parse(text).unwrap().validate(&config).unwrap_err();
+            assert!(matches!(e, ScriptError::Invalid { .. }), "{text:?}: {e}");
+            assert_eq!(error_line(&e), Some(line), "{text:?}: {e}");
+        }
+    }
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(wait("wait 500ms").unwrap(), Duration::from_millis(500));
+        assert_eq!(wait("WAIT 1.5s").unwrap(), Duration::from_millis(1500));
+        assert_eq!(wait("wait 2 # seconds").unwrap(), Duration::from_secs(2));
+        assert_eq!(wait("wait 86400s").unwrap(), MAX_WAIT);
+    }
+
+    #[test]
+    fn rejects_bad_durations() {
+        for duration in [
+            "-1s", "NaN", "infs", "1e300s", "1e19", "86401", "2 days", "s", "ms",
+        ] {
+            let text = format!("open NP1\nwait {duration}");
+            let e = Script::parse(&text).unwrap_err();
+            assert_eq!(error_line(&e), Some(2), "{duration}: {e}");
+        }
+
+        let e = Script::parse("wait until NPT1 > 1 timeout 1e300s else abort").unwrap_err();
+        assert_eq!(error_line(&e), Some(1));
+    }
+
+    #[test]
+    fn rejects_bad_parameters() {
+        let config = StandConfig::built_in();
+        let library = ScriptLibrary::built_in(&config);
+
+        let e = library
+            .sequence("fire", &config, &fire_params(Duration::MAX))
+            .unwrap_err();
+        assert!(matches!(e, ScriptError::Invalid { line: 7, .. }), "{e}");
+
+        let mut params = fire_params(Duration::from_secs(2));
+        params.insert("engine", Param::Duration(Duration::from_secs(1)));
+        let e = library.sequence("fire", &config, &params).unwrap_err();
+        assert!(matches!(e, ScriptError::Invalid { line: 6, .. }), "{e}");
+
+        params.remove("engine");
+        let e = library.sequence("fire", &config, &params).unwrap_err();
+        assert!(matches!(e, ScriptError::Invalid { line: 6, .. }), "{e}");
+    }
+
+    #[test]
+    fn checks_named_sequences() {
+        let config = StandConfig::built_in();
+        let script = |text: &str| Script::parse(text).unwrap();
+        let run = |name: &str| format!("wait until NPT1 > 1 timeout 1s else run {name}");
+
+        let library = ScriptLibrary::new(
+            None,
+            vec![
+                ("loop".to_string(), Ok(script(&run("loop")))),
+                (
+                    "missing".to_string(),
+                    Ok(script(&format!("open NP1\n{}", run("nope")))),
+                ),
+            ],
+        );
+
+        // the sequence named by the timeout action is what runs itself
+        let e = library
+            .sequence("loop", &config, &HashMap::new())
+            .unwrap_err();
+        assert!(matches!(e, ScriptError::Invalid { line: 1, .. }), "{e}");
+        assert!(
+            e.to_string().ends_with("Sequence 'loop' runs itself"),
+            "{e}"
+        );
+        assert!(matches!(
+            library.get("missing"),
+            Some(Err(ScriptError::Invalid { line: 2, .. }))
+        ));
+        assert!(matches!(
+            library.sequence("missing", &config, &HashMap::new()),
+            Err(ScriptError::FailedToLoad(_))
+        ));
+        assert!(matches!(
+            library.sequence("absent", &config, &HashMap::new()),
+            Err(ScriptError::NotFound(_))
+        ));
+    }
+}
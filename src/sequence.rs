@@ -1,10 +1,37 @@
+use crate::{
+    event::{EventKind, StandEvent},
+    serial::{SensorValue, Telemetry, ValveCommand},
+    stand::ValveState,
+};
 use std::{
+    error::Error,
     fmt::Display,
-    sync::mpsc::{SendError, Sender},
-    thread::{self, JoinHandle},
-    time::Duration,
+    sync::{
+        Arc, Condvar, Mutex, MutexGuard,
+        mpsc::{SendError, Sender},
+    },
+    thread,
+    time::{Duration, Instant, SystemTime},
 };
 
+/// How long [`Command::Done`] waits before finishing the sequence.
+///
+/// [`Command::Done`]: Command::Done
+const DONE_WAIT: Duration = Duration::from_millis(500);
+
+/// How often a [`Command::WaitUntil`] checks its [`Condition`].
+///
+/// [`Command::WaitUntil`]: Command::WaitUntil
+/// [`Condition`]: Condition
+const CONDITION_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Longest a [`Command::Wait`] or [`Command::WaitUntil`] may be given to wait for. Scripts and
+/// parameters asking for longer are rejected.
+///
+/// [`Command::Wait`]: Command::Wait
+/// [`Command::WaitUntil`]: Command::WaitUntil
+pub const MAX_WAIT: Duration = Duration::from_secs(24 * 60 * 60);
+
 /// A sequence of [`Command`]s which are executable asyncronously.
 ///
 /// [`Command`]: Command
@@ -36,99 +63,805 @@ impl CommandSequence {
         self
     }
 
-    /// Run the [`CommandSequence`] by running each of its [`Command`]s.
+    /// Run the [`CommandSequence`] by running each of its [`Command`]s, checking any
+    /// [`Command::WaitUntil`] against the given [`Telemetry`].
     ///
     /// [`CommandSequence`]: CommandSequence
     /// [`Command`]: Command
-    pub fn run(self, mut tx: Sender<Vec<u8>>) -> Result<(), SendError<Vec<u8>>> {
-        for command in self.commands {
-            command.run(&mut tx)?
+    /// [`Command::WaitUntil`]: Command::WaitUntil
+    /// [`Telemetry`]: Telemetry
+    pub fn run(
+        self,
+        tx: Sender<ValveCommand>,
+        telemetry: Telemetry,
+        events: Sender<StandEvent>,
+    ) -> Result<(), SequenceError> {
+        SequenceHandle::new(&self).run(self, &tx, &telemetry, &events)
+    }
+
+    /// Run the [`CommandSequence`] by running each of its [`Command`]s in order in a new thread.
+    /// The returned [`SequenceHandle`] may be used to pause or abort the sequence, in which case
+    /// the given abort sequence is run in its place. Each step, and the state the sequence ends in,
+    /// is sent as a [`StandEvent`].
+    ///
+    /// [`CommandSequence`]: CommandSequence
+    /// [`Command`]: Command
+    /// [`SequenceHandle`]: SequenceHandle
+    /// [`StandEvent`]: StandEvent
+    pub fn run_par(
+        self,
+        tx: Sender<ValveCommand>,
+        telemetry: Telemetry,
+        events: Sender<StandEvent>,
+        abort_sequence: Option<CommandSequence>,
+    ) -> SequenceHandle {
+        let handle = SequenceHandle::new(&self);
+        let control = handle.clone();
+
+        thread::spawn(move || {
+            let result = match control.run(self, &tx, &telemetry, &events) {
+                Err(SequenceError::Aborted) => match abort_sequence {
+                    Some(abort_sequence)
+                        if control.start_abort_sequence(&abort_sequence, true).is_ok() =>
+                    {
+                        log::warn!("Sequence aborted, running abort sequence");
+                        control.run(abort_sequence, &tx, &telemetry, &events)
+                    }
+                    _ => Err(SequenceError::Aborted),
+                },
+
+                result => result,
+            };
+
+            let state = match result {
+                Ok(()) => SequenceState::Finished,
+                Err(SequenceError::Aborted) => {
+                    log::warn!("Sequence aborted!");
+                    SequenceState::Aborted
+                }
+                Err(e @ SequenceError::TimedOut(_)) => {
+                    log::warn!("Sequence ran its fallback: {e}");
+                    SequenceState::FellBack
+                }
+                Err(e) => {
+                    log::error!("Sequence failed: {e}");
+                    SequenceState::Failed
+                }
+            };
+
+            control.finish(state);
+            let _ = events.send(StandEvent::now(EventKind::SequenceEnded(state)));
+        });
+
+        handle
+    }
+}
+
+/// The state of a [`CommandSequence`] run with [`CommandSequence::run_par`].
+///
+/// [`CommandSequence`]: CommandSequence
+/// [`CommandSequence::run_par`]: CommandSequence::run_par
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SequenceState {
+    Running,
+    Paused,
+
+    /// The sequence was aborted and its abort sequence is now running.
+    Aborting,
+
+    Finished,
+    Aborted,
+
+    /// A wait in the sequence timed out, and its fallback sequence ran in place of the rest.
+    FellBack,
+
+    /// The sequence could not send a command.
+    Failed,
+}
+
+impl SequenceState {
+    /// Whether a sequence in this state may still send commands.
+    pub fn is_active(self) -> bool {
+        matches!(
+            self,
+            SequenceState::Running | SequenceState::Paused | SequenceState::Aborting
+        )
+    }
+}
+
+impl Display for SequenceState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SequenceState::Running => write!(f, "Running"),
+            SequenceState::Paused => write!(f, "Paused"),
+            SequenceState::Aborting => write!(f, "Aborting"),
+            SequenceState::Finished => write!(f, "Finished"),
+            SequenceState::Aborted => write!(f, "Aborted"),
+            SequenceState::FellBack => write!(f, "Ran fallback"),
+            SequenceState::Failed => write!(f, "Failed"),
         }
+    }
+}
 
-        Ok(())
+/// Reasons a [`CommandSequence`] may stop before finishing.
+///
+/// [`CommandSequence`]: CommandSequence
+#[derive(Debug)]
+pub enum SequenceError {
+    Send(SendError<ValveCommand>),
+    Aborted,
+
+    /// A [`Command::WaitUntil`] timed out, and its fallback sequence was run in place of the rest
+    /// of the sequence.
+    ///
+    /// [`Command::WaitUntil`]: Command::WaitUntil
+    TimedOut(Condition),
+}
+
+impl Display for SequenceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SequenceError::Send(e) => write!(f, "Could not send command: {e}"),
+            SequenceError::Aborted => write!(f, "Sequence was aborted"),
+            SequenceError::TimedOut(condition) => {
+                write!(f, "Timed out waiting until {condition}")
+            }
+        }
     }
+}
 
-    /// Run the [`CommandSequence`] by running each of its [`Command`]s in order in a new thread.
+impl Error for SequenceError {}
+
+/// A handle to a [`CommandSequence`] running in another thread, used to pause, resume, or abort it
+/// and to see its progress. Aborting cancels any [`Command::Wait`] immediately.
+///
+/// [`CommandSequence`]: CommandSequence
+/// [`Command::Wait`]: Command::Wait
+#[derive(Debug, Clone)]
+pub struct SequenceHandle {
+    control: Arc<(Mutex<SequenceStatus>, Condvar)>,
+    started: SystemTime,
+}
+
+#[derive(Debug)]
+struct SequenceStatus {
+    state: SequenceState,
+
+    /// Index of the [`Command`] currently running.
+    ///
+    /// [`Command`]: Command
+    step: usize,
+
+    /// How long each [`Command`] of the running sequence waits for.
+    ///
+    /// [`Command`]: Command
+    step_durations: Vec<Duration>,
+
+    wait: WaitStatus,
+
+    /// Whether the abort sequence should be run once the sequence has stopped.
+    run_abort_sequence: bool,
+
+    /// Whether the thread running the sequence has finished with it, so will send no more
+    /// commands.
+    stopped: bool,
+}
+
+/// Progress through the current [`Command::Wait`], if any.
+///
+/// [`Command::Wait`]: Command::Wait
+#[derive(Debug, Clone, Copy)]
+enum WaitStatus {
+    None,
+    Until(Instant),
+    Paused(Duration),
+}
+
+impl SequenceHandle {
+    fn new(seq: &CommandSequence) -> Self {
+        let status = SequenceStatus {
+            state: SequenceState::Running,
+            step: 0,
+            step_durations: step_durations(seq),
+            wait: WaitStatus::None,
+            run_abort_sequence: false,
+            stopped: false,
+        };
+
+        Self {
+            control: Arc::new((Mutex::new(status), Condvar::new())),
+            started: SystemTime::now(),
+        }
+    }
+
+    fn status(&self) -> MutexGuard<'_, SequenceStatus> {
+        self.control
+            .0
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    pub fn state(&self) -> SequenceState {
+        self.status().state
+    }
+
+    /// When the sequence was started, on the host's clock like the times fields are stamped
+    /// with.
+    pub fn started(&self) -> SystemTime {
+        self.started
+    }
+
+    /// Index of the [`Command`] currently running, and the total number of [`Command`]s.
+    ///
+    /// [`Command`]: Command
+    pub fn step(&self) -> (usize, usize) {
+        let status = self.status();
+        (status.step, status.step_durations.len())
+    }
+
+    /// Time left until the sequence finishes if it is not paused, being the rest of the current
+    /// wait and every wait after it.
+    pub fn time_remaining(&self) -> Duration {
+        let status = self.status();
+
+        if !status.state.is_active() {
+            return Duration::ZERO;
+        }
+
+        let current = match status.wait {
+            WaitStatus::None => Duration::ZERO,
+            WaitStatus::Until(deadline) => deadline.saturating_duration_since(Instant::now()),
+            WaitStatus::Paused(remaining) => remaining,
+        };
+
+        let rest: Duration = status.step_durations.iter().skip(status.step + 1).sum();
+
+        current + rest
+    }
+
+    /// Pause the sequence, freezing any wait in progress and sending no more commands until
+    /// resumed.
+    pub fn pause(&self) {
+        let mut status = self.status();
+
+        if status.state == SequenceState::Running {
+            status.state = SequenceState::Paused;
+
+            if let WaitStatus::Until(deadline) = status.wait {
+                status.wait =
+                    WaitStatus::Paused(deadline.saturating_duration_since(Instant::now()));
+            }
+
+            self.control.1.notify_all();
+        }
+    }
+
+    pub fn resume(&self) {
+        let mut status = self.status();
+
+        if status.state == SequenceState::Paused {
+            status.state = SequenceState::Running;
+            self.control.1.notify_all();
+        }
+    }
+
+    /// Stop the sequence, then run its abort sequence if it has one.
+    pub fn abort(&self) {
+        self.stop(true);
+    }
+
+    /// Stop the sequence without running its abort sequence, including stopping the abort
+    /// sequence if it is already running.
+    pub fn cancel(&self) {
+        self.stop(false);
+    }
+
+    fn stop(&self, run_abort_sequence: bool) {
+        let mut status = self.status();
+
+        let stoppable = match status.state {
+            SequenceState::Running | SequenceState::Paused => true,
+            SequenceState::Aborting => !run_abort_sequence,
+            _ => false,
+        };
+
+        if stoppable {
+            status.state = SequenceState::Aborted;
+            status.run_abort_sequence = run_abort_sequence;
+            self.control.1.notify_all();
+        } else if !run_abort_sequence {
+            // the sequence may have been aborted but not yet started its abort sequence
+            status.run_abort_sequence = false;
+        }
+    }
+
+    /// Block until the thread running the sequence has stopped, and so will send no more
+    /// commands, or until the given timeout passes. Gives whether it stopped.
+    pub fn wait_stopped(&self, timeout: Duration) -> bool {
+        let (status, _) = self
+            .control
+            .1
+            .wait_timeout_while(self.status(), timeout, |status| !status.stopped)
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        status.stopped
+    }
+
+    /// Run each of the given [`CommandSequence`]'s [`Command`]s under the control of this
+    /// [`SequenceHandle`], sending a [`StandEvent`] as each starts.
     ///
     /// [`CommandSequence`]: CommandSequence
     /// [`Command`]: Command
-    pub fn run_par(self, tx: Sender<Vec<u8>>) -> JoinHandle<Result<(), SendError<Vec<u8>>>> {
-        thread::spawn(move || self.run(tx))
+    /// [`SequenceHandle`]: SequenceHandle
+    /// [`StandEvent`]: StandEvent
+    fn run(
+        &self,
+        seq: CommandSequence,
+        tx: &Sender<ValveCommand>,
+        telemetry: &Telemetry,
+        events: &Sender<StandEvent>,
+    ) -> Result<(), SequenceError> {
+        for (i, command) in seq.commands.into_iter().enumerate() {
+            // The lock is held until any command is sent, so that once the sequence is stopped it
+            // cannot send another.
+            let mut status = self.wait_while_paused(self.status())?;
+            status.step = i;
+
+            let _ = events.send(StandEvent::now(EventKind::SequenceStep(
+                command.to_string(),
+            )));
+
+            match command {
+                Command::OpenValve(valve_handle) => tx
+                    .send(ValveCommand::Open(valve_handle.0))
+                    .map_err(SequenceError::Send)?,
+
+                Command::CloseValve(valve_handle) => tx
+                    .send(ValveCommand::Close(valve_handle.0))
+                    .map_err(SequenceError::Send)?,
+
+                Command::Wait(duration) => {
+                    drop(status);
+                    self.wait(duration, None)?;
+                }
+
+                Command::WaitUntil(wait) => {
+                    drop(status);
+
+                    if self.wait(wait.timeout, Some((&wait.condition, telemetry)))? {
+                        continue;
+                    }
+
+                    log::warn!(
+                        "Timed out after {}s waiting until {}",
+                        wait.timeout.as_secs_f64(),
+                        wait.condition
+                    );
+                    let _ = events.send(StandEvent::now(EventKind::SequenceTimedOut(
+                        wait.condition.to_string(),
+                    )));
+
+                    match wait.on_timeout {
+                        TimeoutAction::Skip => (),
+
+                        TimeoutAction::Abort => {
+                            self.abort();
+                            return Err(SequenceError::Aborted);
+                        }
+
+                        TimeoutAction::Run(fallback) => {
+                            log::warn!("Running fallback sequence in place of the rest");
+                            self.start_abort_sequence(&fallback, false)?;
+                            self.run(fallback, tx, telemetry, events)?;
+                            return Err(SequenceError::TimedOut(wait.condition));
+                        }
+                    }
+                }
+
+                Command::Done => {
+                    drop(status);
+                    self.wait(DONE_WAIT, None)?;
+                    log::info!("Finished sequence!");
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Block until the sequence is no longer paused.
+    fn wait_while_paused<'a>(
+        &self,
+        mut status: MutexGuard<'a, SequenceStatus>,
+    ) -> Result<MutexGuard<'a, SequenceStatus>, SequenceError> {
+        loop {
+            match status.state {
+                SequenceState::Aborted => return Err(SequenceError::Aborted),
+                SequenceState::Paused => {
+                    status = self
+                        .control
+                        .1
+                        .wait(status)
+                        .unwrap_or_else(|poisoned| poisoned.into_inner());
+                }
+                _ => return Ok(status),
+            }
+        }
+    }
+
+    /// Wait for the given duration, not counting time spent paused, returning early if aborted.
+    /// If given a [`Condition`] then return early once it holds, giving whether it did.
+    ///
+    /// [`Condition`]: Condition
+    fn wait(
+        &self,
+        duration: Duration,
+        condition: Option<(&Condition, &Telemetry)>,
+    ) -> Result<bool, SequenceError> {
+        let mut status = self.status();
+        let mut remaining = duration;
+
+        let result = loop {
+            if status.state == SequenceState::Paused {
+                status.wait = WaitStatus::Paused(remaining);
+            }
+
+            status = match self.wait_while_paused(status) {
+                Ok(status) => status,
+                Err(e) => {
+                    self.status().wait = WaitStatus::None;
+                    return Err(e);
+                }
+            };
+
+            if let Some((condition, telemetry)) = condition
+                && condition.holds(telemetry)
+            {
+                break Ok(true);
+            }
+
+            if remaining.is_zero() {
+                break Ok(false);
+            }
+
+            let now = Instant::now();
+            let deadline = now.checked_add(remaining).unwrap_or_else(|| now + MAX_WAIT);
+            status.wait = WaitStatus::Until(deadline);
+
+            let timeout = match condition {
+                Some(_) => remaining.min(CONDITION_POLL_INTERVAL),
+                None => remaining,
+            };
+
+            status = self
+                .control
+                .1
+                .wait_timeout(status, timeout)
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .0;
+
+            remaining = deadline.saturating_duration_since(Instant::now());
+        };
+
+        status.wait = WaitStatus::None;
+        result
+    }
+
+    /// Switch to running the given sequence in place of the rest of the sequence, as its abort
+    /// sequence if `aborted`, or else as a fallback. Gives [`SequenceError::Aborted`] without
+    /// switching if the sequence has been cancelled since.
+    ///
+    /// [`SequenceError::Aborted`]: SequenceError::Aborted
+    fn start_abort_sequence(
+        &self,
+        seq: &CommandSequence,
+        aborted: bool,
+    ) -> Result<(), SequenceError> {
+        let mut status = self.status();
+
+        let start = match aborted {
+            true => std::mem::take(&mut status.run_abort_sequence),
+            false => status.state != SequenceState::Aborted,
+        };
+
+        if !start {
+            return Err(SequenceError::Aborted);
+        }
+
+        status.state = SequenceState::Aborting;
+        status.step = 0;
+        status.step_durations = step_durations(seq);
+        Ok(())
+    }
+
+    fn finish(&self, state: SequenceState) {
+        let mut status = self.status();
+        status.state = state;
+        status.wait = WaitStatus::None;
+        status.stopped = true;
+        self.control.1.notify_all();
     }
 }
 
+/// How long each of the given [`CommandSequence`]'s [`Command`]s waits for.
+///
+/// [`CommandSequence`]: CommandSequence
+/// [`Command`]: Command
+fn step_durations(seq: &CommandSequence) -> Vec<Duration> {
+    seq.commands
+        .iter()
+        .map(|command| match command {
+            Command::Wait(duration) => *duration,
+            Command::WaitUntil(wait) => wait.timeout,
+            Command::Done => DONE_WAIT,
+            _ => Duration::ZERO,
+        })
+        .collect()
+}
+
 /// A command that can be sent over serial to the NILE test stand.
 #[derive(Debug)]
 pub enum Command {
     OpenValve(ValveHandle),
     CloseValve(ValveHandle),
     Wait(Duration),
+
+    /// Wait until a [`Condition`] on the stand's telemetry holds.
+    ///
+    /// [`Condition`]: Condition
+    WaitUntil(WaitUntil),
+
     Done,
 }
 
-impl Command {
-    /// Run the given [`Command`], sending them to the given [`Sender`].
+/// A wait for a [`Condition`] to hold, with what to do if it does not hold in time.
+///
+/// [`Condition`]: Condition
+#[derive(Debug)]
+pub struct WaitUntil {
+    pub condition: Condition,
+    pub timeout: Duration,
+    pub on_timeout: TimeoutAction,
+}
+
+/// What a sequence does when a [`Command::WaitUntil`] times out.
+///
+/// [`Command::WaitUntil`]: Command::WaitUntil
+#[derive(Debug)]
+pub enum TimeoutAction {
+    /// Abort the sequence, running its abort sequence if it has one.
+    Abort,
+
+    /// Carry on with the next [`Command`].
     ///
     /// [`Command`]: Command
-    /// [`Sender`]: Sender
-    fn run(self, tx: &mut Sender<Vec<u8>>) -> Result<(), SendError<Vec<u8>>> {
+    Skip,
+
+    /// Run the given sequence in place of the rest of the sequence.
+    Run(CommandSequence),
+}
+
+/// A condition on the stand's telemetry.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Condition {
+    /// A field's value compared against a number.
+    Compare {
+        field: String,
+        comparison: Comparison,
+        value: f64,
+    },
+
+    /// A valve reported in the given state.
+    Valve {
+        valve: ValveHandle,
+        state: ValveState,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    Equal,
+    NotEqual,
+}
+
+impl Condition {
+    /// Whether the [`Condition`] holds for the latest values in the given [`Telemetry`]. A field
+    /// which has not been recieved never satisfies a [`Condition`].
+    ///
+    /// [`Condition`]: Condition
+    /// [`Telemetry`]: Telemetry
+    pub fn holds(&self, telemetry: &Telemetry) -> bool {
         match self {
-            Command::OpenValve(valve_handle) => {
-                tx.send(format!("\nOPEN:{valve_handle}\n").into_bytes())
-            }
+            Condition::Compare {
+                field,
+                comparison,
+                value,
+            } => telemetry
+                .get(field)
+                .is_some_and(|v| comparison.compare(v.to_num(), *value)),
 
-            Command::CloseValve(valve_handle) => {
-                tx.send(format!("\nCLOSE:{valve_handle}\n").into_bytes())
-            }
+            Condition::Valve { valve, state } => {
+                let reported = match telemetry.get(valve.name()) {
+                    Some(SensorValue::Boolean(true)) => ValveState::Open,
+                    Some(SensorValue::Boolean(false)) => ValveState::Closed,
+                    _ => return false,
+                };
 
-            Command::Wait(duration) => {
-                thread::sleep(duration);
-                Ok(())
+                reported == *state
             }
+        }
+    }
+}
 
-            Command::Done => {
-                thread::sleep(Duration::from_millis(500));
-                log::info!("Finished sequence!");
-                Ok(())
-            }
+impl Display for Command {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Command::OpenValve(valve) => write!(f, "open {valve}"),
+            Command::CloseValve(valve) => write!(f, "close {valve}"),
+            Command::Wait(duration) => write!(f, "wait {}s", duration.as_secs_f64()),
+            Command::WaitUntil(wait) => write!(f, "{wait}"),
+            Command::Done => write!(f, "done"),
         }
     }
 }
 
-/// A "handle" to a valve present on the NILE test stand.
-#[derive(Debug, Eq, PartialEq, PartialOrd, Ord, Clone, Copy)]
-pub enum ValveHandle {
-    NP1,
-    NP2,
-    NP3,
-    NP4,
-    IP1,
-    IP2,
-    IP3,
-    Engine1,
-    Engine2,
-    Engine3,
-    TimingOx,
-    TimingFuel,
-    Match,
+impl Display for WaitUntil {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "wait until {} timeout {}s else ",
+            self.condition,
+            self.timeout.as_secs_f64()
+        )?;
+
+        match self.on_timeout {
+            TimeoutAction::Abort => write!(f, "abort"),
+            TimeoutAction::Skip => write!(f, "skip"),
+            TimeoutAction::Run(_) => write!(f, "run fallback"),
+        }
+    }
 }
 
-impl Display for ValveHandle {
+impl Display for Condition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Condition::Compare {
+                field,
+                comparison,
+                value,
+            } => write!(f, "{field} {comparison} {value}"),
+
+            Condition::Valve { valve, state } => write!(f, "{valve} is {state}"),
+        }
+    }
+}
+
+impl Comparison {
+    fn compare(self, a: f64, b: f64) -> bool {
+        match self {
+            Comparison::Less => a < b,
+            Comparison::LessOrEqual => a <= b,
+            Comparison::Greater => a > b,
+            Comparison::GreaterOrEqual => a >= b,
+            Comparison::Equal => a == b,
+            Comparison::NotEqual => a != b,
+        }
+    }
+}
+
+impl Display for Comparison {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            ValveHandle::NP1 => write!(f, "NP1"),
-            ValveHandle::NP2 => write!(f, "NP2"),
-            ValveHandle::NP3 => write!(f, "NP3"),
-            ValveHandle::NP4 => write!(f, "NP4"),
-            ValveHandle::IP1 => write!(f, "IP1"),
-            ValveHandle::IP2 => write!(f, "IP2"),
-            ValveHandle::IP3 => write!(f, "IP3"),
-            ValveHandle::Engine1 => write!(f, "EN1"),
-            ValveHandle::Engine2 => write!(f, "EN2"),
-            ValveHandle::Engine3 => write!(f, "EN3"),
-            ValveHandle::TimingOx => write!(f, "TMN"),
-            ValveHandle::TimingFuel => write!(f, "TMI"),
-            ValveHandle::Match => write!(f, "MCH"),
+            Comparison::Less => write!(f, "<"),
+            Comparison::LessOrEqual => write!(f, "<="),
+            Comparison::Greater => write!(f, ">"),
+            Comparison::GreaterOrEqual => write!(f, ">="),
+            Comparison::Equal => write!(f, "=="),
+            Comparison::NotEqual => write!(f, "!="),
         }
     }
 }
+
+/// A "handle" to a valve present on the NILE test stand, by the name given to it in the
+/// [`StandConfig`].
+///
+/// [`StandConfig`]: crate::config::StandConfig
+#[derive(Debug, Eq, PartialEq, PartialOrd, Ord, Clone, Hash)]
+pub struct ValveHandle(String);
+
+impl ValveHandle {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self(name.into())
+    }
+
+    /// The name the stand knows this valve by, as used in commands sent over serial.
+    pub fn name(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Display for ValveHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    /// A wait on a field which is never sent, taking the given action once it times out.
+    fn never(on_timeout: TimeoutAction) -> Command {
+        Command::WaitUntil(WaitUntil {
+            condition: Condition::Compare {
+                field: "NPT1".to_string(),
+                comparison: Comparison::Greater,
+                value: 400.0,
+            },
+            timeout: Duration::from_millis(10),
+            on_timeout,
+        })
+    }
+
+    /// Run the given sequence until it stops, giving the state it ended in, the commands it sent,
+    /// and the names of the events it sent.
+    fn run(seq: CommandSequence) -> (SequenceState, Vec<ValveCommand>, Vec<&'static str>) {
+        let (tx, commands) = mpsc::channel();
+        let (events_tx, events) = mpsc::channel();
+
+        let handle = seq.run_par(tx, Telemetry::default(), events_tx, None);
+        assert!(handle.wait_stopped(Duration::from_secs(1)));
+
+        // both channels close once the sequence's thread has sent its last event
+        (
+            handle.state(),
+            commands.iter().collect(),
+            events.iter().map(|e| e.kind.name()).collect(),
+        )
+    }
+
+    #[test]
+    fn fallback_after_timeout_is_not_an_abort() {
+        let fallback = CommandSequence::new().then(Command::CloseValve(ValveHandle::new("NP2")));
+        let seq = CommandSequence::new()
+            .then(never(TimeoutAction::Run(fallback)))
+            .then(Command::OpenValve(ValveHandle::new("NP2")));
+
+        let (state, commands, events) = run(seq);
+
+        assert_eq!(state, SequenceState::FellBack);
+        assert_eq!(commands, [ValveCommand::Close("NP2".to_string())]);
+        assert_eq!(
+            events,
+            [
+                "sequence_step",
+                "sequence_timed_out",
+                "sequence_step",
+                "sequence_ended"
+            ]
+        );
+    }
+
+    #[test]
+    fn timeouts_skip_or_abort() {
+        let (state, commands, events) = run(CommandSequence::new()
+            .then(never(TimeoutAction::Skip))
+            .then(Command::OpenValve(ValveHandle::new("NP2"))));
+
+        assert_eq!(state, SequenceState::Finished);
+        assert_eq!(commands, [ValveCommand::Open("NP2".to_string())]);
+        assert!(events.contains(&"sequence_timed_out"));
+
+        let (state, commands, _) = run(CommandSequence::new()
+            .then(never(TimeoutAction::Abort))
+            .then(Command::OpenValve(ValveHandle::new("NP2"))));
+
+        assert_eq!(state, SequenceState::Aborted);
+        assert!(commands.is_empty());
+    }
+}
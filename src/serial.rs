@@ -1,46 +1,54 @@
-use crate::sequence::CommandSequence;
-use serialport::{SerialPort, SerialPortInfo, SerialPortType, UsbPortInfo};
+use crate::{
+    calibration::Calibrator,
+    capture::{CaptureWriter, Direction},
+    clock::{ClockEstimate, ClockSync},
+    derived::DerivedFields,
+    event::{EventKind, StandEvent},
+    frame::{DataFrame, FrameDecoder, FrameStats, PROTOCOL_REPLY, PROTOCOL_REQUEST, Protocol},
+    record::StandRecord,
+    redline::RedlineTrip,
+    safety::{SafetyConfig, SafetyMonitor},
+    sequence::{CommandSequence, SequenceError, SequenceHandle},
+    stand::CommandedValves,
+    watchdog::{WatchdogAction, WatchdogState},
+};
+use serialport::{FlowControl, SerialPort, SerialPortInfo, SerialPortType, UsbPortInfo};
 use std::{
-    collections::{HashMap, hash_map},
+    collections::{BTreeMap, HashMap, hash_map},
     error::Error,
     fmt::Display,
     io::{self, Read, Write},
-    sync::mpsc::{self, Receiver, SendError, Sender, TryRecvError},
-    thread::{self, JoinHandle},
-    time::Duration,
+    mem,
+    path::{Path, PathBuf},
+    sync::{
+        Arc, RwLock,
+        mpsc::{self, Receiver, SendError, Sender, TryRecvError},
+    },
+    thread,
+    time::{Duration, Instant, SystemTime},
 };
 
-#[cfg(not(feature = "allow_all_fields"))]
-const CHECKED_FIELD_NAMES: [&'static str; 24] = [
-    // Valves
-    "NP1",
-    "NP2",
-    "NP3",
-    "NP4",
-    "IP1",
-    "IP2",
-    "IP3",
-    // Pressure Transducers
-    "NPT1",
-    "NPT3",
-    "IPT1",
-    "IPT3",
-    // Scales
-    "Scale Thrust",
-    "Scale Thrust Rate",
-    "Scale Ox",
-    "Scale Ox Rate",
-    "Scale Fuel",
-    "Scale Fuel Rate",
-    "Ox/Fuel Ratio",
-    // Diagnostic
-    "Update Time",
-    "Update Rate",
-    "Bytes Recieved",
-    "Stand Time",
-    "SP Time",
-    "SP Rate",
-];
+/// Name of the field the stand sends to acknowledge a command, its value is the command's ID.
+const ACK_FIELD_NAME: &str = "ACK";
+
+/// Name of the field the stand sends to reject a command, its value is the command's ID.
+const NAK_FIELD_NAME: &str = "NAK";
+
+/// Name of the field carrying the stand's clock, in seconds since the stand started. In text it
+/// stamps the fields which follow it, so should be sent first in each batch of fields. Binary
+/// frames carry it in their header.
+pub const STAND_TIME_FIELD_NAME: &str = "Stand Time";
+
+/// Largest stand time accepted, in seconds, far longer than any stand runs for, so that a corrupt
+/// time is rejected rather than thrown off the host's clock.
+const MAX_STAND_TIME: f64 = 1e9;
+
+/// Longest a read from a serial port blocks the field thread for, so that commands are still sent
+/// and the watchdog still updated while the stand is silent.
+const PORT_READ_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// How long to wait for the stand to agree to switch to binary frames before carrying on with text.
+const NEGOTIATION_TIMEOUT: Duration = Duration::from_secs(2);
 
 /// Like [`SerialPortInfo`], but specialized to ports with of type [`SerialPortType::UsbPort`].
 /// Since this in encoded in the type of the struct the `port_type` field is omitted, and in its
@@ -101,27 +109,55 @@ pub fn available_usb_ports() -> serialport::Result<Vec<UsbSerialPortInfo>> {
     Ok(usb_ports)
 }
 
+/// Baud rates offered to operators when opening a port.
+pub const COMMON_BAUD_RATES: [u32; 8] = [9600, 19200, 38400, 57600, 115200, 230400, 460800, 921600];
+
+/// Settings used when opening a serial port.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct PortSettings {
+    pub baud: u32,
+    pub flow_control: FlowControl,
+
+    /// The [`Protocol`] to ask the stand to send fields in.
+    ///
+    /// [`Protocol`]: Protocol
+    pub protocol: Protocol,
+}
+
+impl Default for PortSettings {
+    fn default() -> Self {
+        Self {
+            baud: 115200,
+            flow_control: FlowControl::Software,
+            protocol: Protocol::Text,
+        }
+    }
+}
+
 /// Open a USB port described by the given [`UsbSerialPortInfo`] for reading [`SensorField`]s from.
 ///
 /// [`UsbSerialPortInfo`]: UsbSerialPortInfo
 /// [`SensorField`]: SensorField
 pub fn open_field_port(
     port: &UsbSerialPortInfo,
-    baud: u32,
+    settings: PortSettings,
 ) -> serialport::Result<FieldIO<Box<dyn SerialPort>>> {
-    let port = open_port(port, baud)?;
-    Ok(FieldIO::new(port))
+    let port = open_port(port, settings)?;
+    Ok(FieldIO::new(port).with_protocol(settings.protocol))
 }
 
-/// Opens the USB port described by the given [`UsbSerialPortInfo`] for serial read/write at the
-/// given `baud`.
+/// Opens the USB port described by the given [`UsbSerialPortInfo`] for serial read/write with the
+/// given [`PortSettings`].
 ///
 /// [`UsbSerialPortInfo`]: UsbSerialPortInfo
-pub fn open_port(port: &UsbSerialPortInfo, baud: u32) -> serialport::Result<Box<dyn SerialPort>> {
-    serialport::new(port.port_name.as_str(), baud)
-        // .flow_control(serialport::FlowControl::Hardware)
-        .flow_control(serialport::FlowControl::Software)
-        .timeout(Duration::from_secs(1))
+/// [`PortSettings`]: PortSettings
+pub fn open_port(
+    port: &UsbSerialPortInfo,
+    settings: PortSettings,
+) -> serialport::Result<Box<dyn SerialPort>> {
+    serialport::new(port.port_name.as_str(), settings.baud)
+        .flow_control(settings.flow_control)
+        .timeout(PORT_READ_TIMEOUT)
         .open()
 }
 
@@ -129,77 +165,125 @@ pub fn open_port(port: &UsbSerialPortInfo, baud: u32) -> serialport::Result<Box<
 /// read and send [`SensorField`]s from a seperate thread. This function returns the associated
 /// [`FieldReciever`] to allow the recieving of read [`SensorField`]s.
 ///
+/// The thread starts without a device, one must be given to it with [`FieldReciever::attach`].
+/// If the device is lost the thread keeps running without one until another is attached. Only
+/// fields with the given names are passed on, unless the `allow_all_fields` feature is enabled,
+/// calibrated by the given [`Calibrator`], along with any fields the given [`DerivedFields`]
+/// derive from them. The thread safes the stand itself as described by the given
+/// [`SafetyConfig`].
+///
+/// [`Calibrator`]: Calibrator
+/// [`DerivedFields`]: DerivedFields
+/// [`SafetyConfig`]: SafetyConfig
 /// [`SensorField`]: SensorField
 /// [`FieldSender`]: FieldSender
 /// [`FieldReciever`]: FieldReciever
-#[cfg(not(feature = "sim_io"))]
-pub fn start_field_thread<R>(field_reader: FieldIO<R>) -> FieldReciever
-where
-    R: 'static + Read + Write + Send,
-{
-    let (field_sender, field_reciever) = field_channel(field_reader);
+/// [`FieldReciever::attach`]: FieldReciever::attach
+pub fn start_field_thread(
+    checked_field_names: Vec<String>,
+    calibrator: Calibrator,
+    derived: DerivedFields,
+    safety: SafetyConfig,
+) -> FieldReciever {
+    let (field_sender, field_reciever) =
+        field_channel(checked_field_names, calibrator, derived, safety);
 
     thread::spawn(move || -> Result<(), SensorFieldReadError> {
         let mut field_sender = field_sender;
 
         loop {
-            if let Err(e) = field_sender.send_fields() {
-                log::error!("Field sender had error: {e}");
-            }
-
-            if let Err(e) = field_sender
-                .send_commands()
-                .map_err(|e| SensorFieldReadError::IoError(e))
-            {
-                log::error!("Field sender had error: {e}");
-            }
+            field_sender.step()?;
         }
     });
 
     field_reciever
 }
 
-#[cfg(feature = "sim_io")]
-pub fn start_simulation_field_thread<R>(field_reader: FieldIO<R>) -> FieldReciever
-where
-    R: 'static + Read + Send,
-{
-    let (field_sender, field_reciever) = field_channel(field_reader);
+/// How often the field thread flushes the record it is writing to disk.
+const RECORD_FLUSH_INTERVAL: Duration = Duration::from_secs(1);
 
-    thread::spawn(move || -> Result<(), SensorFieldReadError> {
-        let mut field_sender = field_sender;
+/// How long the field thread sleeps between checks for a new device while it has none.
+const DETACHED_POLL_INTERVAL: Duration = Duration::from_millis(50);
 
-        loop {
-            if let Err(e) = field_sender.send_fields() {
-                log::error!("Field sender had error: {e}");
-            }
-        }
-    });
-
-    field_reciever
+/// Whether an [`io::Error`] from a field device means the device itself has gone away, as opposed
+/// to a read simply timing out.
+///
+/// [`io::Error`]: io::Error
+fn is_link_lost(e: &io::Error) -> bool {
+    !matches!(
+        e.kind(),
+        io::ErrorKind::TimedOut | io::ErrorKind::Interrupted | io::ErrorKind::WouldBlock
+    )
 }
 
 /// Create a multiple producer single consumer senser reciever channel pair for [`SensorField`]s.
+/// The [`FieldSender`] starts without a device, and passes on only fields with the given names,
+/// calibrated by the given [`Calibrator`], and those the given [`DerivedFields`] derive from them.
+/// It safes the stand as described by the given [`SafetyConfig`].
 ///
 /// [`SensorField`]: SensorField
-pub fn field_channel<R>(field_reader: FieldIO<R>) -> (FieldSender<R>, FieldReciever)
-where
-    R: 'static + Read + Send,
-{
+/// [`FieldSender`]: FieldSender
+/// [`Calibrator`]: Calibrator
+/// [`DerivedFields`]: DerivedFields
+/// [`SafetyConfig`]: SafetyConfig
+pub fn field_channel(
+    checked_field_names: Vec<String>,
+    calibrator: Calibrator,
+    derived: DerivedFields,
+    safety: SafetyConfig,
+) -> (FieldSender, FieldReciever) {
     let (read_tx, read_rx) = mpsc::channel();
     let (command_tx, command_rx) = mpsc::channel();
+    let (event_tx, event_rx) = mpsc::channel();
+    let (control_tx, control_rx) = mpsc::channel();
+    let (stand_event_tx, stand_event_rx) = mpsc::channel();
 
     let sender = FieldSender {
-        reader: field_reader.device,
-        remainder: field_reader.remainder,
+        device: None,
+        decoder: FieldDecoder::Text {
+            remainder: String::new(),
+        },
+        checked_field_names,
+        calibrator,
+        derived,
         read_tx,
+        command_tx: command_tx.clone(),
         command_rx,
+        command_retry: CommandRetry::default(),
+        next_command_id: 0,
+        pending_commands: HashMap::new(),
+        stand_time: None,
+        clock: ClockSync::default(),
+        capture: None,
+        record: None,
+        last_record_flush: Instant::now(),
+        stand_event_rx,
+        event_tx,
+        control_rx,
+        safety: SafetyMonitor::new(safety),
     };
 
     let receiver = FieldReciever {
-        fields: field_reader.fields,
+        fields: HashMap::new(),
+        recieved: Vec::new(),
+        tares: BTreeMap::new(),
         read_rx,
         command_tx,
+        event_rx,
+        control_tx,
+        connected: false,
+        protocol: Protocol::Text,
+        frame_stats: FrameStats::default(),
+        clock: None,
+        capture_path: None,
+        record_path: None,
+        stand_event_tx,
+        command_failures: Vec::new(),
+        redline_trips: Vec::new(),
+        failsafe: None,
+        watchdog: (WatchdogState::Nominal, Instant::now()),
+        commanded_valves: CommandedValves::default(),
+        telemetry: Telemetry::default(),
     };
 
     (sender, receiver)
@@ -212,35 +296,183 @@ where
 /// [`FieldSender`]: FieldSender
 #[derive(Debug)]
 pub struct FieldReciever {
-    fields: HashMap<String, SensorValue>,
+    fields: HashMap<String, SensorField>,
+    /// Every [`SensorField`] recieved by the last call to [`FieldReciever::recieve_fields`].
+    ///
+    /// [`SensorField`]: SensorField
+    /// [`FieldReciever::recieve_fields`]: FieldReciever::recieve_fields
+    recieved: Vec<SensorField>,
+    /// The offset subtracted from each tared field.
+    tares: BTreeMap<String, f64>,
     read_rx: Receiver<SensorField>,
-    command_tx: Sender<Vec<u8>>,
+    command_tx: Sender<ValveCommand>,
+    event_rx: Receiver<SenderEvent>,
+    control_tx: Sender<FieldControl>,
+    /// Whether the [`FieldSender`] currently has a device to read from.
+    ///
+    /// [`FieldSender`]: FieldSender
+    connected: bool,
+    /// The [`Protocol`] the stand is currently sending fields in.
+    ///
+    /// [`Protocol`]: Protocol
+    protocol: Protocol,
+    /// Counts of binary frames decoded from the current device, and errors found in them.
+    frame_stats: FrameStats,
+    /// The latest estimate of the stand's clock against the host's, if the stand stamps its
+    /// fields.
+    clock: Option<ClockEstimate>,
+    /// The file the [`FieldSender`] is capturing the device's bytes to, if any.
+    ///
+    /// [`FieldSender`]: FieldSender
+    capture_path: Option<PathBuf>,
+    /// The file the [`FieldSender`] is recording fields and events to, if any.
+    ///
+    /// [`FieldSender`]: FieldSender
+    record_path: Option<PathBuf>,
+    /// Channel the console and running sequences send [`StandEvent`]s to the [`FieldSender`]
+    /// over, to be recorded.
+    ///
+    /// [`StandEvent`]: StandEvent
+    /// [`FieldSender`]: FieldSender
+    stand_event_tx: Sender<StandEvent>,
+    /// Commands which the stand rejected or never acknowledged, oldest first.
+    command_failures: Vec<CommandFailure>,
+    /// Redlines which have tripped, oldest first.
+    redline_trips: Vec<RedlineTrip>,
+    /// Why the [`FieldSender`] last safed the stand, if it has since this was last taken.
+    ///
+    /// [`FieldSender`]: FieldSender
+    failsafe: Option<String>,
+    /// The latest state of the [`FieldSender`]'s watchdog, and when it was recieved.
+    ///
+    /// [`FieldSender`]: FieldSender
+    watchdog: (WatchdogState, Instant),
+    /// The valve states last commanded down serial.
+    commanded_valves: CommandedValves,
+    /// The latest field values, shared with running sequences.
+    telemetry: Telemetry,
 }
 
-/// A wrapper type over a [`Read`] instance for reading [`SensorField`]s and then sending them over
-/// a channel to a [`FieldReciever`].
+/// A wrapper type over a [`FieldDevice`] for reading [`SensorField`]s and then sending them over
+/// a channel to a [`FieldReciever`]. The device may be lost and replaced while the
+/// [`FieldSender`] lives on.
 ///
-/// [`Read`]: Read
+/// [`FieldDevice`]: FieldDevice
 /// [`SensorField`]: SensorField
 /// [`FieldReciever`]: FieldReciever
-#[derive(Debug)]
-pub struct FieldSender<R>
-where
-    R: 'static + Send,
-{
-    reader: R,
-    remainder: String,
+/// [`FieldSender`]: FieldSender
+pub struct FieldSender {
+    device: Option<Box<dyn FieldDevice>>,
+    decoder: FieldDecoder,
+    /// Names of the fields which are passed on to the [`FieldReciever`].
+    ///
+    /// [`FieldReciever`]: FieldReciever
+    #[cfg_attr(feature = "allow_all_fields", allow(dead_code))]
+    checked_field_names: Vec<String>,
+    /// Calibrates fields as they are read, before any are derived from them.
+    calibrator: Calibrator,
+    /// Computes the fields derived from those read, which are passed on after them.
+    derived: DerivedFields,
     read_tx: Sender<SensorField>,
-    command_rx: Receiver<Vec<u8>>,
+    /// Queues the commands which safe the stand, behind any already sent by a sequence.
+    command_tx: Sender<ValveCommand>,
+    command_rx: Receiver<ValveCommand>,
+    command_retry: CommandRetry,
+    next_command_id: CommandId,
+    /// Commands which have been written but not yet acknowledged by the stand.
+    pending_commands: HashMap<CommandId, PendingCommand>,
+    /// The stand time of the latest `Stand Time` field, which stamps the text fields after it.
+    stand_time: Option<f64>,
+    /// Estimates the stand's clock against the host's, to give fields host times.
+    clock: ClockSync,
+    /// Records every byte read from and written to the device, if capturing.
+    capture: Option<CaptureWriter>,
+    /// Records every field passed on and every [`StandEvent`], if recording.
+    ///
+    /// [`StandEvent`]: StandEvent
+    record: Option<StandRecord>,
+    last_record_flush: Instant,
+    stand_event_rx: Receiver<StandEvent>,
+    event_tx: Sender<SenderEvent>,
+    control_rx: Receiver<FieldControl>,
+    /// Checks fields against their redlines and safes the stand.
+    safety: SafetyMonitor,
+}
+
+/// Anything which fields may be read from and commands written to, such as a serial port.
+pub trait FieldDevice: Read + Write + Send {}
+
+impl<T> FieldDevice for T where T: Read + Write + Send {}
+
+/// Requests sent from a [`FieldReciever`] to its [`FieldSender`] to change its device, or how it
+/// safes the stand.
+///
+/// [`FieldReciever`]: FieldReciever
+/// [`FieldSender`]: FieldSender
+enum FieldControl {
+    Attach(Box<dyn FieldDevice>, Protocol, CommandRetry),
+    Detach,
+
+    /// Start capturing to the given [`CaptureWriter`], or stop capturing.
+    ///
+    /// [`CaptureWriter`]: CaptureWriter
+    Capture(Option<CaptureWriter>),
+
+    /// Start recording to the given [`StandRecord`], or stop recording.
+    ///
+    /// [`StandRecord`]: StandRecord
+    Record(Option<StandRecord>),
+
+    /// Tare the named field at its latest value, or clear its tare.
+    Tare(String, bool),
+
+    /// Arm or disarm the redlines.
+    ArmRedlines(bool),
+
+    /// Watch the given sequence, cancelling it if the stand is safed.
+    WatchSequence(SequenceHandle),
+
+    /// Safe the stand for the given reason.
+    Failsafe(String),
+
+    /// Return a tripped watchdog to nominal.
+    ResetWatchdog,
+}
+
+/// How a [`FieldSender`] decodes fields from the bytes read from its device.
+///
+/// [`FieldSender`]: FieldSender
+#[derive(Debug)]
+enum FieldDecoder {
+    /// Text lines, keeping the incomplete last line of the previous read. Switches to binary
+    /// frames if the stand says it has, even unasked, e.g. when replaying a capture.
+    Text { remainder: String },
+
+    /// Text lines, having asked the stand to switch to binary frames at the given time.
+    Negotiating { remainder: String, since: Instant },
+
+    /// Binary frames.
+    Binary(FrameDecoder),
 }
 
 impl FieldReciever {
-    /// Gives an [`Iterator`] of the sensor fields of the [`FieldReciever`].
+    /// Gives an [`Iterator`] of the latest [`SensorField`] of each name recieved by the
+    /// [`FieldReciever`].
     ///
     /// [`Iterator`]: Iterator
+    /// [`SensorField`]: SensorField
     /// [`FieldReciever`]: FieldReciever
-    pub fn fields(&self) -> hash_map::Iter<'_, String, SensorValue> {
-        self.fields.iter()
+    pub fn fields(&self) -> hash_map::Values<'_, String, SensorField> {
+        self.fields.values()
+    }
+
+    /// Gives every [`SensorField`] recieved by the last call to [`FieldReciever::recieve_fields`],
+    /// oldest first, including any superseded by a newer field of the same name.
+    ///
+    /// [`SensorField`]: SensorField
+    /// [`FieldReciever::recieve_fields`]: FieldReciever::recieve_fields
+    pub fn recieved_fields(&self) -> &[SensorField] {
+        &self.recieved
     }
 
     /// Recieve as many fields as possible over the channel without blocking for new
@@ -259,11 +491,47 @@ impl FieldReciever {
     /// [`Ok`]: Ok
     pub fn recieve_fields(&mut self) -> Result<u32, TryRecvError> {
         let mut count = 0;
+        self.recieved.clear();
+
+        while let Ok(event) = self.event_rx.try_recv() {
+            match event {
+                SenderEvent::Stand(event) => match event.kind {
+                    EventKind::CommandSent(_, command) => self.commanded_valves.command(&command),
+                    EventKind::CommandFailed(failure) => {
+                        log::error!("{failure}");
+                        self.command_failures.push(failure);
+                    }
+                    EventKind::Tared(name, offset) => {
+                        self.tares.insert(name, offset);
+                    }
+                    EventKind::TareCleared(name) => {
+                        self.tares.remove(&name);
+                    }
+                    EventKind::RedlineTripped(trip) => self.redline_trips.push(trip),
+                    EventKind::Failsafe(reason) => self.failsafe = Some(reason),
+                    _ => (),
+                },
+                SenderEvent::Connected => {
+                    self.connected = true;
+                    self.protocol = Protocol::Text;
+                    self.frame_stats = FrameStats::default();
+                    self.clock = None;
+                }
+                SenderEvent::Disconnected => self.connected = false,
+                SenderEvent::ProtocolChanged(protocol) => self.protocol = protocol,
+                SenderEvent::FrameStats(stats) => self.frame_stats = stats,
+                SenderEvent::ClockEstimate(estimate) => self.clock = Some(estimate),
+                SenderEvent::CaptureStopped => self.capture_path = None,
+                SenderEvent::RecordStopped => self.record_path = None,
+                SenderEvent::Watchdog(state) => self.watchdog = (state, Instant::now()),
+            }
+        }
 
         loop {
             match self.read_rx.try_recv() {
                 Ok(field) => {
-                    self.fields.insert(field.name, field.value);
+                    self.recieved.push(field.clone());
+                    self.fields.insert(field.name.clone(), field);
                     count += 1;
                 }
 
@@ -272,85 +540,1056 @@ impl FieldReciever {
             }
         }
 
-        Ok(count)
+        if count > 0 {
+            self.telemetry.update(&self.fields);
+        }
+
+        Ok(count)
+    }
+
+    /// Send a [`ValveCommand`] to the [`FieldSender`] to be sent down serial.
+    ///
+    /// [`ValveCommand`]: ValveCommand
+    /// [`FieldSender`]: FieldSender
+    pub fn send_command(&mut self, command: ValveCommand) -> Result<(), SendError<ValveCommand>> {
+        self.command_tx.send(command)
+    }
+
+    /// Start capturing every byte read from and written to the device to a new file at the given
+    /// path, replacing any current capture.
+    pub fn start_capture<P>(&mut self, path: P) -> io::Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        let writer = CaptureWriter::create(path.as_ref(), self.protocol)?;
+
+        self.control_tx
+            .send(FieldControl::Capture(Some(writer)))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "field thread has died"))?;
+
+        log::info!("Capturing to {}", path.as_ref().display());
+        self.capture_path = Some(path.as_ref().to_path_buf());
+        Ok(())
+    }
+
+    /// Stop capturing, if capturing.
+    pub fn stop_capture(&mut self) {
+        if self.capture_path.take().is_some() {
+            let _ = self.control_tx.send(FieldControl::Capture(None));
+        }
+    }
+
+    /// Gives the file currently being captured to, if any.
+    pub fn capture_path(&self) -> Option<&Path> {
+        self.capture_path.as_deref()
+    }
+
+    /// Gives the latest estimate of the stand's clock against the host's, if the stand stamps its
+    /// fields.
+    pub fn clock_estimate(&self) -> Option<ClockEstimate> {
+        self.clock
+    }
+
+    /// Start recording every field recieved, and every [`StandEvent`], to a new [`StandRecord`]
+    /// at the given path, replacing any current record. Recording happens on the field thread, so
+    /// carries on whatever the console is doing.
+    ///
+    /// [`StandEvent`]: StandEvent
+    /// [`StandRecord`]: StandRecord
+    pub fn start_recording<P>(&mut self, path: P) -> io::Result<()>
+    where
+        P: AsRef<Path>,
+    {
+        let record = StandRecord::open(path.as_ref())?;
+
+        self.control_tx
+            .send(FieldControl::Record(Some(record)))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "field thread has died"))?;
+
+        log::info!("Recording to {}", path.as_ref().display());
+        self.record_path = Some(path.as_ref().to_path_buf());
+        Ok(())
+    }
+
+    /// Stop recording, if recording.
+    pub fn stop_recording(&mut self) {
+        if self.record_path.take().is_some() {
+            let _ = self.control_tx.send(FieldControl::Record(None));
+        }
+    }
+
+    /// Gives the file currently being recorded to, if any.
+    pub fn record_path(&self) -> Option<&Path> {
+        self.record_path.as_deref()
+    }
+
+    /// Record the given [`StandEvent`], if recording.
+    ///
+    /// [`StandEvent`]: StandEvent
+    pub fn log_event(&self, event: StandEvent) {
+        let _ = self.stand_event_tx.send(event);
+    }
+
+    /// Tare the field with the given name on the field thread, so that it reads zero at its
+    /// latest value.
+    pub fn tare(&self, name: &str) {
+        let _ = self
+            .control_tx
+            .send(FieldControl::Tare(name.to_string(), true));
+    }
+
+    /// Clear the tare of the field with the given name.
+    pub fn clear_tare(&self, name: &str) {
+        let _ = self
+            .control_tx
+            .send(FieldControl::Tare(name.to_string(), false));
+    }
+
+    /// Gives the offset subtracted from each tared field.
+    pub fn tares(&self) -> &BTreeMap<String, f64> {
+        &self.tares
+    }
+
+    /// Gives the [`CommandFailure`]s recieved so far, oldest first.
+    ///
+    /// [`CommandFailure`]: CommandFailure
+    pub fn command_failures(&self) -> &[CommandFailure] {
+        &self.command_failures
+    }
+
+    /// Forget all [`CommandFailure`]s recieved so far, e.g. once an operator has seen them.
+    ///
+    /// [`CommandFailure`]: CommandFailure
+    pub fn clear_command_failures(&mut self) {
+        self.command_failures.clear();
+    }
+
+    /// Gives the [`RedlineTrip`]s recieved so far, oldest first.
+    ///
+    /// [`RedlineTrip`]: RedlineTrip
+    pub fn redline_trips(&self) -> &[RedlineTrip] {
+        &self.redline_trips
+    }
+
+    /// Forget all [`RedlineTrip`]s recieved so far, e.g. once an operator has seen them.
+    ///
+    /// [`RedlineTrip`]: RedlineTrip
+    pub fn clear_redline_trips(&mut self) {
+        self.redline_trips.clear();
+    }
+
+    /// Have the [`FieldSender`] check every field it reads against the redlines, or stop. The
+    /// redlines are disarmed by the [`FieldSender`] whenever it safes the stand.
+    ///
+    /// [`FieldSender`]: FieldSender
+    pub fn arm_redlines(&self, armed: bool) {
+        let _ = self.control_tx.send(FieldControl::ArmRedlines(armed));
+    }
+
+    /// Have the [`FieldSender`] cancel any running sequence and send the commands which safe the
+    /// stand, giving the reason in the record.
+    ///
+    /// [`FieldSender`]: FieldSender
+    pub fn failsafe(&self, reason: &str) -> Result<(), SendError<()>> {
+        self.control_tx
+            .send(FieldControl::Failsafe(reason.to_string()))
+            .map_err(|_| SendError(()))
+    }
+
+    /// Takes why the [`FieldSender`] last safed the stand, if it has since the last call. This is
+    /// only updated by [`FieldReciever::recieve_fields`].
+    ///
+    /// [`FieldSender`]: FieldSender
+    /// [`FieldReciever::recieve_fields`]: FieldReciever::recieve_fields
+    pub fn take_failsafe(&mut self) -> Option<String> {
+        self.failsafe.take()
+    }
+
+    /// The state of the [`FieldSender`]'s watchdog, which safes the stand if fields stop
+    /// arriving. This is only updated by [`FieldReciever::recieve_fields`], though the staleness
+    /// of a [`WatchdogState::Warning`] carries on growing in between.
+    ///
+    /// [`FieldSender`]: FieldSender
+    /// [`FieldReciever::recieve_fields`]: FieldReciever::recieve_fields
+    /// [`WatchdogState::Warning`]: WatchdogState::Warning
+    pub fn watchdog_state(&self) -> WatchdogState {
+        match self.watchdog {
+            (WatchdogState::Warning(staleness), since) => {
+                WatchdogState::Warning(staleness + since.elapsed())
+            }
+            (state, _) => state,
+        }
+    }
+
+    /// Return the [`FieldSender`]'s tripped watchdog to [`WatchdogState::Nominal`], e.g. once an
+    /// operator has acknowledged the loss of telemetry.
+    ///
+    /// [`FieldSender`]: FieldSender
+    /// [`WatchdogState::Nominal`]: WatchdogState::Nominal
+    pub fn reset_watchdog(&self) {
+        let _ = self.control_tx.send(FieldControl::ResetWatchdog);
+    }
+
+    /// Whether the [`FieldSender`] currently has a device to read fields from. This is only
+    /// updated by [`FieldReciever::recieve_fields`].
+    ///
+    /// [`FieldSender`]: FieldSender
+    /// [`FieldReciever::recieve_fields`]: FieldReciever::recieve_fields
+    pub fn is_connected(&self) -> bool {
+        self.connected
+    }
+
+    /// The [`Protocol`] the stand is currently sending fields in. This is only updated by
+    /// [`FieldReciever::recieve_fields`].
+    ///
+    /// [`Protocol`]: Protocol
+    /// [`FieldReciever::recieve_fields`]: FieldReciever::recieve_fields
+    pub fn protocol(&self) -> Protocol {
+        self.protocol
+    }
+
+    /// Gives the [`FrameStats`] of the binary frames recieved from the current device.
+    ///
+    /// [`FrameStats`]: FrameStats
+    pub fn frame_stats(&self) -> FrameStats {
+        self.frame_stats
+    }
+
+    /// Give the [`FieldSender`] a new device to read fields from and send commands to, replacing
+    /// any it currently has. All fields recieved so far are kept. The [`FieldReciever`] counts as
+    /// connected from this point, until the [`FieldSender`] reports otherwise.
+    ///
+    /// [`FieldSender`]: FieldSender
+    /// [`FieldReciever`]: FieldReciever
+    pub fn attach<R>(&mut self, field_io: FieldIO<R>) -> Result<(), SendError<()>>
+    where
+        R: 'static + FieldDevice,
+    {
+        self.control_tx
+            .send(FieldControl::Attach(
+                Box::new(field_io.device),
+                field_io.protocol,
+                field_io.command_retry,
+            ))
+            .map_err(|_| SendError(()))?;
+
+        self.connected = true;
+        Ok(())
+    }
+
+    /// Have the [`FieldSender`] drop its current device, if any.
+    ///
+    /// [`FieldSender`]: FieldSender
+    pub fn detach(&mut self) -> Result<(), SendError<()>> {
+        self.connected = false;
+        self.control_tx
+            .send(FieldControl::Detach)
+            .map_err(|_| SendError(()))
+    }
+
+    /// Gives the [`CommandedValves`] tracking what was last commanded of each valve.
+    ///
+    /// [`CommandedValves`]: CommandedValves
+    pub fn commanded_valves(&self) -> &CommandedValves {
+        &self.commanded_valves
+    }
+
+    /// Gives a mutable reference to the [`CommandedValves`], e.g. for changing its settling time.
+    ///
+    /// [`CommandedValves`]: CommandedValves
+    pub fn commanded_valves_mut(&mut self) -> &mut CommandedValves {
+        &mut self.commanded_valves
+    }
+
+    /// Run the given [`CommandSequence`] in the context of the given [`FieldReciever`].
+    ///
+    /// [`CommandSequence`]: CommandSequence
+    /// [`FieldReciever`]: FieldReciever
+    pub fn run_sequence(&self, seq: CommandSequence) -> Result<(), SequenceError> {
+        seq.run(
+            self.command_tx.clone(),
+            self.telemetry.clone(),
+            self.stand_event_tx.clone(),
+        )
+    }
+
+    /// Run the given [`CommandSequence`] in the context of the given [`FieldReciever`], in a new
+    /// thread, running the given abort sequence in its place if it is aborted. The
+    /// [`FieldSender`] cancels the sequence if it safes the stand.
+    ///
+    /// [`CommandSequence`]: CommandSequence
+    /// [`FieldReciever`]: FieldReciever
+    /// [`FieldSender`]: FieldSender
+    pub fn run_sequence_par(
+        &self,
+        seq: CommandSequence,
+        abort_sequence: Option<CommandSequence>,
+    ) -> SequenceHandle {
+        let handle = seq.run_par(
+            self.command_tx.clone(),
+            self.telemetry.clone(),
+            self.stand_event_tx.clone(),
+            abort_sequence,
+        );
+
+        let _ = self
+            .control_tx
+            .send(FieldControl::WatchSequence(handle.clone()));
+        handle
+    }
+}
+
+/// The latest value of each field recieved by a [`FieldReciever`], which may be shared with other
+/// threads.
+///
+/// [`FieldReciever`]: FieldReciever
+#[derive(Debug, Clone, Default)]
+pub struct Telemetry(Arc<RwLock<HashMap<String, SensorValue>>>);
+
+impl Telemetry {
+    /// Gives the latest value of the field with the given name, if it has been recieved.
+    pub fn get(&self, name: &str) -> Option<SensorValue> {
+        self.0
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .get(name)
+            .copied()
+    }
+
+    fn update(&self, fields: &HashMap<String, SensorField>) {
+        let mut values = self
+            .0
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        values.clear();
+        values.extend(
+            fields
+                .iter()
+                .map(|(name, field)| (name.clone(), field.value)),
+        );
+    }
+}
+
+impl FieldSender {
+    /// Whether the [`FieldSender`] currently has a device.
+    ///
+    /// [`FieldSender`]: FieldSender
+    pub fn is_attached(&self) -> bool {
+        self.device.is_some()
+    }
+
+    /// Handle any requests from the [`FieldReciever`] to attach or detach a device.
+    ///
+    /// [`FieldReciever`]: FieldReciever
+    pub fn handle_controls(&mut self) {
+        while let Ok(control) = self.control_rx.try_recv() {
+            match control {
+                FieldControl::Attach(device, protocol, command_retry) => {
+                    log::info!("Field device attached");
+                    self.fail_pending_commands(CommandFailureReason::Disconnected);
+                    self.device = Some(device);
+                    self.decoder = FieldDecoder::Text {
+                        remainder: String::new(),
+                    };
+                    self.command_retry = command_retry;
+                    self.stand_time = None;
+                    self.clock = ClockSync::default();
+                    let _ = self.event_tx.send(SenderEvent::Connected);
+
+                    if protocol == Protocol::Binary {
+                        self.request_binary();
+                    }
+                }
+
+                FieldControl::Detach => {
+                    if self.device.take().is_some() {
+                        log::info!("Field device detached");
+                        self.fail_pending_commands(CommandFailureReason::Disconnected);
+                        let _ = self.event_tx.send(SenderEvent::Disconnected);
+                    }
+                }
+
+                FieldControl::Capture(capture) => {
+                    if capture.is_none() && self.capture.is_some() {
+                        log::info!("Capture stopped");
+                    }
+
+                    self.capture = capture;
+                }
+
+                FieldControl::Record(record) => {
+                    if let Some(mut old) = self.record.take() {
+                        if let Err(e) = old.flush() {
+                            log::error!("Failed to flush record: {e}");
+                        }
+
+                        log::info!("Recording stopped");
+                    }
+
+                    self.record = record;
+                    self.last_record_flush = Instant::now();
+                }
+
+                FieldControl::Tare(name, true) => match self.calibrator.tare(&name) {
+                    Some(offset) => {
+                        log::info!("Tared {name} by {offset}");
+                        let kind = EventKind::Tared(name, offset);
+                        report_event(&mut self.record, &self.event_tx, kind);
+                    }
+                    None => log::warn!("Cannot tare {name} before it is recieved"),
+                },
+
+                FieldControl::Tare(name, false) => {
+                    self.calibrator.clear_tare(&name);
+                    log::info!("Cleared tare of {name}");
+                    report_event(
+                        &mut self.record,
+                        &self.event_tx,
+                        EventKind::TareCleared(name),
+                    );
+                }
+
+                FieldControl::ArmRedlines(armed) => self.safety.arm_redlines(armed),
+                FieldControl::WatchSequence(handle) => self.safety.watch_sequence(handle),
+                FieldControl::Failsafe(reason) => self.failsafe(reason),
+
+                FieldControl::ResetWatchdog => {
+                    log::info!("Watchdog reset");
+                    self.safety.reset_watchdog();
+                    let _ = self
+                        .event_tx
+                        .send(SenderEvent::Watchdog(self.safety.watchdog_state()));
+                }
+            }
+        }
+    }
+
+    /// Do one pass of the field thread's work: handle requests from the [`FieldReciever`],
+    /// record events, and update the watchdog, then read fields and send commands if there is a
+    /// device, or wait a moment for one if not. A device which fails is dropped. Only gives an
+    /// error if the [`FieldReciever`] is gone.
+    ///
+    /// [`FieldReciever`]: FieldReciever
+    pub fn step(&mut self) -> Result<(), SensorFieldReadError> {
+        self.handle_controls();
+        self.record_events();
+        self.update_watchdog();
+
+        if !self.is_attached() {
+            self.fail_commands(CommandFailureReason::Disconnected);
+            thread::sleep(DETACHED_POLL_INTERVAL);
+            return Ok(());
+        }
+
+        match self.send_fields() {
+            Ok(()) => (),
+            Err(SensorFieldReadError::DeadChannel) => {
+                return Err(SensorFieldReadError::DeadChannel);
+            }
+            Err(SensorFieldReadError::IoError(e)) if is_link_lost(&e) => {
+                self.lose_device(e);
+                return Ok(());
+            }
+            Err(e) => log::error!("Field sender had error: {e}"),
+        }
+
+        match self.send_commands() {
+            Ok(()) => (),
+            Err(e) if is_link_lost(&e) => self.lose_device(e),
+            Err(e) => log::error!("Field sender had error: {e}"),
+        }
+
+        Ok(())
+    }
+
+    /// Update the watchdog, safing the stand if fields have stopped arriving for too long or the
+    /// device has been lost for too long, and report its state to the [`FieldReciever`] whenever
+    /// it changes.
+    ///
+    /// [`FieldReciever`]: FieldReciever
+    pub fn update_watchdog(&mut self) {
+        let old_state = self.safety.watchdog_state();
+        let action = self.safety.update_watchdog(self.is_attached());
+        let state = self.safety.watchdog_state();
+
+        // the staleness of a warning changes with every update, so is only reported as it starts
+        let changed = match (old_state, state) {
+            (WatchdogState::Warning(_), WatchdogState::Warning(_)) => false,
+            (old_state, state) => old_state != state,
+        };
+
+        if changed {
+            let _ = self.event_tx.send(SenderEvent::Watchdog(state));
+        }
+
+        if action == WatchdogAction::Safe {
+            self.failsafe("watchdog".to_string());
+        }
+    }
+
+    /// Cancel any running sequence and queue the commands which safe the stand, recording the
+    /// given reason and reporting it to the [`FieldReciever`].
+    ///
+    /// [`FieldReciever`]: FieldReciever
+    fn failsafe(&mut self, reason: String) {
+        log::warn!("Safing stand: {reason}");
+
+        for command in self.safety.safe() {
+            let _ = self.command_tx.send(command);
+        }
+
+        report_event(
+            &mut self.record,
+            &self.event_tx,
+            EventKind::Failsafe(reason),
+        );
+    }
+
+    /// Record any [`StandEvent`]s sent by the [`FieldReciever`] or running sequences, and flush
+    /// the record every [`RECORD_FLUSH_INTERVAL`].
+    ///
+    /// [`StandEvent`]: StandEvent
+    /// [`FieldReciever`]: FieldReciever
+    /// [`RECORD_FLUSH_INTERVAL`]: RECORD_FLUSH_INTERVAL
+    pub fn record_events(&mut self) {
+        while let Ok(event) = self.stand_event_rx.try_recv() {
+            record_event(&mut self.record, &self.event_tx, &event);
+        }
+
+        if self.last_record_flush.elapsed() >= RECORD_FLUSH_INTERVAL {
+            self.last_record_flush = Instant::now();
+
+            if let Some(record) = &mut self.record
+                && let Err(e) = record.flush()
+            {
+                stop_recording(&mut self.record, &self.event_tx, e);
+            }
+        }
+    }
+
+    /// Drop the current device after it has failed with the given error.
+    pub fn lose_device(&mut self, e: io::Error) {
+        log::error!("Lost field device: {e}");
+        self.device = None;
+        self.decoder = FieldDecoder::Text {
+            remainder: String::new(),
+        };
+        self.fail_pending_commands(CommandFailureReason::Disconnected);
+        let _ = self.event_tx.send(SenderEvent::Disconnected);
+    }
+
+    /// Ask the stand to switch to binary frames, carrying on with text until it agrees.
+    fn request_binary(&mut self) {
+        let Some(device) = &mut self.device else {
+            return;
+        };
+
+        log::info!("Asking stand for binary frames");
+
+        let request = format!("\n{PROTOCOL_REQUEST}\n");
+        match write_captured(
+            device,
+            &mut self.capture,
+            &self.event_tx,
+            request.as_bytes(),
+        )
+        .and_then(|()| device.flush())
+        {
+            Ok(()) => {
+                self.decoder = FieldDecoder::Negotiating {
+                    remainder: String::new(),
+                    since: Instant::now(),
+                }
+            }
+
+            Err(e) => self.lose_device(e),
+        }
+    }
+
+    /// Report every command waiting to be sent as a [`CommandFailure`] with the given reason
+    /// without sending it, e.g. because there is no device to send it to.
+    ///
+    /// [`CommandFailure`]: CommandFailure
+    pub fn fail_commands(&mut self, reason: CommandFailureReason) {
+        while let Ok(command) = self.command_rx.try_recv() {
+            let id = self.next_command_id;
+            self.next_command_id = self.next_command_id.wrapping_add(1);
+
+            report_event(
+                &mut self.record,
+                &self.event_tx,
+                EventKind::CommandFailed(CommandFailure {
+                    id,
+                    command,
+                    reason,
+                }),
+            );
+        }
+    }
+
+    /// Report every command which has been sent but not acknowledged as a [`CommandFailure`] with
+    /// the given reason.
+    ///
+    /// [`CommandFailure`]: CommandFailure
+    fn fail_pending_commands(&mut self, reason: CommandFailureReason) {
+        for (id, pending) in self.pending_commands.drain() {
+            report_event(
+                &mut self.record,
+                &self.event_tx,
+                EventKind::CommandFailed(CommandFailure {
+                    id,
+                    command: pending.command,
+                    reason,
+                }),
+            );
+        }
+    }
+
+    /// Read as many [`SensorField`]s as possible from the internal [`FieldDevice`] and send them
+    /// over the channel for the corrosponding [`FieldReciever`]. Does nothing if there is no
+    /// device.
+    ///
+    /// [`SensorField`]: SensorField
+    /// [`FieldReviever`]: FieldReviever
+    /// [`FieldDevice`]: FieldDevice
+    pub fn send_fields(&mut self) -> Result<(), SensorFieldReadError> {
+        let Some(device) = &mut self.device else {
+            return Ok(());
+        };
+
+        let bytes = read_bytes(device)?;
+        capture_bytes(&mut self.capture, &self.event_tx, Direction::Read, &bytes);
+        let old_estimate = self.clock.estimate();
+
+        let fields = self.decode(&bytes);
+        if !fields.is_empty() {
+            self.safety.feed_watchdog();
+        }
+
+        for mut field in fields {
+            self.stamp(&mut field);
+
+            match field.name.as_str() {
+                ACK_FIELD_NAME => {
+                    self.acknowledge_command(field.value, true);
+                    continue;
+                }
+
+                NAK_FIELD_NAME => {
+                    self.acknowledge_command(field.value, false);
+                    continue;
+                }
+
+                _ => (),
+            }
+
+            #[cfg(not(feature = "allow_all_fields"))]
+            if !self.checked_field_names.contains(&field.name) {
+                log::warn!("Field '{}' was recieved but discarded!", field.name);
+                continue;
+            }
+
+            self.calibrator.calibrate(&mut field);
+            let derived = self.derived.derive(&field);
+
+            for field in std::iter::once(field).chain(derived) {
+                if let Some(record) = &mut self.record
+                    && let Err(e) = record.append_field(&field)
+                {
+                    stop_recording(&mut self.record, &self.event_tx, e);
+                }
+
+                let trip = self.safety.check_redlines(&field);
+
+                self.read_tx
+                    .send(field)
+                    .map_err(|_| SensorFieldReadError::DeadChannel)?;
+
+                if let Some(trip) = trip {
+                    log::error!("{trip}, safing stand!");
+                    report_event(
+                        &mut self.record,
+                        &self.event_tx,
+                        EventKind::RedlineTripped(trip),
+                    );
+                    self.failsafe("redline".to_string());
+                }
+            }
+        }
+
+        if let Some(estimate) = self.clock.estimate()
+            && Some(estimate) != old_estimate
+        {
+            let _ = self.event_tx.send(SenderEvent::ClockEstimate(estimate));
+        }
+
+        Ok(())
     }
 
-    /// Send a [`ValveCommand`] to the [`FieldSender`] to be sent down serial.
+    /// Give the [`SensorField`] its stand time, from the latest `Stand Time` field if it was not
+    /// stamped itself, and a host time estimated from its stand time. Stand times beyond
+    /// [`MAX_STAND_TIME`], e.g. from a binary frame, are ignored.
     ///
-    /// [`ValveCommand`]: ValveCommand
-    /// [`FieldSender`]: FieldSender
-    pub fn send_command(&mut self, command: ValveCommand) -> Result<(), SendError<Vec<u8>>> {
-        self.command_tx.send(command.to_string().into_bytes())
+    /// [`SensorField`]: SensorField
+    /// [`MAX_STAND_TIME`]: MAX_STAND_TIME
+    fn stamp(&mut self, field: &mut SensorField) {
+        if field.name == STAND_TIME_FIELD_NAME {
+            field.stand_time = Some(field.value.to_num());
+        }
+
+        field.stand_time = field.stand_time.filter(is_stand_time);
+
+        let Some(stand_time) = field.stand_time.or(self.stand_time) else {
+            return;
+        };
+
+        if self.stand_time != Some(stand_time) {
+            self.stand_time = Some(stand_time);
+            self.clock.add(stand_time, field.time);
+        }
+
+        field.stand_time = Some(stand_time);
+        field.time = self.clock.to_host(stand_time).unwrap_or(field.time);
     }
 
-    /// Run the given [`CommandSequence`] in the context of the given [`FieldReciever`].
-    ///
-    /// [`CommandSequence`]: CommandSequence
-    /// [`FieldReciever`]: FieldReciever
-    pub fn run_sequence(&self, seq: CommandSequence) -> Result<(), SendError<Vec<u8>>> {
-        seq.run(self.command_tx.clone())
+    /// Decode fields from the given bytes in the current protocol, switching to binary frames
+    /// if the stand has agreed to.
+    fn decode(&mut self, bytes: &[u8]) -> Vec<SensorField> {
+        match &mut self.decoder {
+            FieldDecoder::Text { remainder } | FieldDecoder::Negotiating { remainder, .. } => {
+                let mut text = mem::take(remainder).into_bytes();
+                text.extend_from_slice(bytes);
+
+                let reply = text
+                    .windows(PROTOCOL_REPLY.len())
+                    .position(|w| w == PROTOCOL_REPLY.as_bytes());
+
+                let Some(reply) = reply else {
+                    let (rest, fields) = parse_text_fields(&text, String::new());
+                    *remainder = rest;
+
+                    if let FieldDecoder::Negotiating { remainder, since } = &mut self.decoder
+                        && since.elapsed() >= NEGOTIATION_TIMEOUT
+                    {
+                        log::warn!("Stand did not agree to binary frames, carrying on with text");
+                        self.decoder = FieldDecoder::Text {
+                            remainder: mem::take(remainder),
+                        };
+                    }
+
+                    return fields;
+                };
+
+                log::info!("Stand switched to binary frames");
+
+                let (before, after) = text.split_at(reply);
+                let (_, mut fields) = parse_text_fields(&[before, b"\n"].concat(), String::new());
+
+                // Skip the rest of the reply's line.
+                let after = &after[PROTOCOL_REPLY.len()..];
+                let after = after.strip_prefix(b"\r").unwrap_or(after);
+                let after = after.strip_prefix(b"\n").unwrap_or(after);
+
+                self.decoder = FieldDecoder::Binary(FrameDecoder::default());
+                let _ = self
+                    .event_tx
+                    .send(SenderEvent::ProtocolChanged(Protocol::Binary));
+
+                fields.extend(self.decode(after));
+                fields
+            }
+
+            FieldDecoder::Binary(decoder) => {
+                let old_stats = decoder.stats();
+                let frames = decoder.push(bytes);
+
+                if decoder.stats() != old_stats {
+                    let _ = self.event_tx.send(SenderEvent::FrameStats(decoder.stats()));
+                }
+
+                frames.into_iter().flat_map(frame_fields).collect()
+            }
+        }
     }
 
-    /// Run the given [`CommandSequence`] in the context of the given [`FieldReciever`], in a new
-    /// thread.
+    /// Resolve the pending command with the ID carried by the given [`SensorValue`], reporting a
+    /// [`CommandFailure`] if the stand rejected it.
     ///
-    /// [`CommandSequence`]: CommandSequence
-    /// [`FieldReciever`]: FieldReciever
-    pub fn run_sequence_par(
-        &self,
-        seq: CommandSequence,
-    ) -> JoinHandle<Result<(), SendError<Vec<u8>>>> {
-        seq.run_par(self.command_tx.clone())
+    /// [`SensorValue`]: SensorValue
+    /// [`CommandFailure`]: CommandFailure
+    fn acknowledge_command(&mut self, id: SensorValue, accepted: bool) {
+        let id = match id {
+            SensorValue::UnsignedInt(id) => match CommandId::try_from(id) {
+                Ok(id) => id,
+                Err(_) => {
+                    log::warn!("Recieved command acknowledgement with out of range ID: {id}");
+                    return;
+                }
+            },
+            _ => {
+                log::warn!("Recieved command acknowledgement with non-integer ID: {id}");
+                return;
+            }
+        };
+
+        let Some(pending) = self.pending_commands.remove(&id) else {
+            log::warn!("Recieved acknowledgement for unknown command #{id}");
+            return;
+        };
+
+        if accepted {
+            log::info!("Command #{id} acknowledged: {}", pending.command);
+            report_event(
+                &mut self.record,
+                &self.event_tx,
+                EventKind::CommandAcknowledged(id, pending.command),
+            );
+        } else {
+            report_event(
+                &mut self.record,
+                &self.event_tx,
+                EventKind::CommandFailed(CommandFailure {
+                    id,
+                    command: pending.command,
+                    reason: CommandFailureReason::Rejected,
+                }),
+            );
+        }
     }
-}
 
-impl<R> FieldSender<R>
-where
-    R: 'static + Read + Send,
-{
-    /// Read as many [`SensorField`]s as possible from the internal [`Read`] instance and send them
-    /// over the channel for the corrosponding [`FieldReciever`].
+    /// Recieve [`ValveCommand`]s from the [`FieldReciever`] and send them down serial. Commands
+    /// which have gone unacknowledged for longer than the configured [`CommandRetry::timeout`]
+    /// are resent, and reported as a [`CommandFailure`] once out of attempts.
     ///
-    /// [`SensorField`]: SensorField
-    /// [`FieldReviever`]: FieldReviever
-    /// [`Read`]: Read
-    pub fn send_fields(&mut self) -> Result<(), SensorFieldReadError> {
-        let (remainder, fields) = read_fields(&mut self.reader, self.remainder.to_owned())?;
-        self.remainder = remainder;
+    /// [`ValveCommand`]: ValveCommand
+    /// [`FieldReciever`]: FieldReciever
+    /// [`CommandRetry::timeout`]: CommandRetry::timeout
+    /// [`CommandFailure`]: CommandFailure
+    pub fn send_commands(&mut self) -> Result<(), io::Error> {
+        let Some(device) = &mut self.device else {
+            return Ok(());
+        };
+
+        let now = Instant::now();
+        let expired: Vec<CommandId> = self
+            .pending_commands
+            .iter()
+            .filter(|(_, pending)| {
+                now.duration_since(pending.sent_at) >= self.command_retry.timeout
+            })
+            .map(|(&id, _)| id)
+            .collect();
+
+        for id in expired {
+            let Some(pending) = self.pending_commands.get_mut(&id) else {
+                continue;
+            };
 
-        for field in fields {
-            self.read_tx
-                .send(field)
-                .map_err(|_| SensorFieldReadError::DeadChannel)?
+            if pending.attempts >= self.command_retry.max_attempts {
+                let pending = self.pending_commands.remove(&id).unwrap();
+                report_event(
+                    &mut self.record,
+                    &self.event_tx,
+                    EventKind::CommandFailed(CommandFailure {
+                        id,
+                        command: pending.command,
+                        reason: CommandFailureReason::Unacknowledged(pending.attempts),
+                    }),
+                );
+
+                continue;
+            }
+
+            log::warn!(
+                "Resending unacknowledged command #{id}: {}",
+                pending.command
+            );
+
+            pending.attempts += 1;
+            pending.sent_at = now;
+            write_captured(
+                device,
+                &mut self.capture,
+                &self.event_tx,
+                pending.command.to_line(id).as_bytes(),
+            )?;
+        }
+
+        while let Ok(command) = self.command_rx.try_recv() {
+            let id = self.next_command_id;
+            self.next_command_id = self.next_command_id.wrapping_add(1);
+
+            log::info!("Sending command #{id}: {command}");
+            if let Err(e) = write_captured(
+                device,
+                &mut self.capture,
+                &self.event_tx,
+                command.to_line(id).as_bytes(),
+            ) {
+                // the command is neither pending nor queued any more, so would be lost silently
+                report_event(
+                    &mut self.record,
+                    &self.event_tx,
+                    EventKind::CommandFailed(CommandFailure {
+                        id,
+                        command,
+                        reason: CommandFailureReason::Disconnected,
+                    }),
+                );
+
+                return Err(e);
+            }
+
+            report_event(
+                &mut self.record,
+                &self.event_tx,
+                EventKind::CommandSent(id, command.clone()),
+            );
+
+            self.pending_commands.insert(
+                id,
+                PendingCommand {
+                    command,
+                    sent_at: Instant::now(),
+                    attempts: 1,
+                },
+            );
         }
 
+        device.flush()?;
         Ok(())
     }
 }
 
-impl<R> FieldSender<R>
-where
-    R: 'static + Write + Send,
-{
-    /// Recieve [`ValveCommand`]s from the [`FieldReciever`] and send them down serial.
+/// Identifier attached to every [`ValveCommand`] sent down serial, which the stand echoes back in
+/// its `ACK` or `NAK` field.
+///
+/// [`ValveCommand`]: ValveCommand
+pub type CommandId = u32;
+
+/// How long to wait for the stand to acknowledge a [`ValveCommand`], and how many times to send it
+/// before giving up.
+///
+/// [`ValveCommand`]: ValveCommand
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CommandRetry {
+    /// Time to wait for an `ACK` or `NAK` before resending a command.
+    pub timeout: Duration,
+
+    /// Total number of times a command is sent, including the first, before it is reported as a
+    /// [`CommandFailure`].
     ///
-    /// [`ValveCommand`]: ValveCommand
-    /// [`FieldReciever`]: FieldReciever
-    pub fn send_commands(&mut self) -> Result<(), io::Error> {
-        while let Ok(mut cmd) = self.command_rx.try_recv() {
-            log::info!(
-                "Sending command: {}",
-                String::from_utf8(cmd.clone()).unwrap().trim()
-            );
+    /// [`CommandFailure`]: CommandFailure
+    pub max_attempts: u32,
+}
 
-            cmd.push('\n' as _);
-            self.reader.write_all(&cmd)?;
+impl Default for CommandRetry {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_millis(250),
+            max_attempts: 4,
         }
+    }
+}
 
-        self.reader.flush()?;
-        Ok(())
+/// A [`ValveCommand`] which has been written down serial but not yet acknowledged.
+///
+/// [`ValveCommand`]: ValveCommand
+#[derive(Debug)]
+struct PendingCommand {
+    command: ValveCommand,
+    sent_at: Instant,
+    attempts: u32,
+}
+
+/// Things which happen in the [`FieldSender`]'s thread, reported back to the [`FieldReciever`].
+///
+/// [`FieldSender`]: FieldSender
+/// [`FieldReciever`]: FieldReciever
+#[derive(Debug, Clone)]
+enum SenderEvent {
+    /// A command was sent, acknowledged, or failed.
+    Stand(StandEvent),
+
+    /// A new device was attached.
+    Connected,
+
+    /// The device was lost or detached.
+    Disconnected,
+
+    /// The stand switched to sending fields in the given protocol.
+    ProtocolChanged(Protocol),
+
+    /// The counts of binary frames decoded so far changed.
+    FrameStats(FrameStats),
+
+    /// The estimate of the stand's clock against the host's changed.
+    ClockEstimate(ClockEstimate),
+
+    /// The capture could not be written to, so was stopped.
+    CaptureStopped,
+
+    /// The record could not be written to, so was stopped.
+    RecordStopped,
+
+    /// The watchdog changed state.
+    Watchdog(WatchdogState),
+}
+
+/// A [`ValveCommand`] which the stand did not act on.
+///
+/// [`ValveCommand`]: ValveCommand
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandFailure {
+    pub id: CommandId,
+    pub command: ValveCommand,
+    pub reason: CommandFailureReason,
+}
+
+/// Why a [`CommandFailure`] occured.
+///
+/// [`CommandFailure`]: CommandFailure
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommandFailureReason {
+    /// The stand replied with a `NAK` for the command.
+    Rejected,
+
+    /// The stand never replied to the command, after the given number of attempts.
+    Unacknowledged(u32),
+
+    /// There was no connection to the stand to send the command over, or it was lost before the
+    /// command was acknowledged.
+    Disconnected,
+}
+
+impl Display for CommandFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.reason {
+            CommandFailureReason::Rejected => {
+                write!(
+                    f,
+                    "Command #{} ({}) was rejected by the stand",
+                    self.id, self.command
+                )
+            }
+
+            CommandFailureReason::Unacknowledged(attempts) => write!(
+                f,
+                "Command #{} ({}) was not acknowledged after {attempts} attempts",
+                self.id, self.command
+            ),
+
+            CommandFailureReason::Disconnected => write!(
+                f,
+                "Command #{} ({}) was not acknowledged, no connection to the stand",
+                self.id, self.command
+            ),
+        }
     }
 }
+
+impl Error for CommandFailure {}
 /// A wrapper over an I/O device or pair of input and output devices which is intended for
 /// exchanging field (which should be the input) and commands (which this program should output).
 /// The type argument given should usually implement _both_ [`Read`] and [`Write`], but need not
@@ -364,8 +1603,8 @@ where
     R: Read,
 {
     device: R,
-    remainder: String,
-    fields: HashMap<String, SensorValue>,
+    protocol: Protocol,
+    command_retry: CommandRetry,
 }
 
 impl<R> FieldIO<R>
@@ -379,39 +1618,120 @@ where
     pub fn new(reader: R) -> Self {
         Self {
             device: reader,
-            remainder: String::new(),
-            fields: HashMap::new(),
+            protocol: Protocol::Text,
+            command_retry: CommandRetry::default(),
         }
     }
+
+    /// Set the [`CommandRetry`] policy used for commands sent to this device.
+    ///
+    /// [`CommandRetry`]: CommandRetry
+    pub fn with_command_retry(mut self, command_retry: CommandRetry) -> Self {
+        self.command_retry = command_retry;
+        self
+    }
+
+    /// Set the [`Protocol`] to ask the stand to send fields in once attached.
+    ///
+    /// [`Protocol`]: Protocol
+    pub fn with_protocol(mut self, protocol: Protocol) -> Self {
+        self.protocol = protocol;
+        self
+    }
 }
 
-/// Read as many [`SensorField`]s as can be parsed from the given [`Read`]. The [`String`] argument
-/// should be the returned [`String`] of the previous call to this function, or an empty [`String`]
-/// if this is the first call.
+/// Write the given bytes to the given [`FieldDevice`], recording them in the capture if there is
+/// one.
+///
+/// [`FieldDevice`]: FieldDevice
+fn write_captured(
+    device: &mut Box<dyn FieldDevice>,
+    capture: &mut Option<CaptureWriter>,
+    event_tx: &Sender<SenderEvent>,
+    bytes: &[u8],
+) -> io::Result<()> {
+    device.write_all(bytes)?;
+    capture_bytes(capture, event_tx, Direction::Written, bytes);
+    Ok(())
+}
+
+/// Record the given bytes in the capture if there is one, stopping the capture if it can no longer
+/// be written to.
+fn capture_bytes(
+    capture: &mut Option<CaptureWriter>,
+    event_tx: &Sender<SenderEvent>,
+    direction: Direction,
+    bytes: &[u8],
+) {
+    if let Some(writer) = capture
+        && let Err(e) = writer.record(direction, bytes)
+    {
+        log::error!("Failed to write capture, stopping capture: {e}");
+        *capture = None;
+        let _ = event_tx.send(SenderEvent::CaptureStopped);
+    }
+}
+
+/// Record the given kind of [`StandEvent`] as happening now, if recording, and report it to the
+/// [`FieldReciever`].
+///
+/// [`StandEvent`]: StandEvent
+/// [`FieldReciever`]: FieldReciever
+fn report_event(record: &mut Option<StandRecord>, event_tx: &Sender<SenderEvent>, kind: EventKind) {
+    let event = StandEvent::now(kind);
+    record_event(record, event_tx, &event);
+    let _ = event_tx.send(SenderEvent::Stand(event));
+}
+
+/// Append the given [`StandEvent`] to the record, if recording, stopping recording if it cannot
+/// be written to.
+///
+/// [`StandEvent`]: StandEvent
+fn record_event(
+    record: &mut Option<StandRecord>,
+    event_tx: &Sender<SenderEvent>,
+    event: &StandEvent,
+) {
+    if let Some(writer) = record
+        && let Err(e) = writer.append_event(event)
+    {
+        stop_recording(record, event_tx, e);
+    }
+}
+
+/// Stop recording after the record failed to be written with the given error.
+fn stop_recording(record: &mut Option<StandRecord>, event_tx: &Sender<SenderEvent>, e: io::Error) {
+    log::error!("Failed to write record, stopping recording: {e}");
+    *record = None;
+    let _ = event_tx.send(SenderEvent::RecordStopped);
+}
+
+/// Read whatever bytes are available from the given [`Read`]. A read which times out gives no
+/// bytes rather than being retried, so that the field thread carries on while the stand is
+/// silent.
 ///
-/// [`SensorField`]: SensorField
 /// [`Read`]: Read
-/// [`String`]: String
-fn read_fields<R>(
-    r: &mut R,
-    remainder: String,
-) -> Result<(String, Vec<SensorField>), SensorFieldReadError>
+fn read_bytes<R>(r: &mut R) -> Result<Vec<u8>, SensorFieldReadError>
 where
     R: Read,
 {
-    const MAX_READ_RETRYS: u32 = 16;
-
     let mut buf: [u8; 1024] = [0; 1024];
 
-    for i in 0..=MAX_READ_RETRYS {
-        match r.read(&mut buf) {
-            Ok(_) => break,
-            Err(e) if i != MAX_READ_RETRYS && e.kind() == io::ErrorKind::TimedOut => continue,
-            Err(e) => return Err(SensorFieldReadError::IoError(e)),
-        }
+    match r.read(&mut buf) {
+        Ok(len) => Ok(buf[..len].to_vec()),
+        Err(e) if e.kind() == io::ErrorKind::TimedOut => Ok(Vec::new()),
+        Err(e) => Err(SensorFieldReadError::IoError(e)),
     }
+}
 
-    let filtered_buf: Vec<u8> = buf.into_iter().filter(|&b| b != 0).collect();
+/// Parse as many [`SensorField`]s as possible from the given bytes of text. The [`String`]
+/// argument should be the returned [`String`] of the previous call to this function, or an empty
+/// [`String`] if this is the first call.
+///
+/// [`SensorField`]: SensorField
+/// [`String`]: String
+fn parse_text_fields(bytes: &[u8], remainder: String) -> (String, Vec<SensorField>) {
+    let filtered_buf: Vec<u8> = bytes.iter().copied().filter(|&b| b != 0).collect();
     let read_text = match String::from_utf8(filtered_buf) {
         Ok(s) => s,
         Err(e) => {
@@ -426,33 +1746,31 @@ where
     // Remove the last line since it might not be a complete field, which would cause a parse error.
     let (lines, remainder) = text.rsplit_once('\n').unwrap_or(("", text.as_str()));
 
-    #[cfg(not(feature = "allow_all_fields"))]
     let fields = lines
         .lines()
         .map(|line| parse_sensor_field(line))
         .filter_map(Result::ok)
-        .filter_map(
-            |field| match CHECKED_FIELD_NAMES.contains(&field.name.as_str()) {
-                true => Some(field),
-                false => {
-                    log::warn!(
-                        "Field '{}' was recieved but discarded!",
-                        field.name.as_str()
-                    );
-                    None
-                }
-            },
-        )
         .collect();
 
-    #[cfg(feature = "allow_all_fields")]
-    let fields = lines
-        .lines()
-        .map(|line| parse_sensor_field(line))
-        .filter_map(Result::ok)
-        .collect();
+    (remainder.to_string(), fields)
+}
 
-    Ok((remainder.to_string(), fields))
+/// The fields of the given [`DataFrame`], preceded by its timestamp as a field.
+///
+/// [`DataFrame`]: DataFrame
+fn frame_fields(frame: DataFrame) -> impl Iterator<Item = SensorField> {
+    let stand_time = frame.timestamp as f64 / 1e6;
+    let time_field = SensorField::new(
+        STAND_TIME_FIELD_NAME.to_string(),
+        SensorValue::Float(stand_time),
+    );
+
+    std::iter::once(time_field)
+        .chain(frame.fields)
+        .map(move |field| SensorField {
+            stand_time: Some(stand_time),
+            ..field
+        })
 }
 
 #[derive(Debug)]
@@ -472,24 +1790,14 @@ impl Display for SensorFieldReadError {
 
 impl Error for SensorFieldReadError {}
 
-pub const NILE_VALVE_NP1: &'static str = "NP1";
-pub const NILE_VALVE_NP2: &'static str = "NP2";
-pub const NILE_VALVE_NP3: &'static str = "NP3";
-pub const NILE_VALVE_NP4: &'static str = "NP4";
-
-pub const NILE_VALVE_IP1: &'static str = "IP1";
-pub const NILE_VALVE_IP2: &'static str = "IP2";
-pub const NILE_VALVE_IP3: &'static str = "IP3";
-pub const NILE_VALVE_ENGINE: &'static str = "ENG";
-
 /// A command for actuating valves on NILE.
-#[derive(Clone, Eq, PartialEq)]
+#[derive(Debug, Clone, Eq, PartialEq)]
 pub enum ValveCommand {
     /// Open a valve with the given name.
-    Open(&'static str),
+    Open(String),
 
     /// Close a valve with the given name.
-    Close(&'static str),
+    Close(String),
 }
 
 /// A field, presumably transmitted over serial representing the reading of a sensor on the NILE
@@ -498,6 +1806,14 @@ pub enum ValveCommand {
 pub struct SensorField {
     pub name: String,
     pub value: SensorValue,
+
+    /// When the reading was taken on the host's clock. Estimated from `stand_time` if the stand
+    /// stamped it, otherwise when it was read.
+    pub time: SystemTime,
+
+    /// When the reading was taken on the stand's clock, in seconds since the stand started, if the
+    /// stand stamped it.
+    pub stand_time: Option<f64>,
 }
 
 /// A value from a sensor. Includes basic primitives
@@ -510,14 +1826,23 @@ pub enum SensorValue {
 }
 
 impl ValveCommand {
-    /// Serialize the [`ValveCommand`] into a [`String`].
+    /// Serialize the [`ValveCommand`] into a line to send down serial, tagged with the given
+    /// [`CommandId`] like so:
+    ///
+    /// `OPEN:[valve name]#[id]`
     ///
     /// [`ValveCommand`]: ValveCommand
-    /// [`String`]: String
-    fn to_string(self) -> String {
+    /// [`CommandId`]: CommandId
+    fn to_line(&self, id: CommandId) -> String {
+        format!("\n{self}#{id}\n")
+    }
+}
+
+impl Display for ValveCommand {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            ValveCommand::Open(name) => format!("\nOPEN:{name}\n"),
-            ValveCommand::Close(name) => format!("\nCLOSE:{name}\n"),
+            ValveCommand::Open(name) => write!(f, "OPEN:{name}"),
+            ValveCommand::Close(name) => write!(f, "CLOSE:{name}"),
         }
     }
 }
@@ -533,6 +1858,20 @@ impl Display for SensorValue {
     }
 }
 
+impl SensorField {
+    /// A [`SensorField`] read now, not yet stamped with a stand time.
+    ///
+    /// [`SensorField`]: SensorField
+    pub fn new(name: String, value: SensorValue) -> Self {
+        Self {
+            name,
+            value,
+            time: SystemTime::now(),
+            stand_time: None,
+        }
+    }
+}
+
 impl SensorValue {
     pub fn to_num(self) -> f64 {
         match self {
@@ -556,6 +1895,7 @@ pub enum FieldParseError {
     MissingName,
     InvalidType(String),
     InvalidValue(String),
+    InvalidTime(String),
     ToManyTokens,
 }
 
@@ -568,6 +1908,7 @@ impl Display for FieldParseError {
             FieldParseError::MissingName => write!(f, "Missing field name"),
             FieldParseError::InvalidType(token) => write!(f, "Invalie field type: {token}"),
             FieldParseError::InvalidValue(token) => write!(f, "Invalid value: '{token}'"),
+            FieldParseError::InvalidTime(token) => write!(f, "Invalid stand time: '{token}'"),
             FieldParseError::ToManyTokens => write!(f, "To many tokens in field"),
         }
     }
@@ -579,11 +1920,20 @@ impl Error for FieldParseError {}
 ///
 /// `[name]:[type]=[value]`
 ///
+/// Optionally followed by the stand time the reading was taken at, in seconds since the stand
+/// started, which otherwise comes from the latest `Stand Time` field:
+///
+/// `[name]:[type]=[value]@[stand time]`
+///
+/// Stand times, including the value of a `Stand Time` field, must be finite and from zero to
+/// [`MAX_STAND_TIME`].
+///
 /// See [`serial::parse_sensor_value`] for more info on the `[type]` and `[value]` parts.
 ///
 /// `[name]` may be any text not including the ':' and '=' characters used as delimeters and also
 /// not including a newline.
 ///
+/// [`MAX_STAND_TIME`]: MAX_STAND_TIME
 /// [`SensorField`]: SensorField
 /// [`serial::parse_sensor_value`]: parse_sensor_value
 fn parse_sensor_field(s: &str) -> Result<SensorField, FieldParseError> {
@@ -608,12 +1958,41 @@ fn parse_sensor_field(s: &str) -> Result<SensorField, FieldParseError> {
         .filter(|&c| c != '\0')
         .collect();
 
+    let (value_token, stand_time) = match value_token.split_once('@') {
+        Some((value, time)) => {
+            let time = time
+                .trim()
+                .parse()
+                .ok()
+                .filter(is_stand_time)
+                .ok_or_else(|| FieldParseError::InvalidTime(time.to_string()))?;
+
+            (value.trim(), Some(time))
+        }
+
+        None => (value_token.as_str(), None),
+    };
+
+    let value = parse_sensor_value(value_token)?;
+
+    if name == STAND_TIME_FIELD_NAME && !is_stand_time(&value.to_num()) {
+        return Err(FieldParseError::InvalidTime(value.to_string()));
+    }
+
     Ok(SensorField {
-        name: name.to_string(),
-        value: parse_sensor_value(&value_token)?,
+        stand_time,
+        ..SensorField::new(name, value)
     })
 }
 
+/// Whether the given number of seconds is a stand time which may be used, being finite and from
+/// zero to [`MAX_STAND_TIME`].
+///
+/// [`MAX_STAND_TIME`]: MAX_STAND_TIME
+fn is_stand_time(time: &f64) -> bool {
+    (0.0..=MAX_STAND_TIME).contains(time)
+}
+
 /// Parses a [`SensorValue`] from a string in this format:
 ///
 /// `[type]=[value]`
@@ -675,3 +2054,445 @@ fn parse_sensor_value(s: &str) -> Result<SensorValue, FieldParseError> {
         _ => Err(FieldParseError::InvalidType(type_token.to_string())),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        calibration::{Calibration, FieldCalibration},
+        watchdog::WatchdogConfig,
+    };
+    use std::{collections::VecDeque, sync::Mutex};
+
+    /// What a test has sent through a [`Link`] as the stand, and what the console has written.
+    ///
+    /// [`Link`]: Link
+    #[derive(Debug, Default)]
+    struct LinkState {
+        to_read: VecDeque<u8>,
+        written: Vec<u8>,
+        lost: bool,
+        unwritable: bool,
+    }
+
+    /// An in-memory device which reads like a serial port, timing out whenever the stand has
+    /// sent nothing. Clones share the same link, so a test can play the stand through one while
+    /// the [`FieldSender`] has another.
+    ///
+    /// [`FieldSender`]: FieldSender
+    #[derive(Debug, Clone, Default)]
+    struct Link(Arc<Mutex<LinkState>>);
+
+    impl Link {
+        /// Send the given text from the stand.
+        fn send(&self, text: &str) {
+            self.0.lock().unwrap().to_read.extend(text.as_bytes());
+        }
+
+        /// Make every read and write fail as if the port had been unplugged.
+        fn lose(&self) {
+            self.0.lock().unwrap().lost = true;
+        }
+
+        /// Make every write fail, while reads carry on.
+        fn break_writes(&self) {
+            self.0.lock().unwrap().unwritable = true;
+        }
+
+        /// The number of times the given command has been written to the stand, counting every
+        /// resend.
+        fn writes(&self, command: &ValveCommand) -> usize {
+            let written = String::from_utf8_lossy(&self.0.lock().unwrap().written).to_string();
+            let prefix = format!("{command}#");
+            written
+                .lines()
+                .filter(|line| line.starts_with(&prefix))
+                .count()
+        }
+
+        /// The commands written to the stand, without their IDs, counting commands which were
+        /// resent once.
+        fn commands(&self) -> Vec<String> {
+            let written = String::from_utf8_lossy(&self.0.lock().unwrap().written).to_string();
+            let mut ids = Vec::new();
+
+            written
+                .lines()
+                .filter_map(|line| line.split_once('#'))
+                .filter(|&(_, id)| {
+                    let first = !ids.contains(&id);
+                    ids.push(id);
+                    first
+                })
+                .map(|(command, _)| command.to_string())
+                .collect()
+        }
+    }
+
+    impl Read for Link {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let mut state = self.0.lock().unwrap();
+
+            if state.lost {
+                return Err(io::ErrorKind::BrokenPipe.into());
+            }
+
+            if state.to_read.is_empty() {
+                drop(state);
+                thread::sleep(Duration::from_millis(1));
+                return Err(io::ErrorKind::TimedOut.into());
+            }
+
+            let len = buf.len().min(state.to_read.len());
+            for (b, byte) in buf.iter_mut().zip(state.to_read.drain(..len)) {
+                *b = byte;
+            }
+
+            Ok(len)
+        }
+    }
+
+    impl Write for Link {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let mut state = self.0.lock().unwrap();
+
+            if state.lost || state.unwritable {
+                return Err(io::ErrorKind::BrokenPipe.into());
+            }
+
+            state.written.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// How long the watchdog in [`watched_link`] waits before warning and before safing.
+    ///
+    /// [`watched_link`]: watched_link
+    const WARN_AFTER: Duration = Duration::from_millis(100);
+    const SAFE_AFTER: Duration = Duration::from_millis(300);
+
+    /// A field channel with a quick watchdog, attached to a [`Link`] over which the stand has sent
+    /// one field.
+    ///
+    /// [`Link`]: Link
+    fn watched_link() -> (FieldSender, FieldReciever, Link) {
+        let (mut sender, mut reciever) = field_channel(
+            vec!["NPT1".to_string()],
+            Calibrator::default(),
+            DerivedFields::default(),
+            SafetyConfig {
+                redlines: Vec::new(),
+                watchdog: WatchdogConfig {
+                    warn_after: WARN_AFTER,
+                    safe_after: SAFE_AFTER,
+                },
+                safing_commands: safing_commands(),
+            },
+        );
+
+        let link = Link::default();
+        link.send("NPT1:f=1.0\n");
+        reciever.attach(FieldIO::new(link.clone())).unwrap();
+        sender.step().unwrap();
+
+        (sender, reciever, link)
+    }
+
+    fn safing_commands() -> Vec<ValveCommand> {
+        vec![
+            ValveCommand::Close("NP1".to_string()),
+            ValveCommand::Open("NP3".to_string()),
+        ]
+    }
+
+    /// The safing commands as written to the stand, repeated the given number of times.
+    fn safed(times: usize) -> Vec<String> {
+        safing_commands()
+            .iter()
+            .map(ValveCommand::to_string)
+            .cycle()
+            .take(times * safing_commands().len())
+            .collect()
+    }
+
+    /// Run the field thread's loop for the given time, then recieve what it sent.
+    fn run_for(sender: &mut FieldSender, reciever: &mut FieldReciever, time: Duration) {
+        let start = Instant::now();
+
+        while start.elapsed() < time {
+            sender.step().unwrap();
+        }
+
+        reciever.recieve_fields().unwrap();
+    }
+
+    #[test]
+    fn parses_stamped_fields() {
+        let field = parse_sensor_field("NPT1:f=420.5@12.25").unwrap();
+        assert_eq!(field.name, "NPT1");
+        assert_eq!(field.value, SensorValue::Float(420.5));
+        assert_eq!(field.stand_time, Some(12.25));
+
+        let field = parse_sensor_field("Stand Time:f=0").unwrap();
+        assert_eq!(field.value, SensorValue::Float(0.0));
+    }
+
+    #[test]
+    fn rejects_unusable_stand_times() {
+        for line in [
+            "NPT1:f=1.0@inf",
+            "NPT1:f=1.0@-inf",
+            "NPT1:f=1.0@nan",
+            "NPT1:f=1.0@1e300",
+            "NPT1:f=1.0@-1",
+            "Stand Time:f=inf",
+            "Stand Time:f=nan",
+            "Stand Time:f=1e300",
+            "Stand Time:i=-5",
+        ] {
+            assert!(
+                matches!(
+                    parse_sensor_field(line),
+                    Err(FieldParseError::InvalidTime(_))
+                ),
+                "{line} was accepted"
+            );
+        }
+    }
+
+    #[test]
+    fn corrupt_stand_times_do_not_stop_the_field_thread() {
+        let (mut sender, mut reciever) = field_channel(
+            vec![STAND_TIME_FIELD_NAME.to_string(), "NPT1".to_string()],
+            Calibrator::default(),
+            DerivedFields::default(),
+            SafetyConfig::default(),
+        );
+
+        let text = b"Stand Time:f=1.5\nNPT1:f=1.0@inf\nStand Time:f=inf\nNPT1:f=2.0\n";
+        reciever
+            .attach(FieldIO::new(io::Cursor::new(text.to_vec())))
+            .unwrap();
+        sender.handle_controls();
+        sender.send_fields().unwrap();
+        reciever.recieve_fields().unwrap();
+
+        let fields = reciever.recieved_fields();
+        assert_eq!(fields.len(), 2);
+        assert_eq!(fields[1].value, SensorValue::Float(2.0));
+        assert_eq!(fields[1].stand_time, Some(1.5));
+    }
+
+    #[test]
+    fn zero_tares_are_kept_until_cleared() {
+        let calibration = Calibration {
+            version: "test".to_string(),
+            fields: vec![FieldCalibration {
+                name: "Scale Ox".to_string(),
+                unit: None,
+                polynomial: None,
+                table: None,
+                tare: true,
+            }],
+        };
+        let (mut sender, mut reciever) = field_channel(
+            vec!["Scale Ox".to_string()],
+            Calibrator::new(&calibration),
+            DerivedFields::default(),
+            SafetyConfig::default(),
+        );
+
+        let text = b"Scale Ox:f=0.0\n";
+        reciever
+            .attach(FieldIO::new(io::Cursor::new(text.to_vec())))
+            .unwrap();
+        sender.handle_controls();
+        sender.send_fields().unwrap();
+
+        reciever.tare("Scale Ox");
+        sender.handle_controls();
+        reciever.recieve_fields().unwrap();
+        assert_eq!(reciever.tares().get("Scale Ox"), Some(&0.0));
+
+        reciever.clear_tare("Scale Ox");
+        sender.handle_controls();
+        reciever.recieve_fields().unwrap();
+        assert!(reciever.tares().is_empty());
+    }
+
+    #[test]
+    fn watchdog_safes_a_silent_stand_once() {
+        let (mut sender, mut reciever, link) = watched_link();
+
+        run_for(&mut sender, &mut reciever, Duration::from_millis(20));
+        assert_eq!(reciever.watchdog_state(), WatchdogState::Nominal);
+
+        run_for(&mut sender, &mut reciever, Duration::from_millis(130));
+        assert!(matches!(
+            reciever.watchdog_state(),
+            WatchdogState::Warning(staleness) if staleness >= WARN_AFTER && staleness < SAFE_AFTER
+        ));
+        assert_eq!(reciever.take_failsafe(), None);
+        assert!(link.commands().is_empty());
+
+        run_for(&mut sender, &mut reciever, Duration::from_millis(200));
+        assert_eq!(
+            reciever.watchdog_state(),
+            WatchdogState::Tripped {
+                safed_with_link: false
+            }
+        );
+        assert_eq!(reciever.take_failsafe().as_deref(), Some("watchdog"));
+        assert_eq!(link.commands(), safed(1));
+
+        // the stand stays silent, but is not safed again
+        run_for(&mut sender, &mut reciever, Duration::from_millis(200));
+        assert_eq!(reciever.take_failsafe(), None);
+        assert_eq!(link.commands(), safed(1));
+
+        // once the stand is heard from again it is safed again, in case it missed the commands
+        link.send("NPT1:f=2.0\n");
+        run_for(&mut sender, &mut reciever, Duration::from_millis(20));
+        assert_eq!(
+            reciever.watchdog_state(),
+            WatchdogState::Tripped {
+                safed_with_link: true
+            }
+        );
+        assert_eq!(reciever.take_failsafe().as_deref(), Some("watchdog"));
+        assert_eq!(link.commands(), safed(2));
+
+        link.send("NPT1:f=3.0\n");
+        run_for(&mut sender, &mut reciever, Duration::from_millis(20));
+        assert_eq!(link.commands(), safed(2));
+
+        reciever.reset_watchdog();
+        link.send("NPT1:f=4.0\n");
+        run_for(&mut sender, &mut reciever, Duration::from_millis(20));
+        assert_eq!(reciever.watchdog_state(), WatchdogState::Nominal);
+        assert_eq!(link.commands(), safed(2));
+    }
+
+    #[test]
+    fn watchdog_recovers_if_fields_return_in_time() {
+        let (mut sender, mut reciever, link) = watched_link();
+
+        run_for(&mut sender, &mut reciever, Duration::from_millis(150));
+        assert!(matches!(
+            reciever.watchdog_state(),
+            WatchdogState::Warning(_)
+        ));
+
+        link.send("NPT1:f=2.0\n");
+        run_for(&mut sender, &mut reciever, Duration::from_millis(20));
+        assert_eq!(reciever.watchdog_state(), WatchdogState::Nominal);
+        assert_eq!(reciever.take_failsafe(), None);
+        assert!(link.commands().is_empty());
+    }
+
+    #[test]
+    fn watchdog_safes_again_when_a_lost_link_returns() {
+        let (mut sender, mut reciever, link) = watched_link();
+
+        // a lost link is stale at once, and the stand is safed once it has been lost long enough
+        link.lose();
+        run_for(&mut sender, &mut reciever, Duration::from_millis(20));
+        assert!(!reciever.is_connected());
+        assert!(matches!(
+            reciever.watchdog_state(),
+            WatchdogState::Warning(_)
+        ));
+
+        run_for(&mut sender, &mut reciever, Duration::from_millis(400));
+        assert_eq!(
+            reciever.watchdog_state(),
+            WatchdogState::Tripped {
+                safed_with_link: false
+            }
+        );
+        assert_eq!(reciever.take_failsafe().as_deref(), Some("watchdog"));
+
+        // with no link the safing commands could not be sent
+        let failures: Vec<String> = reciever
+            .command_failures()
+            .iter()
+            .inspect(|f| assert_eq!(f.reason, CommandFailureReason::Disconnected))
+            .map(|f| f.command.to_string())
+            .collect();
+        assert_eq!(failures, safed(1));
+
+        // a new link is not safed over until the stand is heard from on it
+        let new_link = Link::default();
+        reciever.attach(FieldIO::new(new_link.clone())).unwrap();
+        run_for(&mut sender, &mut reciever, Duration::from_millis(20));
+        assert!(new_link.commands().is_empty());
+
+        new_link.send("NPT1:f=2.0\n");
+        run_for(&mut sender, &mut reciever, Duration::from_millis(20));
+        assert_eq!(
+            reciever.watchdog_state(),
+            WatchdogState::Tripped {
+                safed_with_link: true
+            }
+        );
+        assert_eq!(reciever.take_failsafe().as_deref(), Some("watchdog"));
+        assert_eq!(new_link.commands(), safed(1));
+        assert_eq!(reciever.command_failures().len(), 2);
+    }
+
+    #[test]
+    fn commands_which_fail_to_write_are_reported() {
+        let (mut sender, mut reciever, link) = watched_link();
+        let commands = [
+            ValveCommand::Open("NP2".to_string()),
+            ValveCommand::Close("NP4".to_string()),
+        ];
+
+        link.break_writes();
+        for command in &commands {
+            reciever.send_command(command.clone()).unwrap();
+        }
+        run_for(&mut sender, &mut reciever, Duration::from_millis(100));
+
+        assert!(!reciever.is_connected());
+        let failures: Vec<(CommandId, ValveCommand)> = reciever
+            .command_failures()
+            .iter()
+            .inspect(|f| assert_eq!(f.reason, CommandFailureReason::Disconnected))
+            .map(|f| (f.id, f.command.clone()))
+            .collect();
+        assert_eq!(
+            failures,
+            [(0, commands[0].clone()), (1, commands[1].clone())]
+        );
+    }
+
+    #[test]
+    fn out_of_range_acknowledgements_are_ignored() {
+        let (mut sender, mut reciever, link) = watched_link();
+        let command = ValveCommand::Open("NP2".to_string());
+
+        reciever.send_command(command.clone()).unwrap();
+        run_for(&mut sender, &mut reciever, Duration::from_millis(20));
+        assert_eq!(link.writes(&command), 1);
+
+        // truncated to a command ID this would acknowledge command #0
+        link.send(&format!("ACK:u={}\n", (1u64 << 32)));
+        run_for(&mut sender, &mut reciever, Duration::from_millis(300));
+        assert_eq!(link.writes(&command), 2);
+
+        link.send("ACK:u=0\n");
+        run_for(&mut sender, &mut reciever, Duration::from_millis(300));
+        assert_eq!(link.writes(&command), 2);
+        assert!(
+            !reciever
+                .command_failures()
+                .iter()
+                .any(|f| f.command == command)
+        );
+    }
+}
@@ -0,0 +1,199 @@
+use crate::{
+    calibration::Calibration,
+    clock::file_timestamp,
+    serial::{PortSettings, UsbSerialPortInfo},
+};
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+/// Name of the metadata file written into each session directory.
+pub const METADATA_FILE: &str = "session.toml";
+
+/// Name of the [`StandRecord`] CSV written into each session directory.
+///
+/// [`StandRecord`]: StandRecord
+pub const RECORD_FILE: &str = "record.csv";
+
+/// Everything about a test which is not in its record, written into its session directory as
+/// [`METADATA_FILE`].
+///
+/// [`METADATA_FILE`]: METADATA_FILE
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionMetadata {
+    pub test_id: String,
+    pub operator: String,
+    pub test_article: String,
+
+    /// The engine or timing valve selected to fire, if any.
+    pub engine: Option<String>,
+
+    /// Fire time entered by the operator, in seconds.
+    pub fire_time: f64,
+    pub target_ox_fuel_ratio: f32,
+    pub target_ox_fuel_deviation: f32,
+
+    /// Version of the console which recorded the session.
+    pub console_version: String,
+
+    /// Whether the session was started by entering [`StandMode::PressurizationAndFiring`] rather
+    /// than by the operator.
+    ///
+    /// [`StandMode::PressurizationAndFiring`]: crate::stand::StandMode::PressurizationAndFiring
+    pub automatic: bool,
+
+    /// UTC times the session started and stopped at, formatted as by [`file_timestamp`].
+    ///
+    /// [`file_timestamp`]: file_timestamp
+    pub started_at: String,
+    pub stopped_at: Option<String>,
+
+    /// The serial port the stand was connected on, if any.
+    pub port: Option<PortMetadata>,
+
+    /// Offsets subtracted from each tared field when the session started. Tares set during the
+    /// session are in its events.
+    pub tares: BTreeMap<String, f64>,
+
+    /// The [`Calibration`] fields were recorded with, if any, which gives its version.
+    ///
+    /// [`Calibration`]: Calibration
+    pub calibration: Option<Calibration>,
+}
+
+/// The serial port a session was recorded from, and the settings it was opened with.
+#[derive(Debug, Clone, Serialize)]
+pub struct PortMetadata {
+    pub name: String,
+    pub vid: u16,
+    pub pid: u16,
+    pub serial_number: Option<String>,
+    pub manufacturer: Option<String>,
+    pub product: Option<String>,
+    pub baud: u32,
+    pub flow_control: String,
+    pub protocol: String,
+}
+
+impl PortMetadata {
+    pub fn new(port: &UsbSerialPortInfo, settings: PortSettings) -> Self {
+        Self {
+            name: port.port_name.clone(),
+            vid: port.usb_info.vid,
+            pid: port.usb_info.pid,
+            serial_number: port.usb_info.serial_number.clone(),
+            manufacturer: port.usb_info.manufacturer.clone(),
+            product: port.usb_info.product.clone(),
+            baud: settings.baud,
+            flow_control: format!("{:?}", settings.flow_control),
+            protocol: settings.protocol.to_string(),
+        }
+    }
+}
+
+/// A recording session: a directory named after its start time, test ID and engine, holding a
+/// [`StandRecord`] at [`Session::record_path`] and its [`SessionMetadata`].
+///
+/// [`StandRecord`]: crate::record::StandRecord
+/// [`Session::record_path`]: Session::record_path
+/// [`SessionMetadata`]: SessionMetadata
+#[derive(Debug)]
+pub struct Session {
+    dir: PathBuf,
+    metadata: SessionMetadata,
+}
+
+impl Session {
+    /// Start a new [`Session`] in a new directory under the given one. The metadata's start time
+    /// is set to now.
+    ///
+    /// [`Session`]: Session
+    pub fn start<P>(parent: P, mut metadata: SessionMetadata) -> io::Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        metadata.started_at = file_timestamp(SystemTime::now());
+        metadata.stopped_at = None;
+
+        let name = [
+            Some(metadata.started_at.as_str()),
+            Some(metadata.test_id.as_str()),
+            metadata.engine.as_deref(),
+        ]
+        .into_iter()
+        .flatten()
+        .map(file_name_part)
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("_");
+
+        let dir = unused_dir(parent.as_ref().join(name));
+        fs::create_dir_all(&dir)?;
+
+        let session = Self { dir, metadata };
+        session.write_metadata()?;
+
+        log::info!("Started session in {}", session.dir.display());
+        Ok(session)
+    }
+
+    /// The directory this [`Session`] is recorded in.
+    ///
+    /// [`Session`]: Session
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn metadata(&self) -> &SessionMetadata {
+        &self.metadata
+    }
+
+    /// Path the session's [`StandRecord`] should be recorded to.
+    ///
+    /// [`StandRecord`]: crate::record::StandRecord
+    pub fn record_path(&self) -> PathBuf {
+        self.dir.join(RECORD_FILE)
+    }
+
+    /// Finish the session, noting the time in the metadata.
+    pub fn stop(mut self) -> io::Result<()> {
+        self.metadata.stopped_at = Some(file_timestamp(SystemTime::now()));
+        log::info!("Stopped session in {}", self.dir.display());
+        self.write_metadata()
+    }
+
+    fn write_metadata(&self) -> io::Result<()> {
+        let text = toml::to_string_pretty(&self.metadata).map_err(io::Error::other)?;
+        fs::write(self.dir.join(METADATA_FILE), text)
+    }
+}
+
+/// The given text with anything but letters, digits, `-`, `_` and `.` replaced, fit for a part of
+/// a file name.
+fn file_name_part(text: &str) -> String {
+    text.trim()
+        .chars()
+        .map(|c| match c {
+            c if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.') => c,
+            _ => '-',
+        })
+        .collect()
+}
+
+/// The given path if nothing exists there yet, or else the path with the lowest numbered suffix
+/// which does not exist.
+fn unused_dir(path: PathBuf) -> PathBuf {
+    if !path.exists() {
+        return path;
+    }
+
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    (2..)
+        .map(|n| path.with_file_name(format!("{name}_{n}")))
+        .find(|path| !path.exists())
+        .unwrap()
+}
@@ -0,0 +1,1278 @@
+use crate::{
+    config::{StandConfig, ValveKind},
+    frame::{FrameEncoder, PROTOCOL_REPLY, PROTOCOL_REQUEST},
+    serial::{STAND_TIME_FIELD_NAME, SensorValue},
+};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    error::Error,
+    fmt::Display,
+    fs,
+    io::{self, Read, Write},
+    path::Path,
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Longest a [`ScriptedStand`] blocks a read for while waiting for its next line to be due.
+///
+/// [`ScriptedStand`]: ScriptedStand
+const READ_WAIT: Duration = Duration::from_millis(10);
+
+/// A simulated stand which plays back field lines from a scenario file at set times, so that the
+/// console may be tested against specific values, such as a pressure running past its redline.
+///
+/// Each line of a scenario is the number of seconds after the first read to send at, followed by
+/// a field line exactly as the stand would send it:
+///
+/// `1.5 NPT1:f=420.0`
+///
+/// Blank lines and lines starting with `#` are ignored. Commands written to the stand are
+/// acknowledged, and the commanded valve is reported in its new state.
+#[derive(Debug)]
+pub struct ScriptedStand {
+    lines: VecDeque<(Duration, String)>,
+    started: Option<Instant>,
+    /// Bytes waiting to be read.
+    output: VecDeque<u8>,
+    /// Partial command line written so far.
+    input: String,
+}
+
+impl ScriptedStand {
+    /// Load a [`ScriptedStand`] from the scenario file at the given path.
+    ///
+    /// [`ScriptedStand`]: ScriptedStand
+    pub fn load<P>(path: P) -> Result<Self, ScenarioError>
+    where
+        P: AsRef<Path>,
+    {
+        let text = fs::read_to_string(path).map_err(ScenarioError::Io)?;
+        Self::parse(&text)
+    }
+
+    /// Parse a [`ScriptedStand`] from the text of a scenario.
+    ///
+    /// [`ScriptedStand`]: ScriptedStand
+    pub fn parse(text: &str) -> Result<Self, ScenarioError> {
+        let mut lines = Vec::new();
+
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (time, field) = line
+                .split_once(char::is_whitespace)
+                .ok_or(ScenarioError::Syntax(i + 1))?;
+
+            let time = time
+                .parse()
+                .ok()
+                .and_then(|time| Duration::try_from_secs_f64(time).ok())
+                .ok_or(ScenarioError::Syntax(i + 1))?;
+
+            lines.push((time, field.trim().to_string()));
+        }
+
+        // Keep lines given at the same time in the order they were written.
+        lines.sort_by_key(|&(time, _)| time);
+
+        Ok(Self {
+            lines: lines.into(),
+            started: None,
+            output: VecDeque::new(),
+            input: String::new(),
+        })
+    }
+
+    /// Queue every line which is due to be sent, giving how long until the next one is.
+    fn queue_due_lines(&mut self) -> Option<Duration> {
+        let elapsed = self.started.get_or_insert_with(Instant::now).elapsed();
+
+        while let Some((time, _)) = self.lines.front() {
+            if *time > elapsed {
+                return Some(*time - elapsed);
+            }
+
+            let (_, line) = self.lines.pop_front().unwrap();
+            self.queue_line(&line);
+        }
+
+        None
+    }
+
+    fn queue_line(&mut self, line: &str) {
+        self.output.extend(line.as_bytes());
+        self.output.push_back(b'\n');
+    }
+
+    /// Respond to a single command line written to the stand.
+    fn handle_command(&mut self, line: &str) {
+        let Some((id, command)) = parse_command(line) else {
+            return;
+        };
+
+        match command {
+            Some((valve, open)) => {
+                self.queue_line(&format!("ACK:u={id}"));
+                self.queue_line(&field_line(valve, SensorValue::Boolean(open)));
+            }
+
+            None => self.queue_line(&format!("NAK:u={id}")),
+        }
+    }
+}
+
+impl Read for ScriptedStand {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let until_next = self.queue_due_lines();
+
+        if self.output.is_empty() {
+            thread::sleep(until_next.map_or(READ_WAIT, |t| t.min(READ_WAIT)));
+            return Ok(0);
+        }
+
+        let len = buf.len().min(self.output.len());
+        for (b, out) in buf.iter_mut().zip(self.output.drain(..len)) {
+            *b = out;
+        }
+
+        Ok(len)
+    }
+}
+
+impl Write for ScriptedStand {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.input.push_str(&String::from_utf8_lossy(buf));
+
+        while let Some((line, rest)) = self.input.split_once('\n') {
+            let line = line.trim().to_string();
+            self.input = rest.to_string();
+
+            if !line.is_empty() {
+                self.handle_command(&line);
+            }
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Rate a [`SimulatedStand`] sends fields at unless another is given, in hertz.
+///
+/// [`SimulatedStand`]: SimulatedStand
+pub const DEFAULT_SIM_RATE: f64 = 50.0;
+
+/// Time step the [`SimulatedStand`]'s physics is advanced by.
+///
+/// [`SimulatedStand`]: SimulatedStand
+const SIM_STEP: f64 = 0.001;
+
+/// Fastest rate a [`SimulatedStand`] can send fields at, in hertz, as it sends at most one frame
+/// per physics step.
+///
+/// [`SimulatedStand`]: SimulatedStand
+pub const MAX_SIM_RATE: f64 = 1.0 / SIM_STEP;
+
+/// Seconds a simulated valve takes to change state after being commanded.
+const VALVE_ACTUATION_TIME: f64 = 0.08;
+
+/// Pressure of the nitrogen supply which the tanks are pressurized from, in psi.
+const SUPPLY_PRESSURE: f64 = 500.0;
+
+/// Time constants, in seconds, of a tank's pressure approaching the supply through its
+/// pressurization valve, falling through its vent valve, and falling through its bleed valve.
+const PRESSURIZE_TIME_CONSTANT: f64 = 8.0;
+const VENT_TIME_CONSTANT: f64 = 1.5;
+const BLEED_TIME_CONSTANT: f64 = 6.0;
+
+/// Pressure lost from a tank for each pound of propellant expelled from it, in psi.
+const BLOWDOWN: f64 = 40.0;
+
+/// Fraction of tank pressure lost between the tank and the line sensor while propellant flows.
+const LINE_PRESSURE_DROP: f64 = 0.3;
+
+/// Time constants, in seconds, of line pressures and thrust following the flow.
+const LINE_TIME_CONSTANT: f64 = 0.1;
+const THRUST_TIME_CONSTANT: f64 = 0.05;
+
+/// Thrust produced per pound per second of propellant burnt, in lbf. Propellant flowing without
+/// being lit produces only [`COLD_FLOW_FRACTION`] of this.
+///
+/// [`COLD_FLOW_FRACTION`]: COLD_FLOW_FRACTION
+const SPECIFIC_IMPULSE: f64 = 180.0;
+const COLD_FLOW_FRACTION: f64 = 0.1;
+
+/// Tank pressure below which propellant stops flowing, in psi.
+const MIN_FLOW_PRESSURE: f64 = 20.0;
+
+/// Seconds between the field tables a [`SimulatedStand`] sends while sending binary frames.
+///
+/// [`SimulatedStand`]: SimulatedStand
+const TABLE_INTERVAL: f64 = 1.0;
+
+/// Amplitude of the noise added to pressures, in psi, and to scale readings, in lb or lbf.
+const PRESSURE_NOISE: f64 = 0.5;
+const SCALE_NOISE: f64 = 0.02;
+
+/// How much faster the simulated stand's clock runs than the host's, as a fraction, so that the
+/// console has some drift to estimate.
+const CLOCK_DRIFT: f64 = 50e-6;
+
+/// A simulated NILE stand which runs in process, so that procedures may be rehearsed and the
+/// console tested without the stand.
+///
+/// Valves respond to `OPEN:`/`CLOSE:` commands after a short actuation time. The nitrogen (`NP`)
+/// and oxidizer (`IP`) tanks are pressurized through `NP2`/`IP2` and vented through `NP3`/`IP3`,
+/// with `NP4` bleeding the nitrogen side. Opening an engine valve while the igniter is open lights
+/// the engine, which burns propellant from both tanks in proportion to the square root of their
+/// pressures, draining the scales and producing thrust until the engine valve closes. Timing valves
+/// flow propellant without lighting. Every field the config expects is sent at a fixed rate, as
+/// text or, once asked for them, as binary frames.
+#[derive(Debug)]
+pub struct SimulatedStand {
+    valves: Vec<SimValve>,
+    /// Names of the fields the stand is expected to send, others are not sent.
+    fields: HashSet<String>,
+    fuel: SimTank,
+    ox: SimTank,
+    lit: bool,
+    thrust: f64,
+    last_thrust: f64,
+
+    /// Seconds simulated so far.
+    time: f64,
+    next_frame: f64,
+    frame_period: f64,
+    started: Option<Instant>,
+    /// State of the random number generator used for noise and faults.
+    random: u64,
+
+    faults: Vec<ScheduledFault>,
+    /// Values sensors with a [`Fault::StuckSensor`] are stuck at.
+    ///
+    /// [`Fault::StuckSensor`]: Fault::StuckSensor
+    stuck_values: HashMap<String, f64>,
+    disconnected: bool,
+
+    /// Encodes fields once the console has asked for binary frames.
+    encoder: Option<FrameEncoder>,
+    last_table: f64,
+
+    /// Bytes waiting to be read.
+    output: VecDeque<u8>,
+    /// Partial command line written so far.
+    input: String,
+}
+
+#[derive(Debug)]
+struct SimValve {
+    name: String,
+    kind: ValveKind,
+    open: bool,
+    /// The state the valve was last commanded to, and the time it will reach it at.
+    commanded: Option<(bool, f64)>,
+}
+
+#[derive(Debug)]
+struct SimTank {
+    pressure_field: &'static str,
+    line_field: &'static str,
+    mass_field: &'static str,
+    mass_rate_field: &'static str,
+    pressurize_valve: &'static str,
+    vent_valve: &'static str,
+    bleed_valve: Option<&'static str>,
+    /// Flow through the engine, in lb/s per square root psi of tank pressure.
+    flow_coefficient: f64,
+
+    pressure: f64,
+    line_pressure: f64,
+    mass: f64,
+    flow: f64,
+}
+
+impl SimulatedStand {
+    /// Create a [`SimulatedStand`] with the valves and fields of the given [`StandConfig`],
+    /// sending fields the given number of times per second.
+    ///
+    /// [`SimulatedStand`]: SimulatedStand
+    /// [`StandConfig`]: StandConfig
+    pub fn new(config: &StandConfig, rate: f64) -> Self {
+        let valves = config
+            .valves
+            .iter()
+            .map(|v| SimValve {
+                name: v.name.clone(),
+                kind: v.kind,
+                open: false,
+                commanded: None,
+            })
+            .collect();
+
+        Self {
+            valves,
+            fields: config.field_names().into_iter().collect(),
+            fuel: SimTank {
+                pressure_field: "NPT1",
+                line_field: "NPT3",
+                mass_field: "Scale Fuel",
+                mass_rate_field: "Scale Fuel Rate",
+                pressurize_valve: "NP2",
+                vent_valve: "NP3",
+                bleed_valve: Some("NP4"),
+                flow_coefficient: 0.01,
+                pressure: 0.0,
+                line_pressure: 0.0,
+                mass: 4.0,
+                flow: 0.0,
+            },
+            ox: SimTank {
+                pressure_field: "IPT1",
+                line_field: "IPT3",
+                mass_field: "Scale Ox",
+                mass_rate_field: "Scale Ox Rate",
+                pressurize_valve: "IP2",
+                vent_valve: "IP3",
+                bleed_valve: None,
+                flow_coefficient: 0.025,
+                pressure: 0.0,
+                line_pressure: 0.0,
+                mass: 10.0,
+                flow: 0.0,
+            },
+            lit: false,
+            thrust: 0.0,
+            last_thrust: 0.0,
+            time: 0.0,
+            next_frame: 0.0,
+            frame_period: 1.0 / rate,
+            started: None,
+            random: 0x2545_f491_4f6c_dd1d,
+            faults: Vec::new(),
+            stuck_values: HashMap::new(),
+            disconnected: false,
+            encoder: None,
+            last_table: 0.0,
+            output: VecDeque::new(),
+            input: String::new(),
+        }
+    }
+
+    /// Inject the faults of the given [`FaultSchedule`] into the stand.
+    ///
+    /// [`FaultSchedule`]: FaultSchedule
+    pub fn with_faults(mut self, schedule: FaultSchedule) -> Self {
+        self.faults = schedule.faults;
+        self
+    }
+
+    /// Gives an [`Iterator`] of the faults currently in effect.
+    ///
+    /// [`Iterator`]: Iterator
+    fn active_faults(&self) -> impl Iterator<Item = &Fault> {
+        self.faults
+            .iter()
+            .filter(|f| f.start <= self.time && f.end.is_none_or(|end| self.time < end))
+            .map(|f| &f.fault)
+    }
+
+    fn is_paused(&self) -> bool {
+        self.active_faults().any(|f| *f == Fault::Pause)
+    }
+
+    /// Apply the faults which take effect once, at their start time.
+    fn start_faults(&mut self) {
+        for fault in self.faults.iter_mut() {
+            if fault.started || fault.start > self.time {
+                continue;
+            }
+
+            fault.started = true;
+            log::warn!("Simulated fault: {}", fault.fault);
+
+            match fault.fault {
+                Fault::NulBurst(count) => self.output.extend(std::iter::repeat_n(0, count)),
+                Fault::Disconnect => self.disconnected = true,
+                _ => (),
+            }
+        }
+    }
+
+    fn any_open(&self, kind: ValveKind) -> bool {
+        self.valves.iter().any(|v| v.kind == kind && v.open)
+    }
+
+    /// Advance the simulation to the current time, queueing a frame of fields whenever one is due.
+    /// Gives how long until the next frame is due.
+    fn run_until_now(&mut self) -> Duration {
+        let now = self.started.get_or_insert_with(Instant::now).elapsed();
+
+        while self.time < now.as_secs_f64() {
+            self.start_faults();
+
+            if self.time >= self.next_frame {
+                if !self.is_paused() {
+                    self.queue_frame();
+                }
+
+                self.next_frame += self.frame_period;
+            }
+
+            self.step(SIM_STEP);
+        }
+
+        Duration::from_secs_f64((self.next_frame - self.time).max(0.0))
+    }
+
+    /// Advance the simulation by the given number of seconds.
+    fn step(&mut self, dt: f64) {
+        self.time += dt;
+
+        let stuck: Vec<String> = self
+            .active_faults()
+            .filter_map(|f| match f {
+                Fault::StuckValve(name) => Some(name.clone()),
+                _ => None,
+            })
+            .collect();
+
+        for valve in &mut self.valves {
+            if let Some((open, at)) = valve.commanded
+                && self.time >= at
+            {
+                if !stuck.contains(&valve.name) {
+                    valve.open = open;
+                }
+
+                valve.commanded = None;
+            }
+        }
+
+        let engine_open = self.any_open(ValveKind::Engine);
+
+        if engine_open && self.any_open(ValveKind::Igniter) {
+            self.lit = true;
+        } else if !engine_open {
+            self.lit = false;
+        }
+
+        let flowing = (engine_open || self.any_open(ValveKind::Timing))
+            && [&self.fuel, &self.ox]
+                .iter()
+                .all(|t| t.pressure > MIN_FLOW_PRESSURE && t.mass > 0.0);
+
+        for tank in [&mut self.fuel, &mut self.ox] {
+            let is_open = |name: &str| self.valves.iter().any(|v| v.name == name && v.open);
+            let pressure = tank.pressure;
+            let mut rate = 0.0;
+
+            if is_open(tank.pressurize_valve) {
+                rate += (SUPPLY_PRESSURE - pressure) / PRESSURIZE_TIME_CONSTANT;
+            }
+
+            if is_open(tank.vent_valve) {
+                rate -= pressure / VENT_TIME_CONSTANT;
+            }
+
+            if tank.bleed_valve.is_some_and(is_open) {
+                rate -= pressure / BLEED_TIME_CONSTANT;
+            }
+
+            tank.flow = if flowing {
+                (tank.flow_coefficient * pressure.sqrt()).min(tank.mass / dt)
+            } else {
+                0.0
+            };
+
+            rate -= BLOWDOWN * tank.flow;
+
+            tank.pressure = (pressure + rate * dt).max(0.0);
+            tank.mass -= tank.flow * dt;
+
+            let line_target = if flowing {
+                tank.pressure * (1.0 - LINE_PRESSURE_DROP)
+            } else {
+                tank.pressure
+            };
+            tank.line_pressure += (line_target - tank.line_pressure) * dt / LINE_TIME_CONSTANT;
+        }
+
+        let mut thrust_target = SPECIFIC_IMPULSE * (self.fuel.flow + self.ox.flow);
+        if !self.lit {
+            thrust_target *= COLD_FLOW_FRACTION;
+        }
+
+        self.thrust += (thrust_target - self.thrust) * dt / THRUST_TIME_CONSTANT;
+    }
+
+    /// Queue a line for every field the stand sends.
+    fn queue_frame(&mut self) {
+        let mut fields = Vec::new();
+
+        // Binary frames carry the stand time in their header.
+        if self.encoder.is_none() {
+            fields.push((
+                STAND_TIME_FIELD_NAME.to_string(),
+                SensorValue::Float(self.stand_time()),
+            ));
+        }
+
+        for valve in self.valves.iter().filter(|v| v.kind == ValveKind::Reported) {
+            fields.push((valve.name.clone(), SensorValue::Boolean(valve.open)));
+        }
+
+        for tank in [&self.fuel, &self.ox] {
+            fields.push((
+                tank.pressure_field.to_string(),
+                SensorValue::Float(tank.pressure),
+            ));
+            fields.push((
+                tank.line_field.to_string(),
+                SensorValue::Float(tank.line_pressure),
+            ));
+            fields.push((tank.mass_field.to_string(), SensorValue::Float(tank.mass)));
+            fields.push((
+                tank.mass_rate_field.to_string(),
+                SensorValue::Float(-tank.flow),
+            ));
+        }
+
+        let thrust_rate = (self.thrust - self.last_thrust) / self.frame_period;
+        self.last_thrust = self.thrust;
+
+        let ratio = if self.fuel.flow > 0.0 {
+            self.ox.flow / self.fuel.flow
+        } else {
+            0.0
+        };
+
+        fields.push(("Scale Thrust".to_string(), SensorValue::Float(self.thrust)));
+        fields.push((
+            "Scale Thrust Rate".to_string(),
+            SensorValue::Float(thrust_rate),
+        ));
+        fields.push(("Ox/Fuel Ratio".to_string(), SensorValue::Float(ratio)));
+
+        let stuck: HashMap<String, Option<f64>> = self
+            .active_faults()
+            .filter_map(|f| match f {
+                Fault::StuckSensor(name, value) => Some((name.clone(), *value)),
+                _ => None,
+            })
+            .collect();
+        self.stuck_values.retain(|name, _| stuck.contains_key(name));
+
+        fields.retain(|(name, _)| self.fields.contains(name));
+
+        for (name, value) in fields.iter_mut() {
+            if let SensorValue::Float(v) = value {
+                *v += self.noise(name);
+
+                if let Some(stuck_value) = stuck.get(name) {
+                    *v = *self
+                        .stuck_values
+                        .entry(name.clone())
+                        .or_insert(stuck_value.unwrap_or(*v));
+                }
+            }
+        }
+
+        if let Some(encoder) = &self.encoder
+            && self.time - self.last_table >= TABLE_INTERVAL
+        {
+            let table = encoder.table();
+            self.last_table = self.time;
+            self.queue_bytes(&table);
+        }
+
+        self.send_fields(&fields);
+    }
+
+    /// Queue the given fields to be sent, as a binary frame if the console has asked for them or
+    /// else as lines of text.
+    fn send_fields(&mut self, fields: &[(String, SensorValue)]) {
+        let timestamp = (self.stand_time() * 1e6) as u64;
+
+        match &mut self.encoder {
+            Some(encoder) => {
+                let frame = encoder.encode(timestamp, fields);
+                self.queue_bytes(&frame);
+            }
+
+            None => {
+                for (name, value) in fields {
+                    self.queue_line(&field_line(name, *value));
+                }
+            }
+        }
+    }
+
+    /// The time on the stand's clock, which drifts from the simulation's.
+    fn stand_time(&self) -> f64 {
+        self.time * (1.0 + CLOCK_DRIFT)
+    }
+
+    /// Noise to add to the field with the given name.
+    fn noise(&mut self, name: &str) -> f64 {
+        let amplitude = if name.starts_with("Scale") {
+            SCALE_NOISE
+        } else if name.ends_with("PT1") || name.ends_with("PT3") {
+            PRESSURE_NOISE
+        } else {
+            return 0.0;
+        };
+
+        (self.random() * 2.0 - 1.0) * amplitude
+    }
+
+    /// A random number in `[0, 1)`.
+    fn random(&mut self) -> f64 {
+        // xorshift64
+        self.random ^= self.random << 13;
+        self.random ^= self.random >> 7;
+        self.random ^= self.random << 17;
+
+        (self.random >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn queue_line(&mut self, line: &str) {
+        self.queue_bytes(format!("{line}\n").as_bytes());
+    }
+
+    /// Queue a line or frame to be sent, unless it is dropped by a [`Fault::DropLines`],
+    /// corrupting it under a [`Fault::Corrupt`].
+    ///
+    /// [`Fault::DropLines`]: Fault::DropLines
+    /// [`Fault::Corrupt`]: Fault::Corrupt
+    fn queue_bytes(&mut self, bytes: &[u8]) {
+        let mut drop_chance = 0.0;
+        let mut corrupt_chance = 0.0;
+
+        for fault in self.active_faults() {
+            match fault {
+                Fault::DropLines(chance) => drop_chance = *chance,
+                Fault::Corrupt(chance) => corrupt_chance = *chance,
+                _ => (),
+            }
+        }
+
+        if self.random() < drop_chance {
+            return;
+        }
+
+        for &b in bytes {
+            let mut b = b;
+
+            if self.random() < corrupt_chance {
+                b ^= 1 << (self.random() * 8.0) as u32;
+            }
+
+            self.output.push_back(b);
+        }
+    }
+
+    /// Respond to a single command line written to the stand.
+    fn handle_command(&mut self, line: &str) {
+        if line == PROTOCOL_REQUEST {
+            self.queue_line(PROTOCOL_REPLY);
+            self.encoder = Some(FrameEncoder::default());
+            return;
+        }
+
+        let Some((id, command)) = parse_command(line) else {
+            return;
+        };
+
+        let Ok(id) = id.parse() else {
+            return;
+        };
+
+        let time = self.time;
+        let valve = command.and_then(|(name, open)| {
+            self.valves
+                .iter_mut()
+                .find(|v| v.name == name)
+                .map(|v| (v, open))
+        });
+
+        let reply = match valve {
+            Some((valve, open)) => {
+                valve.commanded = Some((open, time + VALVE_ACTUATION_TIME));
+                "ACK"
+            }
+
+            None => "NAK",
+        };
+
+        // A paused stand still acts on commands, but its replies are lost rather than delayed.
+        if !self.is_paused() {
+            self.send_fields(&[(reply.to_string(), SensorValue::UnsignedInt(id))]);
+        }
+    }
+}
+
+impl Read for SimulatedStand {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let until_next = self.run_until_now();
+
+        if self.disconnected {
+            return Err(io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                "Simulated serial line dropped",
+            ));
+        }
+
+        if self.output.is_empty() || self.is_paused() {
+            thread::sleep(until_next.min(READ_WAIT));
+            return Ok(0);
+        }
+
+        let len = buf.len().min(self.output.len());
+        for (b, out) in buf.iter_mut().zip(self.output.drain(..len)) {
+            *b = out;
+        }
+
+        Ok(len)
+    }
+}
+
+impl Write for SimulatedStand {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.disconnected {
+            return Err(io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                "Simulated serial line dropped",
+            ));
+        }
+
+        self.input.push_str(&String::from_utf8_lossy(buf));
+
+        while let Some((line, rest)) = self.input.split_once('\n') {
+            let line = line.trim().to_string();
+            self.input = rest.to_string();
+
+            if !line.is_empty() {
+                self.handle_command(&line);
+            }
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A fault injected into a [`SimulatedStand`], for training operators on failures and checking
+/// how the console handles them.
+///
+/// [`SimulatedStand`]: SimulatedStand
+#[derive(Debug, Clone, PartialEq)]
+pub enum Fault {
+    /// The valve ignores commands, staying in whatever state it is in. Commands are still
+    /// acknowledged.
+    StuckValve(String),
+
+    /// The sensor keeps sending the given value, or the value it had when the fault started.
+    StuckSensor(String, Option<f64>),
+
+    /// Each line or binary frame sent is dropped with the given probability, including
+    /// acknowledgements.
+    DropLines(f64),
+
+    /// Each byte sent has a bit flipped with the given probability.
+    Corrupt(f64),
+
+    /// The given number of NUL bytes are sent at once.
+    NulBurst(usize),
+
+    /// Nothing is sent, including acknowledgements, which are lost rather than sent once the
+    /// pause ends. Commands are still acted on.
+    Pause,
+
+    /// The serial line is lost, reads and writes failing from then on.
+    Disconnect,
+}
+
+#[derive(Debug, Clone)]
+struct ScheduledFault {
+    /// Seconds after the first read that the fault starts at, and ends at if it ends.
+    start: f64,
+    end: Option<f64>,
+    fault: Fault,
+    started: bool,
+}
+
+/// Faults to inject into a [`SimulatedStand`] at set times, loaded from a file with one fault per
+/// line, in this format:
+///
+/// `[seconds] [fault] [args] for [seconds]`
+///
+/// Where `for [seconds]` is optional, without it the fault lasts forever. The faults are
+/// `stuck_valve [valve]`, `stuck_sensor [sensor] at [value]` where `at [value]` is optional,
+/// `drop_lines [probability]`, `corrupt [probability]`, `nul_burst [count]`, `pause`, and
+/// `disconnect`. Blank lines and lines starting with `#` are ignored.
+///
+/// [`SimulatedStand`]: SimulatedStand
+#[derive(Debug, Clone, Default)]
+pub struct FaultSchedule {
+    faults: Vec<ScheduledFault>,
+}
+
+impl FaultSchedule {
+    /// Load a [`FaultSchedule`] from the file at the given path.
+    ///
+    /// [`FaultSchedule`]: FaultSchedule
+    pub fn load<P>(path: P) -> Result<Self, ScenarioError>
+    where
+        P: AsRef<Path>,
+    {
+        let text = fs::read_to_string(path).map_err(ScenarioError::Io)?;
+        Self::parse(&text)
+    }
+
+    /// Parse a [`FaultSchedule`] from text.
+    ///
+    /// [`FaultSchedule`]: FaultSchedule
+    pub fn parse(text: &str) -> Result<Self, ScenarioError> {
+        let mut faults = Vec::new();
+
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let fault = parse_fault(line).map_err(|e| ScenarioError::Fault(i + 1, e))?;
+            faults.push(fault);
+        }
+
+        Ok(Self { faults })
+    }
+}
+
+/// Errors from loading a [`ScriptedStand`] scenario or a [`FaultSchedule`].
+///
+/// [`ScriptedStand`]: ScriptedStand
+/// [`FaultSchedule`]: FaultSchedule
+#[derive(Debug)]
+pub enum ScenarioError {
+    Io(io::Error),
+    /// The line with the given number is not a time followed by a field.
+    Syntax(usize),
+    /// The line with the given number is not a valid fault.
+    Fault(usize, String),
+}
+
+impl Display for ScenarioError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ScenarioError::Io(e) => write!(f, "Could not read simulation file: {e}"),
+            ScenarioError::Syntax(line) => {
+                write!(
+                    f,
+                    "Scenario line {line} should be a time followed by a field"
+                )
+            }
+            ScenarioError::Fault(line, message) => write!(f, "Fault line {line}: {message}"),
+        }
+    }
+}
+
+impl Error for ScenarioError {}
+
+/// Parse a command line sent to the stand, giving its ID and, if it is a valve command, the
+/// valve and whether it should be opened.
+///
+/// `OPEN:[valve name]#[id]`
+fn parse_command(line: &str) -> Option<(&str, Option<(&str, bool)>)> {
+    let (command, id) = line.rsplit_once('#')?;
+
+    let valve = match command.split_once(':') {
+        Some(("OPEN", valve)) => Some((valve, true)),
+        Some(("CLOSE", valve)) => Some((valve, false)),
+        _ => None,
+    };
+
+    Some((id, valve))
+}
+
+/// Format a field as a line the stand would send, without the newline.
+fn field_line(name: &str, value: SensorValue) -> String {
+    match value {
+        SensorValue::UnsignedInt(v) => format!("{name}:u={v}"),
+        SensorValue::SignedInt(v) => format!("{name}:i={v}"),
+        SensorValue::Float(v) => format!("{name}:f={v:.3}"),
+        SensorValue::Boolean(true) => format!("{name}:b=TRUE"),
+        SensorValue::Boolean(false) => format!("{name}:b=FALSE"),
+    }
+}
+
+/// Parse a single line of a [`FaultSchedule`].
+///
+/// [`FaultSchedule`]: FaultSchedule
+fn parse_fault(line: &str) -> Result<ScheduledFault, String> {
+    let parse_seconds = |s: &str| match s.trim().parse::<f64>() {
+        Ok(t) if t.is_finite() && t >= 0.0 => Ok(t),
+        _ => Err(format!("'{}' is not a number of seconds", s.trim())),
+    };
+
+    let parse_chance = |s: &str| match s.trim().parse::<f64>() {
+        Ok(p) if (0.0..=1.0).contains(&p) => Ok(p),
+        _ => Err(format!("'{}' is not a probability from 0 to 1", s.trim())),
+    };
+
+    let (start, rest) = line
+        .split_once(char::is_whitespace)
+        .ok_or("Expected a time followed by a fault")?;
+    let start = parse_seconds(start)?;
+
+    let (rest, end) = match rest.rsplit_once(" for ") {
+        Some((rest, duration)) => (rest, Some(start + parse_seconds(duration)?)),
+        None => (rest, None),
+    };
+
+    let rest = rest.trim();
+    let (kind, args) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+    let args = args.trim();
+
+    let fault = match kind {
+        "stuck_valve" if !args.is_empty() => Fault::StuckValve(args.to_string()),
+
+        "stuck_sensor" if !args.is_empty() => match args.rsplit_once(" at ") {
+            Some((name, value)) => {
+                let value = value
+                    .trim()
+                    .parse()
+                    .map_err(|_| format!("'{}' is not a number", value.trim()))?;
+                Fault::StuckSensor(name.trim().to_string(), Some(value))
+            }
+            None => Fault::StuckSensor(args.to_string(), None),
+        },
+
+        "drop_lines" => Fault::DropLines(parse_chance(args)?),
+        "corrupt" => Fault::Corrupt(parse_chance(args)?),
+
+        "nul_burst" => Fault::NulBurst(
+            args.parse()
+                .map_err(|_| format!("'{args}' is not a number of bytes"))?,
+        ),
+
+        "pause" if args.is_empty() => Fault::Pause,
+        "disconnect" if args.is_empty() => Fault::Disconnect,
+
+        "stuck_valve" | "stuck_sensor" | "pause" | "disconnect" => {
+            return Err(format!("Wrong arguments for '{kind}'"));
+        }
+
+        _ => return Err(format!("Unknown fault '{kind}'")),
+    };
+
+    Ok(ScheduledFault {
+        start,
+        end,
+        fault,
+        started: false,
+    })
+}
+
+impl Display for Fault {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Fault::StuckValve(valve) => write!(f, "{valve} stuck"),
+            Fault::StuckSensor(sensor, Some(value)) => write!(f, "{sensor} stuck at {value}"),
+            Fault::StuckSensor(sensor, None) => write!(f, "{sensor} stuck"),
+            Fault::DropLines(chance) => write!(f, "dropping {}% of lines", chance * 100.0),
+            Fault::Corrupt(chance) => write!(f, "corrupting {}% of bytes", chance * 100.0),
+            Fault::NulBurst(count) => write!(f, "burst of {count} NUL bytes"),
+            Fault::Pause => write!(f, "field stream paused"),
+            Fault::Disconnect => write!(f, "serial line dropped"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        calibration::Calibrator,
+        derived::DerivedFields,
+        frame::Protocol,
+        safety::SafetyConfig,
+        serial::{
+            CommandFailureReason, CommandRetry, FieldIO, FieldReciever, FieldSender, SensorField,
+            SensorFieldReadError, ValveCommand, field_channel,
+        },
+        stand::{StandMode, StandState, ValveMismatch, ValveState},
+    };
+
+    /// Frames sent per second by the stands in these tests.
+    const RATE: f64 = 100.0;
+
+    /// A [`SimulatedStand`] of the built in config with the faults of the given schedule.
+    ///
+    /// [`SimulatedStand`]: SimulatedStand
+    fn faulty(faults: &str) -> SimulatedStand {
+        SimulatedStand::new(&StandConfig::built_in(), RATE)
+            .with_faults(FaultSchedule::parse(faults).unwrap())
+    }
+
+    /// A field channel for the built in config, attached to the given [`FieldIO`].
+    ///
+    /// [`FieldIO`]: FieldIO
+    fn attached(field_io: FieldIO<SimulatedStand>) -> (FieldSender, FieldReciever) {
+        let (sender, mut reciever) = field_channel(
+            StandConfig::built_in().field_names(),
+            Calibrator::default(),
+            DerivedFields::default(),
+            SafetyConfig::default(),
+        );
+
+        reciever.attach(field_io).unwrap();
+        reciever.commanded_valves_mut().settling_time = Duration::ZERO;
+
+        (sender, reciever)
+    }
+
+    /// Run the field thread's loop for the given time, giving every field recieved meanwhile.
+    fn run_for(
+        sender: &mut FieldSender,
+        reciever: &mut FieldReciever,
+        time: Duration,
+    ) -> Vec<SensorField> {
+        let start = Instant::now();
+        let mut fields = Vec::new();
+
+        while start.elapsed() < time {
+            sender.step().unwrap();
+            reciever.recieve_fields().unwrap();
+            fields.extend_from_slice(reciever.recieved_fields());
+        }
+
+        fields
+    }
+
+    /// The [`StandState`] reported by the latest fields the [`FieldReciever`] has.
+    ///
+    /// [`StandState`]: StandState
+    /// [`FieldReciever`]: FieldReciever
+    fn reported(reciever: &FieldReciever) -> StandState {
+        let fields: Vec<SensorField> = reciever.fields().cloned().collect();
+        let mut state = StandState::new(&StandConfig::built_in());
+        state.update(&fields);
+        state
+    }
+
+    /// Check that every valve the stand reports on is reported closed, so the stand may be
+    /// moved into Ox Filling.
+    fn assert_all_closed(reciever: &FieldReciever) {
+        let config = StandConfig::built_in();
+        let mut state = reported(reciever);
+
+        for valve in config.valves_of_kind(ValveKind::Reported) {
+            assert_eq!(
+                state.valve(&valve.name),
+                Some(ValveState::Closed),
+                "{}",
+                valve.name
+            );
+        }
+
+        assert!(reciever.commanded_valves().mismatches(&state).is_empty());
+        state
+            .transition_mode(StandMode::OxygenFilling, &config)
+            .unwrap();
+    }
+
+    /// Stand times of the given fields with the given name.
+    fn stand_times(fields: &[SensorField], name: &str) -> Vec<f64> {
+        fields
+            .iter()
+            .filter(|f| f.name == name)
+            .filter_map(|f| f.stand_time)
+            .collect()
+    }
+
+    #[test]
+    fn rejects_unusable_scenario_times() {
+        for time in ["-1", "nan", "inf", "1e300", "soon"] {
+            let scenario = format!("0 NPT1:f=1.0\n\n{time} NPT1:f=2.0\n");
+            assert!(
+                matches!(
+                    ScriptedStand::parse(&scenario),
+                    Err(ScenarioError::Syntax(3))
+                ),
+                "{time} was accepted"
+            );
+        }
+    }
+
+    #[test]
+    fn replies_during_a_pause_are_lost() {
+        let mut stand = faulty("0 pause for 0.1");
+        let mut buf = [0; 4096];
+        let mut text = String::new();
+
+        // the first read starts the stand's clock, and so the pause
+        assert_eq!(stand.read(&mut buf).unwrap(), 0);
+        stand.write_all(b"OPEN:NP3#7\n").unwrap();
+
+        let start = Instant::now();
+        while start.elapsed() < Duration::from_millis(200) {
+            let len = stand.read(&mut buf).unwrap();
+            text.push_str(&String::from_utf8_lossy(&buf[..len]));
+        }
+
+        assert!(!text.contains("ACK"), "{text}");
+        assert!(text.contains("NP3:b=TRUE"), "{text}");
+    }
+
+    #[test]
+    fn corrupt_lines_are_discarded() {
+        let (mut sender, mut reciever) = attached(FieldIO::new(faulty("0 corrupt 0.02")));
+        let fields = run_for(&mut sender, &mut reciever, Duration::from_millis(400));
+
+        let names = StandConfig::built_in().field_names();
+        assert!(!fields.is_empty());
+        assert!(fields.iter().all(|f| names.contains(&f.name)));
+        assert!(reciever.is_connected());
+        assert_all_closed(&reciever);
+    }
+
+    #[test]
+    fn corrupt_frames_fail_their_crc() {
+        let field_io = FieldIO::new(faulty("0.3 corrupt 0.002")).with_protocol(Protocol::Binary);
+        let (mut sender, mut reciever) = attached(field_io);
+        let fields = run_for(&mut sender, &mut reciever, Duration::from_millis(800));
+
+        assert_eq!(reciever.protocol(), Protocol::Binary);
+        assert!(reciever.frame_stats().crc_errors > 0);
+        assert!(stand_times(&fields, "NPT1").iter().any(|&t| t > 0.5));
+        assert_all_closed(&reciever);
+    }
+
+    #[test]
+    fn nul_bursts_are_skipped_without_losing_frames() {
+        let field_io = FieldIO::new(faulty("0.3 nul_burst 4096")).with_protocol(Protocol::Binary);
+        let (mut sender, mut reciever) = attached(field_io);
+        let fields = run_for(&mut sender, &mut reciever, Duration::from_millis(600));
+
+        let stats = reciever.frame_stats();
+        assert_eq!(reciever.protocol(), Protocol::Binary);
+        assert!(stats.skipped_bytes >= 4096);
+        assert_eq!(stats.crc_errors, 0);
+
+        let times = stand_times(&fields, "NPT1");
+        assert!(times.last().is_some_and(|&t| t > 0.4));
+        assert!(
+            times.windows(2).all(|w| w[1] - w[0] < 1.5 / RATE),
+            "{times:?}"
+        );
+    }
+
+    #[test]
+    fn dropped_acknowledgements_fail_commands_the_stand_acted_on() {
+        let retry = CommandRetry {
+            timeout: Duration::from_millis(20),
+            max_attempts: 3,
+        };
+        let field_io = FieldIO::new(faulty("0 drop_lines 1.0 for 0.3")).with_command_retry(retry);
+        let (mut sender, mut reciever) = attached(field_io);
+        let open = ValveCommand::Open("NP3".to_string());
+
+        reciever.send_command(open.clone()).unwrap();
+        let fields = run_for(&mut sender, &mut reciever, Duration::from_millis(150));
+
+        assert!(fields.is_empty());
+        let failures = reciever.command_failures();
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].command, open);
+        assert_eq!(failures[0].reason, CommandFailureReason::Unacknowledged(3));
+
+        // the stand still opened the valve, so it agrees with what was commanded once it is heard
+        run_for(&mut sender, &mut reciever, Duration::from_millis(300));
+        let state = reported(&reciever);
+        assert_eq!(state.valve("NP3"), Some(ValveState::Open));
+        assert!(reciever.commanded_valves().mismatches(&state).is_empty());
+    }
+
+    #[test]
+    fn stuck_valve_is_a_mismatch_and_blocks_ox_filling() {
+        let config = StandConfig::built_in();
+        let (mut sender, mut reciever) = attached(FieldIO::new(faulty("0.2 stuck_valve NP3")));
+
+        reciever
+            .send_command(ValveCommand::Open("NP3".to_string()))
+            .unwrap();
+        run_for(&mut sender, &mut reciever, Duration::from_millis(150));
+        let state = reported(&reciever);
+        assert_eq!(state.valve("NP3"), Some(ValveState::Open));
+        assert!(reciever.commanded_valves().mismatches(&state).is_empty());
+
+        run_for(&mut sender, &mut reciever, Duration::from_millis(100));
+        reciever
+            .send_command(ValveCommand::Close("NP3".to_string()))
+            .unwrap();
+        run_for(&mut sender, &mut reciever, Duration::from_millis(200));
+
+        let mut state = reported(&reciever);
+        assert!(reciever.command_failures().is_empty());
+        assert_eq!(
+            reciever.commanded_valves().mismatches(&state),
+            [ValveMismatch {
+                valve: "NP3".to_string(),
+                commanded: ValveState::Closed,
+                reported: ValveState::Open,
+            }]
+        );
+        assert!(
+            state
+                .transition_mode(StandMode::OxygenFilling, &config)
+                .is_err()
+        );
+        state.transition_mode(StandMode::Safing, &config).unwrap();
+    }
+
+    #[test]
+    fn disconnect_loses_the_device_and_fails_commands() {
+        let (mut sender, mut reciever) = attached(FieldIO::new(faulty("0.2 disconnect")));
+        let fields = run_for(&mut sender, &mut reciever, Duration::from_millis(100));
+        assert!(!fields.is_empty());
+        assert!(reciever.is_connected());
+
+        let start = Instant::now();
+        let error = loop {
+            assert!(start.elapsed() < Duration::from_secs(1));
+
+            if let Err(e) = sender.send_fields() {
+                break e;
+            }
+        };
+        assert!(matches!(
+            error,
+            SensorFieldReadError::IoError(e) if e.kind() == io::ErrorKind::BrokenPipe
+        ));
+
+        let open = ValveCommand::Open("NP3".to_string());
+        reciever.send_command(open.clone()).unwrap();
+        sender.step().unwrap();
+        sender.step().unwrap();
+        reciever.recieve_fields().unwrap();
+
+        assert!(!reciever.is_connected());
+        let failures = reciever.command_failures();
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].command, open);
+        assert_eq!(failures[0].reason, CommandFailureReason::Disconnected);
+    }
+}
@@ -1,19 +1,24 @@
-use crate::serial::{self, SensorField, SensorValue};
-use std::{error::Error, fmt::Display};
+use crate::{
+    config::{StandConfig, ValveKind},
+    serial::{SensorField, SensorValue, ValveCommand},
+};
+use std::{
+    collections::{BTreeMap, HashMap},
+    error::Error,
+    fmt::Display,
+    time::{Duration, Instant},
+};
+
+/// Default time given to a valve to report the state it was commanded to before it is flagged.
+const DEFAULT_VALVE_SETTLING_TIME: Duration = Duration::from_secs(1);
 
 /// Structure representing the state of the NILE stand.
-#[derive(Eq, PartialEq, Debug, Clone, Copy, Default)]
+#[derive(Eq, PartialEq, Debug, Clone, Default)]
 pub struct StandState {
     stand_mode: StandMode,
 
-    pub valve_np1: Option<ValveState>,
-    pub valve_np2: Option<ValveState>,
-    pub valve_np3: Option<ValveState>,
-    pub valve_np4: Option<ValveState>,
-
-    pub valve_ip1: Option<ValveState>,
-    pub valve_ip2: Option<ValveState>,
-    pub valve_ip3: Option<ValveState>,
+    /// Reported state of every valve the stand reports on, by name.
+    valves: BTreeMap<String, Option<ValveState>>,
 }
 
 /// State of a single valve.
@@ -23,26 +28,145 @@ pub enum ValveState {
     Closed,
 }
 
+impl Display for ValveState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ValveState::Open => write!(f, "Open"),
+            ValveState::Closed => write!(f, "Closed"),
+        }
+    }
+}
+
 impl StandState {
-    pub fn transition_mode(&mut self, mode: StandMode) -> Result<(), ModeTransitionError> {
-        mode.check_transition(self)?;
+    /// Create a new [`StandState`] for the stand described by the given [`StandConfig`], with
+    /// every reported valve in an unknown state.
+    ///
+    /// [`StandState`]: StandState
+    /// [`StandConfig`]: StandConfig
+    pub fn new(config: &StandConfig) -> Self {
+        Self {
+            stand_mode: StandMode::default(),
+            valves: config
+                .valves_of_kind(ValveKind::Reported)
+                .map(|v| (v.name.clone(), None))
+                .collect(),
+        }
+    }
+
+    pub fn transition_mode(
+        &mut self,
+        mode: StandMode,
+        config: &StandConfig,
+    ) -> Result<(), ModeTransitionError> {
+        mode.check_transition(self, config)?;
         self.stand_mode = mode;
         Ok(())
     }
 
     pub fn update(&mut self, fields: &[SensorField]) {
-        self.valve_np1 = valve_state("NP1", &fields);
-        self.valve_np2 = valve_state("NP2", &fields);
-        self.valve_np3 = valve_state("NP3", &fields);
-        self.valve_np4 = valve_state("NP4", &fields);
-        self.valve_ip1 = valve_state("IP1", &fields);
-        self.valve_ip2 = valve_state("IP2", &fields);
-        self.valve_ip3 = valve_state("IP3", &fields);
+        for (name, state) in self.valves.iter_mut() {
+            *state = valve_state(name, fields);
+        }
     }
 
     pub fn mode(&self) -> StandMode {
         self.stand_mode
     }
+
+    /// Gives the reported state of the valve with the given name, or [`None`] if the valve is not
+    /// one the stand reports on or its state has not been reported.
+    ///
+    /// [`None`]: Option::None
+    pub fn valve(&self, name: &str) -> Option<ValveState> {
+        self.valves.get(name).copied().flatten()
+    }
+}
+
+/// Tracks the state each valve was last commanded to, so that it can be compared against the
+/// state the stand reports.
+#[derive(Debug, Clone)]
+pub struct CommandedValves {
+    commands: HashMap<String, (ValveState, Instant)>,
+
+    /// How long a valve has to report its commanded state before it counts as a mismatch.
+    pub settling_time: Duration,
+}
+
+/// A valve whose reported [`ValveState`] disagrees with the state it was last commanded to.
+///
+/// [`ValveState`]: ValveState
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ValveMismatch {
+    pub valve: String,
+    pub commanded: ValveState,
+    pub reported: ValveState,
+}
+
+impl CommandedValves {
+    /// Create a new [`CommandedValves`] with nothing commanded yet.
+    ///
+    /// [`CommandedValves`]: CommandedValves
+    pub fn new(settling_time: Duration) -> Self {
+        Self {
+            commands: HashMap::new(),
+            settling_time,
+        }
+    }
+
+    /// Record that the given [`ValveCommand`] was just sent.
+    ///
+    /// [`ValveCommand`]: ValveCommand
+    pub fn command(&mut self, command: &ValveCommand) {
+        let (valve, state) = match command {
+            ValveCommand::Open(valve) => (valve, ValveState::Open),
+            ValveCommand::Close(valve) => (valve, ValveState::Closed),
+        };
+
+        self.commands.insert(valve.clone(), (state, Instant::now()));
+    }
+
+    /// Produce a [`ValveMismatch`] for every valve which was commanded longer than the settling
+    /// time ago and whose state in the given [`StandState`] disagrees with that command. The
+    /// result is sorted by valve name.
+    ///
+    /// [`ValveMismatch`]: ValveMismatch
+    /// [`StandState`]: StandState
+    pub fn mismatches(&self, state: &StandState) -> Vec<ValveMismatch> {
+        let now = Instant::now();
+
+        let mut mismatches: Vec<ValveMismatch> = self
+            .commands
+            .iter()
+            .filter(|(_, (_, time))| now.duration_since(*time) >= self.settling_time)
+            .filter_map(|(valve, &(commanded, _))| match state.valve(valve) {
+                Some(reported) if reported != commanded => Some(ValveMismatch {
+                    valve: valve.clone(),
+                    commanded,
+                    reported,
+                }),
+                _ => None,
+            })
+            .collect();
+
+        mismatches.sort_unstable_by(|a, b| a.valve.cmp(&b.valve));
+        mismatches
+    }
+}
+
+impl Default for CommandedValves {
+    fn default() -> Self {
+        Self::new(DEFAULT_VALVE_SETTLING_TIME)
+    }
+}
+
+impl Display for ValveMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} commanded {} but reported {}",
+            self.valve, self.commanded, self.reported
+        )
+    }
 }
 
 /// Checks for a [`SensorField`] with the given name, if it exists and its value is
@@ -67,85 +191,54 @@ fn valve_state(name: &str, fields: &[SensorField]) -> Option<ValveState> {
         })
 }
 
-/// The different modes that the NILE stand software can take on.
+/// The different modes that the NILE stand software can take on. Which valves may be controlled
+/// in each mode, and which must be closed to enter or leave it, is given by the [`ModeConfig`]s
+/// of the [`StandConfig`].
+///
+/// [`ModeConfig`]: crate::config::ModeConfig
+/// [`StandConfig`]: StandConfig
 #[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
 pub enum StandMode {
     /// Complete manual control of valves.
     CheckOut,
 
-    /// Limits control to control of valves [`serial::NILE_VALVE_NP3`] and
-    /// [`serial::NILE_VALVE_NP4`].
-    ///
-    /// [`serial::NILE_VALVE_NP3`]: serial::NILE_VALVE_NP3
-    /// [`serial::NILE_VALVE_NP4`]: serial::NILE_VALVE_NP4
+    /// Limits control to the nitrogen valves used while filling oxidizer, and requires all valves
+    /// be closed before entering.
     OxygenFilling,
 
-    /// Manual control over valves [`serial::NILE_VALVE_NP2`], [`serial::NILE_VALVE_IP2`],
-    /// [`serial::NILE_VALVE_NP3`], and [`serial::NILE_VALVE_IP3`]. Ability to begin sequence which
-    /// ingnites the ignitor, then opens [`serial::NILE_VALVE_NP1`] and [`serial::NILE_VALVE_IP1`]
-    /// simultaniously. Operators can enter a firing time which holds [`serial::NILE_VALVE_NP1`] and
-    /// [`serial::NILE_VALVE_IP1`] open for that time plus three seconds to clear excess propellant.
-    /// After this time plus three seconds [`serial::NILE_VALVE_NP1`], [`serial::NILE_VALVE_IP1`],
-    /// [`serial::NILE_VALVE_NP2`], and [`serial::NILE_VALVE_IP2`] will all close while
-    /// [`serial::NILE_VALVE_NP3`] and [`serial::NILE_VALVE_IP3`] open to vent excess nitrogen
-    /// - "Fire".
+    /// Manual control over the pressurization and vent valves. Ability to begin sequence which
+    /// ingnites the ignitor, then opens the selected engine valve. Operators can enter a firing
+    /// time which holds the engine open for that time plus three seconds to clear excess
+    /// propellant, after which the pressurization valves close while the vent valves open to vent
+    /// excess nitrogen - "Fire".
     ///
     /// NOTE: Maybe have entry for timing delays between NP1 and IP1, though this is probably best
     /// done on the stand side.
-    ///
-    /// [`serial::NILE_VALVE_NP1`]: serial::NILE_VALVE_NP1
-    /// [`serial::NILE_VALVE_IP1`]: serial::NILE_VALVE_IP1
-    /// [`serial::NILE_VALVE_NP2`]: serial::NILE_VALVE_NP2
-    /// [`serial::NILE_VALVE_IP2`]: serial::NILE_VALVE_IP2
-    /// [`serial::NILE_VALVE_NP3`]: serial::NILE_VALVE_NP3
-    /// [`serial::NILE_VALVE_IP3`]: serial::NILE_VALVE_IP3
     PressurizationAndFiring,
 
-    /// Sets [`serial::NILE_VALVE_NP3`] and [`serial::NILE_VALVE_IP3`] open and closes all others.
-    /// Also allows for operators to use a "Depress System" button which will open
-    /// [`serial::NILE_VALVE_NP4`] for five seconds then closes it, followed by opening
-    /// [`serial::NILE_VALVE_IP2`] for five seconds then closing it, followed by finally opening
-    /// [`serial::NILE_VALVE_NP2`] for five seconds and then closing it, there should be one second
-    /// delay between all valve openings - "Depressurize System".
-    ///
-    /// [`serial::NILE_VALVE_NP2`]: serial::NILE_VALVE_NP2
-    /// [`serial::NILE_VALVE_IP2`]: serial::NILE_VALVE_IP2
-    /// [`serial::NILE_VALVE_NP3`]: serial::NILE_VALVE_NP3
-    /// [`serial::NILE_VALVE_IP3`]: serial::NILE_VALVE_IP3
-    /// [`serial::NILE_VALVE_NP4`]: serial::NILE_VALVE_NP4
+    /// Opens the vent valves and closes all others. Also allows for operators to use a "Depress
+    /// System" button which will vent each part of the system in turn - "Depressurize System".
     #[default]
     Safing,
 }
 
 impl StandMode {
-    /// Returns a [`Vec`] of the valves which may be manually controlled in the given [`StandMode`].
+    /// Every [`StandMode`], in the order they are presented to operators.
     ///
-    /// [`Vec`]: Vec
     /// [`StandMode`]: StandMode
-    pub fn manual_control_valves(self) -> Vec<&'static str> {
-        match self {
-            Self::CheckOut => vec![
-                serial::NILE_VALVE_NP1,
-                serial::NILE_VALVE_NP2,
-                serial::NILE_VALVE_NP3,
-                serial::NILE_VALVE_NP4,
-                serial::NILE_VALVE_IP1,
-                serial::NILE_VALVE_IP2,
-                serial::NILE_VALVE_IP3,
-                serial::NILE_VALVE_ENGINE,
-            ],
-
-            Self::OxygenFilling => vec![serial::NILE_VALVE_NP3, serial::NILE_VALVE_NP4],
-
-            Self::PressurizationAndFiring => vec![
-                serial::NILE_VALVE_NP2,
-                serial::NILE_VALVE_NP3,
-                serial::NILE_VALVE_IP2,
-                serial::NILE_VALVE_IP3,
-            ],
-
-            Self::Safing => vec![],
-        }
+    pub const ALL: [StandMode; 4] = [
+        StandMode::CheckOut,
+        StandMode::OxygenFilling,
+        StandMode::PressurizationAndFiring,
+        StandMode::Safing,
+    ];
+
+    /// Returns the names of the valves which may be manually controlled in the given
+    /// [`StandMode`].
+    ///
+    /// [`StandMode`]: StandMode
+    pub fn manual_control_valves(self, config: &StandConfig) -> &[String] {
+        &config.mode(self).manual_valves
     }
 
     /// Check the necessary conditions for moving out of the current [`StandMode`] and into the
@@ -153,22 +246,16 @@ impl StandMode {
     ///
     /// [`StandMode`]: StandMode
     /// [`StandState`]: StandState
-    fn check_transition(&self, state: &StandState) -> Result<(), ModeTransitionError> {
+    fn check_transition(
+        &self,
+        state: &StandState,
+        config: &StandConfig,
+    ) -> Result<(), ModeTransitionError> {
         // Checks for moving _out_ of a state.
-        match state.stand_mode {
-            StandMode::CheckOut => (),
-            StandMode::OxygenFilling => check_pretransition_ox_filling(state)?,
-            StandMode::PressurizationAndFiring => (),
-            StandMode::Safing => (),
-        }
+        check_closed(state, &config.mode(state.stand_mode).closed_on_exit)?;
 
         // Checks for moving _into_ a given state.
-        match self {
-            StandMode::CheckOut => Ok(()),
-            StandMode::OxygenFilling => check_transition_ox_filling(state),
-            StandMode::PressurizationAndFiring => Ok(()),
-            StandMode::Safing => Ok(()),
-        }
+        check_closed(state, &config.mode(*self).closed_on_entry)
     }
 }
 
@@ -189,53 +276,29 @@ impl Into<String> for StandMode {
     }
 }
 
-/// Produce and error if a transition out of [`OxygenFilling`] mode would be erronious with the
-/// given [`StandState`].
-///
-/// [`OxygenFilling`]: StandMode::OxygenFilling
-/// [`StandState`]: StandState
-fn check_pretransition_ox_filling(state: &StandState) -> Result<(), ModeTransitionError> {
-    match state {
-        StandState {
-            valve_np3: Some(ValveState::Closed),
-            valve_np4: Some(ValveState::Closed),
-            ..
-        } => Ok(()),
-
-        _ => Err(ModeTransitionError(
-            "Expected valves NP3 and NP4 to be closed",
-        )),
-    }
-}
-
-/// Produce and error if a transition into [`OxygenFilling`] would be erronious with the given
+/// Produce an error if any of the given valves are not reported closed in the given
 /// [`StandState`].
 ///
-/// [`OxygenFilling`]: StandMode::OxygenFilling
 /// [`StandState`]: StandState
-fn check_transition_ox_filling(state: &StandState) -> Result<(), ModeTransitionError> {
-    match state {
-        StandState {
-            valve_np1: Some(ValveState::Closed),
-            valve_np2: Some(ValveState::Closed),
-            valve_np3: Some(ValveState::Closed),
-            valve_np4: Some(ValveState::Closed),
-
-            valve_ip1: Some(ValveState::Closed),
-            valve_ip2: Some(ValveState::Closed),
-            valve_ip3: Some(ValveState::Closed),
-            ..
-        } => Ok(()),
-
-        _ => Err(ModeTransitionError("Expected all valves to be closed")),
+fn check_closed(state: &StandState, valves: &[String]) -> Result<(), ModeTransitionError> {
+    if valves
+        .iter()
+        .all(|v| state.valve(v) == Some(ValveState::Closed))
+    {
+        return Ok(());
     }
+
+    Err(ModeTransitionError(format!(
+        "Expected valves {} to be closed",
+        valves.join(", ")
+    )))
 }
 
 /// Failures for transitioning between [`StandMode`]s.
 ///
 /// [`StandMode`]: StandMode
 #[derive(PartialEq, Eq, Debug, Clone)]
-pub struct ModeTransitionError(&'static str);
+pub struct ModeTransitionError(String);
 
 impl Display for ModeTransitionError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
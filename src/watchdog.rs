@@ -0,0 +1,166 @@
+use std::time::{Duration, Instant};
+
+/// Timing configuration for a [`Watchdog`].
+///
+/// [`Watchdog`]: Watchdog
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchdogConfig {
+    /// How long telemetry may go without updating before the operator is warned.
+    pub warn_after: Duration,
+
+    /// How long telemetry may go without updating before the stand is automatically safed.
+    pub safe_after: Duration,
+}
+
+impl Default for WatchdogConfig {
+    fn default() -> Self {
+        Self {
+            warn_after: Duration::from_secs(1),
+            safe_after: Duration::from_secs(5),
+        }
+    }
+}
+
+/// The state of a [`Watchdog`].
+///
+/// [`Watchdog`]: Watchdog
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchdogState {
+    /// Telemetry is fresh, or has never been recieved.
+    Nominal,
+
+    /// Telemetry has gone stale for the given length of time, but not yet long enough to safe.
+    Warning(Duration),
+
+    /// Telemetry went stale for long enough that the stand was safed. `safed_with_link` is true
+    /// once the safing sequence has been run while telemetry was fresh, meaning the stand should
+    /// have actually recieved it.
+    Tripped { safed_with_link: bool },
+}
+
+/// What the owner of a [`Watchdog`] should do after calling [`Watchdog::update`].
+///
+/// [`Watchdog`]: Watchdog
+/// [`Watchdog::update`]: Watchdog::update
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchdogAction {
+    None,
+
+    /// Run the safing sequence.
+    Safe,
+}
+
+/// Watches for telemetry going stale, first warning and then requesting that the stand be safed.
+/// Once tripped the [`Watchdog`] keeps requesting safing each time telemetry returns after being
+/// lost, until it is [`Watchdog::reset`].
+///
+/// The [`Watchdog`] is only armed once it has been fed at least once, so that a console which has
+/// not yet heard from the stand does not immediately safe it.
+///
+/// [`Watchdog`]: Watchdog
+/// [`Watchdog::reset`]: Watchdog::reset
+#[derive(Debug, Clone)]
+pub struct Watchdog {
+    pub config: WatchdogConfig,
+    state: WatchdogState,
+    last_fed: Option<Instant>,
+}
+
+impl Watchdog {
+    /// Create a new, unarmed [`Watchdog`].
+    ///
+    /// [`Watchdog`]: Watchdog
+    pub fn new(config: WatchdogConfig) -> Self {
+        Self {
+            config,
+            state: WatchdogState::Nominal,
+            last_fed: None,
+        }
+    }
+
+    /// Tell the [`Watchdog`] that telemetry was recieved at the given time.
+    ///
+    /// [`Watchdog`]: Watchdog
+    pub fn feed(&mut self, now: Instant) {
+        self.last_fed = Some(now);
+    }
+
+    pub fn state(&self) -> WatchdogState {
+        self.state
+    }
+
+    /// Return a tripped [`Watchdog`] to [`WatchdogState::Nominal`], e.g. once an operator has
+    /// acknowledged the loss of telemetry.
+    ///
+    /// [`Watchdog`]: Watchdog
+    /// [`WatchdogState::Nominal`]: WatchdogState::Nominal
+    pub fn reset(&mut self) {
+        self.state = WatchdogState::Nominal;
+    }
+
+    /// Update the [`Watchdog`]'s state for the given time. `link_alive` should be false if the
+    /// connection to the stand is known to be dead, which is treated as telemetry being stale.
+    ///
+    /// [`Watchdog`]: Watchdog
+    pub fn update(&mut self, now: Instant, link_alive: bool) -> WatchdogAction {
+        let Some(last_fed) = self.last_fed else {
+            return WatchdogAction::None;
+        };
+
+        let staleness = now.duration_since(last_fed);
+        let stale = !link_alive || staleness >= self.config.warn_after;
+
+        match self.state {
+            WatchdogState::Nominal | WatchdogState::Warning(_) if !stale => {
+                self.state = WatchdogState::Nominal;
+                WatchdogAction::None
+            }
+
+            WatchdogState::Nominal | WatchdogState::Warning(_)
+                if staleness < self.config.safe_after =>
+            {
+                if self.state == WatchdogState::Nominal {
+                    log::warn!("Telemetry has gone stale!");
+                }
+
+                self.state = WatchdogState::Warning(staleness);
+                WatchdogAction::None
+            }
+
+            WatchdogState::Nominal | WatchdogState::Warning(_) => {
+                log::error!(
+                    "Telemetry lost for {}ms, safing stand!",
+                    staleness.as_millis()
+                );
+                self.state = WatchdogState::Tripped {
+                    safed_with_link: false,
+                };
+
+                WatchdogAction::Safe
+            }
+
+            WatchdogState::Tripped {
+                safed_with_link: false,
+            } if !stale => {
+                log::warn!("Telemetry has returned, safing stand again!");
+                self.state = WatchdogState::Tripped {
+                    safed_with_link: true,
+                };
+
+                WatchdogAction::Safe
+            }
+
+            WatchdogState::Tripped {
+                safed_with_link: true,
+            } if stale => {
+                self.state = WatchdogState::Tripped {
+                    safed_with_link: false,
+                };
+
+                WatchdogAction::None
+            }
+
+            WatchdogState::Tripped { .. } => WatchdogAction::None,
+        }
+    }
+}